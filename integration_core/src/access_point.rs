@@ -1,4 +1,4 @@
-use crate::clients::LqClientDevice;
+use crate::LqClientDevice;
 
 #[derive(Debug, Clone)]
 pub struct LqAccessPoint {
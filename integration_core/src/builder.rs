@@ -0,0 +1,421 @@
+use crate::{LqAccessPoint, LqClientDevice, LqClientSite, LqSite};
+use anyhow::{Error, Result};
+use std::collections::{HashMap, HashSet};
+
+/// Name of the synthetic access point that collects devices we couldn't place.
+pub const UNPARENTED: &str = "Unparented";
+
+/// Which sites become the top-level nodes of the tree.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RootSelection {
+    /// The sites with these names, in this order. Each name must belong to
+    /// exactly one site.
+    Named(Vec<String>),
+    /// Every site without a (known) parent, sorted by name.
+    Automatic,
+}
+
+/// Why a device was placed under [`UNPARENTED`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ParentlessReason {
+    /// The device has no parent site ID.
+    NoParentSite,
+    /// The parent site isn't one of the active sites.
+    ParentSiteNotFound,
+}
+
+impl ParentlessReason {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::NoParentSite => "no parent site ID",
+            Self::ParentSiteNotFound => "parent site not in active list",
+        }
+    }
+}
+
+/// A device that couldn't be placed in the tree.
+#[derive(Debug, Clone)]
+pub struct ParentlessDevice {
+    /// The device, with its access point rewritten to [`UNPARENTED`].
+    pub device: LqClientDevice,
+    pub reason: ParentlessReason,
+    /// The access point the device was supplied with, from a data link or
+    /// its own settings; empty if it had none.
+    pub access_point_id: String,
+    pub access_point_name: String,
+}
+
+/// The result of building a topology: the site trees used for `network.json`
+/// (one per root), the flat site list (with access points populated) and the
+/// devices that couldn't be placed.
+#[derive(Debug, Clone)]
+pub struct Topology {
+    pub roots: Vec<LqSite>,
+    pub sites: HashMap<String, LqSite>,
+    pub parentless: Vec<ParentlessDevice>,
+}
+
+impl Topology {
+    /// The first root, which also holds the [`UNPARENTED`] access point.
+    pub fn root(&self) -> &LqSite {
+        &self.roots[0]
+    }
+
+    /// Sites that aren't beneath any root, because their parents don't lead
+    /// to one, so are left out of `network.json`. Sorted by name.
+    pub fn rejected_sites(&self) -> Vec<&LqSite> {
+        fn collect<'a>(site: &'a LqSite, ids: &mut HashSet<&'a str>) {
+            ids.insert(&site.id);
+            for child in site.children.iter() {
+                collect(child, ids);
+            }
+        }
+        let mut in_tree = HashSet::new();
+        for root in self.roots.iter() {
+            collect(root, &mut in_tree);
+        }
+        let mut rejected: Vec<&LqSite> = self
+            .sites
+            .values()
+            .filter(|s| !in_tree.contains(s.id.as_str()))
+            .collect();
+        rejected.sort_by(|a, b| a.name.cmp(&b.name).then_with(|| a.id.cmp(&b.id)));
+        rejected
+    }
+}
+
+/// Builds a site/access point tree from a flat list of sites and the client
+/// devices attached to them.
+///
+/// Devices are placed on the access point identified by `access_point_id`
+/// (named `access_point_name`) in the site identified by `parent_site_id`.
+/// Devices without an access point are placed on a synthetic `"{site}-NoAP"`
+/// access point, and devices whose parent site is missing are collected
+/// under [`UNPARENTED`] at the first root. Access points already present on a
+/// site, keyed by ID, keep their rates.
+pub struct TopologyBuilder {
+    sites: HashMap<String, LqSite>,
+    access_point_rates: HashMap<String, (usize, usize)>,
+    default_rates: (usize, usize),
+}
+
+impl TopologyBuilder {
+    pub fn new(sites: HashMap<String, LqSite>) -> Self {
+        Self {
+            sites,
+            access_point_rates: HashMap::new(),
+            default_rates: (1_000, 1_000),
+        }
+    }
+
+    /// Configured (download, upload) Mbps for access points, keyed by ID
+    /// (see [`LqClientDevice::parent_node_id`]).
+    pub fn access_point_rates(mut self, rates: HashMap<String, (usize, usize)>) -> Self {
+        self.access_point_rates = rates;
+        self
+    }
+
+    /// (download, upload) Mbps for access points without a configured rate.
+    pub fn default_rates(mut self, download_mbps: usize, upload_mbps: usize) -> Self {
+        self.default_rates = (download_mbps, upload_mbps);
+        self
+    }
+
+    /// Attaches every client device to its site, then assembles the tree
+    /// beneath the site named `root_name`. Parentless devices have their access
+    /// point rewritten to [`UNPARENTED`].
+    pub fn build(self, root_name: &str, clients: &mut [LqClientSite]) -> Result<Topology> {
+        self.build_forest(&RootSelection::Named(vec![root_name.to_string()]), clients)
+    }
+
+    /// Like [`build`](Self::build), but assembles a tree beneath each of the
+    /// selected roots. Sites that aren't beneath any root are left out.
+    pub fn build_forest(
+        mut self,
+        roots: &RootSelection,
+        clients: &mut [LqClientSite],
+    ) -> Result<Topology> {
+        let mut parentless = Vec::new();
+        for client in clients.iter_mut() {
+            for cpe in client.devices.iter_mut() {
+                let mut no_parent = None;
+                if cpe.parent_site_id.is_empty() {
+                    no_parent = Some(ParentlessReason::NoParentSite);
+                } else if let Some(site) = self.sites.get_mut(&cpe.parent_site_id) {
+                    let access_point = cpe.parent_node_id();
+
+                    if let Some(ap) = site.access_points.get_mut(&access_point) {
+                        ap.clients.push(cpe.clone());
+                    } else {
+                        let (download_mbps, upload_mbps) = self
+                            .access_point_rates
+                            .get(&access_point)
+                            .cloned()
+                            .unwrap_or(self.default_rates);
+                        site.access_points.insert(
+                            access_point,
+                            LqAccessPoint {
+                                name: cpe.parent_node(),
+                                clients: vec![cpe.clone()],
+                                download_mbps,
+                                upload_mbps,
+                            },
+                        );
+                    }
+                } else {
+                    no_parent = Some(ParentlessReason::ParentSiteNotFound);
+                }
+
+                if let Some(reason) = no_parent {
+                    let access_point_id =
+                        std::mem::replace(&mut cpe.access_point_id, "noparent".to_string());
+                    let access_point_name =
+                        std::mem::replace(&mut cpe.access_point_name, UNPARENTED.to_string());
+                    parentless.push(ParentlessDevice {
+                        device: cpe.clone(),
+                        reason,
+                        access_point_id,
+                        access_point_name,
+                    });
+                }
+            }
+        }
+
+        // Access points supplied up front (to carry their rates) are only
+        // kept if a device connects to them.
+        for site in self.sites.values_mut() {
+            site.access_points.retain(|_, ap| !ap.clients.is_empty());
+        }
+
+        let mut roots: Vec<LqSite> = match roots {
+            RootSelection::Named(names) => names
+                .iter()
+                .map(|name| {
+                    let mut named: Vec<&LqSite> =
+                        self.sites.values().filter(|s| &s.name == name).collect();
+                    match named.len() {
+                        0 => Err(Error::msg(format!("Root site '{name}' not found"))),
+                        1 => Ok(named[0].clone()),
+                        _ => {
+                            named.sort_by(|a, b| a.id.cmp(&b.id));
+                            let ids: Vec<&str> = named.iter().map(|s| s.id.as_str()).collect();
+                            Err(Error::msg(format!(
+                                "Root site name '{name}' is shared by sites {}; rename all but one of them",
+                                ids.join(", ")
+                            )))
+                        }
+                    }
+                })
+                .collect::<Result<_>>()?,
+            RootSelection::Automatic => {
+                let mut found: Vec<LqSite> = self
+                    .sites
+                    .values()
+                    .filter(|s| match &s.parent {
+                        None => true,
+                        Some(parent) => !self.sites.contains_key(parent),
+                    })
+                    .cloned()
+                    .collect();
+                found.sort_by(|a, b| a.name.cmp(&b.name));
+                found
+            }
+        };
+        if roots.is_empty() {
+            return Err(Error::msg("No root sites found"));
+        }
+        for root in roots.iter_mut() {
+            root.take_children(&self.sites);
+        }
+        parentless.sort_by(|a, b| {
+            (&a.device.hostname, &a.device.id).cmp(&(&b.device.hostname, &b.device.id))
+        });
+        roots[0].access_points.insert(
+            "0".to_string(),
+            LqAccessPoint {
+                name: UNPARENTED.to_string(),
+                clients: parentless.iter().map(|p| p.device.clone()).collect(),
+                download_mbps: self.default_rates.0,
+                upload_mbps: self.default_rates.1,
+            },
+        );
+
+        Ok(Topology {
+            roots,
+            sites: self.sites,
+            parentless,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{assign_unique_names, NodeNaming};
+    use std::collections::BTreeMap;
+
+    fn device(id: &str, ap: (&str, &str), site: &str) -> LqClientDevice {
+        LqClientDevice {
+            id: id.to_string(),
+            hostname: id.to_string(),
+            mac: String::new(),
+            model: String::new(),
+            ip: String::new(),
+            access_point_id: ap.0.to_string(),
+            access_point_name: ap.1.to_string(),
+            parent_site_id: site.to_string(),
+            parent_site_name: site.to_uppercase(),
+            upload: 0,
+            download: 0,
+            is_access_point: false,
+            is_bridge: false,
+        }
+    }
+
+    fn circuit(device: LqClientDevice) -> LqClientSite {
+        LqClientSite {
+            id: device.id.clone(),
+            name: device.id.clone(),
+            download: 0,
+            upload: 0,
+            suspended: false,
+            devices: vec![device],
+        }
+    }
+
+    fn sites() -> HashMap<String, LqSite> {
+        HashMap::from([(
+            "tower".to_string(),
+            LqSite {
+                id: "tower".to_string(),
+                name: "Tower".to_string(),
+                parent: None,
+                children: Vec::new(),
+                access_points: BTreeMap::new(),
+                download_mbps: 1_000,
+                upload_mbps: 1_000,
+            },
+        )])
+    }
+
+    #[test]
+    fn access_points_with_the_same_name_are_kept_apart() {
+        let mut clients = vec![
+            circuit(device("a", ("ap-1", "Sector"), "tower")),
+            circuit(device("b", ("ap-2", "Sector"), "tower")),
+            circuit(device("c", ("", ""), "tower")),
+        ];
+        let rates = HashMap::from([
+            ("ap-1".to_string(), (100, 10)),
+            ("ap-2".to_string(), (200, 20)),
+        ]);
+        let mut topology = TopologyBuilder::new(sites())
+            .access_point_rates(rates)
+            .build("Tower", &mut clients)
+            .unwrap();
+
+        let aps: Vec<(&str, &str, usize, usize)> = topology
+            .sites
+            .values()
+            .flat_map(|s| s.access_points.iter())
+            .map(|(id, ap)| {
+                (
+                    id.as_str(),
+                    ap.name.as_str(),
+                    ap.download_mbps,
+                    ap.clients.len(),
+                )
+            })
+            .collect();
+        assert_eq!(
+            aps,
+            vec![
+                ("ap-1", "Sector", 100, 1),
+                ("ap-2", "Sector", 200, 1),
+                ("tower-NoAP", "TOWER-NoAP", 1_000, 1),
+            ]
+        );
+
+        assign_unique_names(&mut topology.roots, &mut clients, &NodeNaming::default());
+        let names: Vec<(&str, &str)> = clients
+            .iter()
+            .map(|c| (c.id.as_str(), c.devices[0].access_point_name.as_str()))
+            .collect();
+        assert_eq!(
+            names,
+            vec![("a", "Sector"), ("b", "Sector (2)"), ("c", "TOWER-NoAP")]
+        );
+        let tree = &topology.roots[0].access_points;
+        assert_eq!(tree["Sector"].download_mbps, 100);
+        assert_eq!(tree["Sector (2)"].download_mbps, 200);
+    }
+
+    #[test]
+    fn parentless_devices_record_why() {
+        let mut clients = vec![
+            circuit(device("a", ("ap-1", "Sector"), "")),
+            circuit(device("b", ("", ""), "gone")),
+        ];
+        let topology = TopologyBuilder::new(sites())
+            .build("Tower", &mut clients)
+            .unwrap();
+        let parentless: Vec<(&str, ParentlessReason, &str)> = topology
+            .parentless
+            .iter()
+            .map(|p| (p.device.id.as_str(), p.reason, p.access_point_id.as_str()))
+            .collect();
+        assert_eq!(
+            parentless,
+            vec![
+                ("a", ParentlessReason::NoParentSite, "ap-1"),
+                ("b", ParentlessReason::ParentSiteNotFound, ""),
+            ]
+        );
+        assert_eq!(clients[0].devices[0].access_point_name, UNPARENTED);
+        assert_eq!(topology.root().access_points["0"].clients.len(), 2);
+    }
+
+    #[test]
+    fn an_ambiguous_root_name_is_an_error() {
+        let mut sites = sites();
+        sites.insert(
+            "tower-2".to_string(),
+            LqSite {
+                id: "tower-2".to_string(),
+                ..sites["tower"].clone()
+            },
+        );
+        let error = TopologyBuilder::new(sites)
+            .build("Tower", &mut Vec::new())
+            .unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "Root site name 'Tower' is shared by sites tower, tower-2; rename all but one of them"
+        );
+    }
+
+    #[test]
+    fn sites_not_beneath_the_root_are_rejected() {
+        let mut sites = sites();
+        for (id, parent) in [("relay", Some("tower")), ("orphan", Some("missing"))] {
+            sites.insert(
+                id.to_string(),
+                LqSite {
+                    id: id.to_string(),
+                    name: id.to_uppercase(),
+                    parent: parent.map(str::to_string),
+                    ..sites["tower"].clone()
+                },
+            );
+        }
+        let topology = TopologyBuilder::new(sites)
+            .build("Tower", &mut Vec::new())
+            .unwrap();
+        let rejected: Vec<&str> = topology
+            .rejected_sites()
+            .iter()
+            .map(|s| s.id.as_str())
+            .collect();
+        assert_eq!(rejected, vec!["orphan"]);
+    }
+}
@@ -0,0 +1,163 @@
+use crate::{
+    node_loads_with_top, write_atomic, CapacityLimits, LoadLevel, LqClientSite, LqSite, NodeLoad,
+    OutputConfig,
+};
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+
+/// Where the capacity-planning report is written.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct CapacityPlanConfig {
+    /// Path of a CSV file with one row per site and access point.
+    pub csv: Option<String>,
+    /// Path of a JSON file with the same figures.
+    pub json: Option<String>,
+    /// How many of the highest-rate circuits to list for each node.
+    pub top_circuits: usize,
+    /// Include the nodes over `capacity.warn_ratio` in the HTML run report.
+    pub in_report: bool,
+}
+
+impl CapacityPlanConfig {
+    /// Resolves the report paths against the output directory.
+    pub fn resolve_paths(&mut self, output: &OutputConfig) {
+        output.resolve(&mut self.csv);
+        output.resolve(&mut self.json);
+    }
+}
+
+impl Default for CapacityPlanConfig {
+    fn default() -> Self {
+        Self {
+            csv: None,
+            json: None,
+            top_circuits: 5,
+            in_report: true,
+        }
+    }
+}
+
+/// One site or access point in a [`CapacityPlan`].
+#[derive(Clone, Debug, Serialize)]
+pub struct PlanEntry {
+    /// `"site"` or `"access_point"`.
+    pub kind: &'static str,
+    #[serde(flatten)]
+    pub load: NodeLoad,
+    pub oversubscription: f64,
+    pub level: LoadLevel,
+}
+
+/// Provisioned bandwidth against capacity for every node in the tree.
+#[derive(Clone, Debug, Serialize)]
+pub struct CapacityPlan {
+    pub warn_ratio: f64,
+    pub critical_ratio: f64,
+    /// Sites, then access points, each most oversubscribed first.
+    pub nodes: Vec<PlanEntry>,
+    /// Names of the sites over `warn_ratio`, most oversubscribed first.
+    pub sites_over_limit: Vec<String>,
+}
+
+fn entries(kind: &'static str, loads: Vec<NodeLoad>, limits: &CapacityLimits) -> Vec<PlanEntry> {
+    let mut entries: Vec<PlanEntry> = loads
+        .into_iter()
+        .map(|load| PlanEntry {
+            kind,
+            oversubscription: load.oversubscription(),
+            level: limits.level(&load),
+            load,
+        })
+        .collect();
+    entries.sort_by(|a, b| {
+        b.oversubscription
+            .total_cmp(&a.oversubscription)
+            .then_with(|| a.load.name.cmp(&b.load.name))
+            .then_with(|| a.load.id.cmp(&b.load.id))
+    });
+    entries
+}
+
+/// Compares the sum of the maximum rates of the circuits beneath every site
+/// and access point with its configured capacity, listing up to
+/// `top_circuits` of the circuits contributing most to each.
+pub fn capacity_plan(
+    roots: &[LqSite],
+    clients: &[LqClientSite],
+    limits: &CapacityLimits,
+    top_circuits: usize,
+) -> CapacityPlan {
+    let (sites, access_points) = node_loads_with_top(roots, clients, top_circuits);
+    let mut nodes = entries("site", sites, limits);
+    let sites_over_limit = nodes
+        .iter()
+        .filter(|e| e.level != LoadLevel::Normal)
+        .map(|e| e.load.name.clone())
+        .collect();
+    nodes.extend(entries("access_point", access_points, limits));
+    CapacityPlan {
+        warn_ratio: limits.warn_ratio,
+        critical_ratio: limits.critical_ratio,
+        nodes,
+        sites_over_limit,
+    }
+}
+
+impl CapacityPlan {
+    /// One row per node. Top circuits are listed in a single column as
+    /// `name (download/upload)`, separated by semicolons.
+    pub fn to_csv(&self) -> Result<String> {
+        let mut writer = csv::Writer::from_writer(Vec::new());
+        writer.write_record([
+            "Type",
+            "ID",
+            "Name",
+            "Download Mbps",
+            "Upload Mbps",
+            "Provisioned Download Mbps",
+            "Provisioned Upload Mbps",
+            "Circuits",
+            "Oversubscription",
+            "Level",
+            "Top Circuits",
+        ])?;
+        for entry in self.nodes.iter() {
+            let load = &entry.load;
+            let top: Vec<String> = load
+                .top_circuits
+                .iter()
+                .map(|c| format!("{} ({}/{})", c.name, c.download_mbps, c.upload_mbps))
+                .collect();
+            writer.write_record([
+                entry.kind.to_string(),
+                load.id.clone(),
+                load.name.clone(),
+                load.download_mbps.to_string(),
+                load.upload_mbps.to_string(),
+                load.provisioned_download_mbps.to_string(),
+                load.provisioned_upload_mbps.to_string(),
+                load.circuits.to_string(),
+                format!("{:.2}", entry.oversubscription),
+                entry.level.as_str().to_string(),
+                top.join("; "),
+            ])?;
+        }
+        Ok(String::from_utf8(writer.into_inner()?)?)
+    }
+
+    pub fn to_json(&self) -> Result<String> {
+        Ok(serde_json::to_string_pretty(self)?)
+    }
+}
+
+/// Writes the plan as CSV and/or JSON, as configured.
+pub fn write_capacity_plan(config: &CapacityPlanConfig, plan: &CapacityPlan) -> Result<()> {
+    if let Some(path) = &config.csv {
+        write_atomic(path, plan.to_csv()?.as_bytes())?;
+    }
+    if let Some(path) = &config.json {
+        write_atomic(path, plan.to_json()?.as_bytes())?;
+    }
+    Ok(())
+}
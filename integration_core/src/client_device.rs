@@ -0,0 +1,41 @@
+#[derive(Debug, Clone)]
+pub struct LqClientDevice {
+    pub id: String,
+    pub hostname: String,
+    pub mac: String,
+    pub model: String,
+    pub ip: String,
+    pub access_point_id: String,
+    pub access_point_name: String,
+    pub parent_site_id: String,
+    pub parent_site_name: String,
+    pub upload: usize,
+    pub download: usize,
+    pub is_access_point: bool,
+    pub is_bridge: bool,
+}
+
+impl LqClientDevice {
+    /// The name of the tree node this device is shaped beneath: its access
+    /// point, or a synthetic `"{site}-NoAP"` node if it doesn't have one.
+    pub fn parent_node(&self) -> String {
+        if self.access_point_name.is_empty() {
+            format!("{}-NoAP", self.parent_site_name.replace(',', "_"))
+        } else {
+            self.access_point_name.clone()
+        }
+    }
+
+    /// The ID of the tree node this device is shaped beneath: its access
+    /// point's ID (or name, if the source has no IDs), or `"{site ID}-NoAP"`.
+    /// Sites' access points are keyed by this ID.
+    pub fn parent_node_id(&self) -> String {
+        if !self.access_point_id.is_empty() {
+            self.access_point_id.clone()
+        } else if self.access_point_name.is_empty() {
+            format!("{}-NoAP", self.parent_site_id)
+        } else {
+            self.access_point_name.clone()
+        }
+    }
+}
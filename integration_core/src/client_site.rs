@@ -0,0 +1,26 @@
+use crate::LqClientDevice;
+
+#[derive(Debug, Clone)]
+pub struct LqClientSite {
+    pub id: String,
+    pub name: String,
+    pub download: usize,
+    pub upload: usize,
+    pub suspended: bool,
+    pub devices: Vec<LqClientDevice>,
+}
+
+/// Circuits sorted by name then ID, each with its devices sorted by ID, so
+/// generated files come out in the same order every run.
+pub(crate) fn output_order(clients: &[LqClientSite]) -> Vec<(&LqClientSite, Vec<&LqClientDevice>)> {
+    let mut circuits: Vec<&LqClientSite> = clients.iter().collect();
+    circuits.sort_by(|a, b| a.name.cmp(&b.name).then_with(|| a.id.cmp(&b.id)));
+    circuits
+        .into_iter()
+        .map(|circuit| {
+            let mut devices: Vec<&LqClientDevice> = circuit.devices.iter().collect();
+            devices.sort_by(|a, b| a.id.cmp(&b.id));
+            (circuit, devices)
+        })
+        .collect()
+}
@@ -0,0 +1,546 @@
+use crate::{
+    Integration, Inventory, LqAccessPoint, LqClientDevice, LqClientSite, LqSite, RunMetrics,
+};
+use anyhow::{Error, Result};
+use async_trait::async_trait;
+use serde_json::Value;
+use std::{
+    collections::{BTreeMap, HashMap, HashSet},
+    fmt, fs,
+    net::IpAddr,
+    path::{Path, PathBuf},
+};
+
+/// A row-level problem in an inventory file.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ImportError {
+    pub file: String,
+    pub line: usize,
+    pub message: String,
+}
+
+impl fmt::Display for ImportError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}: {}", self.file, self.line, self.message)
+    }
+}
+
+/// The kinds of record an inventory file can hold.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum RecordKind {
+    Site,
+    AccessPoint,
+    Device,
+}
+
+/// One row from a CSV file or line from a JSON Lines file, with its origin.
+struct Record {
+    kind: RecordKind,
+    file: String,
+    line: usize,
+    fields: HashMap<String, String>,
+}
+
+impl Record {
+    fn error(&self, message: impl Into<String>) -> ImportError {
+        ImportError {
+            file: self.file.clone(),
+            line: self.line,
+            message: message.into(),
+        }
+    }
+
+    fn optional(&self, field: &str) -> String {
+        self.fields
+            .get(field)
+            .map(|v| v.trim().to_string())
+            .unwrap_or_default()
+    }
+
+    fn required(&self, field: &str) -> Result<String, ImportError> {
+        let value = self.optional(field);
+        if value.is_empty() {
+            Err(self.error(format!("missing {field}")))
+        } else {
+            Ok(value)
+        }
+    }
+
+    /// Reads a rate in Mbps. Blank means the default of 1000.
+    fn mbps(&self, field: &str) -> Result<usize, ImportError> {
+        let value = self.optional(field);
+        if value.is_empty() {
+            return Ok(1_000);
+        }
+        value
+            .parse::<f64>()
+            .ok()
+            .filter(|n| *n > 0.0)
+            .map(|n| n as usize)
+            .ok_or_else(|| self.error(format!("{field} must be a positive number, not '{value}'")))
+    }
+}
+
+/// Reads a CSV inventory from a directory holding `sites.csv`,
+/// `access_points.csv` (optional) and `devices.csv`.
+fn read_csv_dir(dir: &Path, errors: &mut Vec<ImportError>) -> Result<Vec<Record>> {
+    let mut records = Vec::new();
+    for (file, kind, required) in [
+        ("sites.csv", RecordKind::Site, true),
+        ("access_points.csv", RecordKind::AccessPoint, false),
+        ("devices.csv", RecordKind::Device, true),
+    ] {
+        let path = dir.join(file);
+        if !path.exists() {
+            if required {
+                return Err(Error::msg(format!("{} not found", path.display())));
+            }
+            continue;
+        }
+        let mut reader = csv::ReaderBuilder::new()
+            .trim(csv::Trim::All)
+            .comment(Some(b'#'))
+            .flexible(true)
+            .from_path(&path)?;
+        let headers: Vec<String> = reader.headers()?.iter().map(|h| h.to_lowercase()).collect();
+        for row in reader.records() {
+            match row {
+                Ok(row) => records.push(Record {
+                    kind,
+                    file: path.display().to_string(),
+                    line: row.position().map(|p| p.line() as usize).unwrap_or(0),
+                    fields: headers
+                        .iter()
+                        .cloned()
+                        .zip(row.iter().map(|v| v.to_string()))
+                        .collect(),
+                }),
+                Err(e) => errors.push(ImportError {
+                    file: path.display().to_string(),
+                    line: e.position().map(|p| p.line() as usize).unwrap_or(0),
+                    message: e.to_string(),
+                }),
+            }
+        }
+    }
+    Ok(records)
+}
+
+/// Reads a JSON Lines inventory: one object per line, each with a `type` of
+/// `site`, `access_point` or `device` and the same fields as the CSV columns.
+fn read_json_lines(path: &Path, errors: &mut Vec<ImportError>) -> Result<Vec<Record>> {
+    let file = path.display().to_string();
+    let mut records = Vec::new();
+    for (i, line) in fs::read_to_string(path)?.lines().enumerate() {
+        let line_number = i + 1;
+        if line.trim().is_empty() || line.trim_start().starts_with("//") {
+            continue;
+        }
+        let error = |message: String| ImportError {
+            file: file.clone(),
+            line: line_number,
+            message,
+        };
+        let object = match serde_json::from_str::<Value>(line) {
+            Ok(Value::Object(object)) => object,
+            Ok(_) => {
+                errors.push(error("expected a JSON object".to_string()));
+                continue;
+            }
+            Err(e) => {
+                errors.push(error(format!("invalid JSON: {e}")));
+                continue;
+            }
+        };
+        let kind = match object.get("type").and_then(|t| t.as_str()) {
+            Some("site") => RecordKind::Site,
+            Some("access_point") => RecordKind::AccessPoint,
+            Some("device") => RecordKind::Device,
+            _ => {
+                errors.push(error(
+                    "type must be site, access_point or device".to_string(),
+                ));
+                continue;
+            }
+        };
+        let fields = object
+            .into_iter()
+            .filter_map(|(k, v)| {
+                let value = match v {
+                    Value::String(s) => s,
+                    Value::Number(n) => n.to_string(),
+                    Value::Bool(b) => b.to_string(),
+                    _ => return None,
+                };
+                Some((k.to_lowercase(), value))
+            })
+            .collect();
+        records.push(Record {
+            kind,
+            file: file.clone(),
+            line: line_number,
+            fields,
+        });
+    }
+    Ok(records)
+}
+
+/// Validates records and converts them into an inventory. All problems are
+/// collected, rather than stopping at the first.
+fn build_inventory(records: &[Record], errors: &mut Vec<ImportError>) -> Inventory {
+    let mut inventory = Inventory::default();
+
+    for record in records.iter().filter(|r| r.kind == RecordKind::Site) {
+        let site = (|| {
+            Ok::<_, ImportError>(LqSite {
+                id: record.required("id")?,
+                name: record.required("name")?.replace(',', "_"),
+                parent: Some(record.optional("parent")).filter(|p| !p.is_empty()),
+                children: Vec::new(),
+                access_points: BTreeMap::new(),
+                download_mbps: record.mbps("download_mbps")?,
+                upload_mbps: record.mbps("upload_mbps")?,
+            })
+        })();
+        match site {
+            Ok(site) if inventory.sites.contains_key(&site.id) => {
+                errors.push(record.error(format!("duplicate site id {}", site.id)))
+            }
+            Ok(site) => {
+                inventory.sites.insert(site.id.clone(), site);
+            }
+            Err(e) => errors.push(e),
+        }
+    }
+    for record in records.iter().filter(|r| r.kind == RecordKind::Site) {
+        let parent = record.optional("parent");
+        if !parent.is_empty() && !inventory.sites.contains_key(&parent) {
+            errors.push(record.error(format!("unknown parent site {parent}")));
+        }
+    }
+
+    for record in records.iter().filter(|r| r.kind == RecordKind::AccessPoint) {
+        let ap = (|| {
+            let site = record.required("site")?;
+            let ap = LqAccessPoint {
+                name: record.required("name")?.replace(',', "_"),
+                download_mbps: record.mbps("download_mbps")?,
+                upload_mbps: record.mbps("upload_mbps")?,
+                clients: Vec::new(),
+            };
+            Ok::<_, ImportError>((site, ap))
+        })();
+        match ap {
+            Ok((site, ap)) => match inventory.sites.get_mut(&site) {
+                Some(site) if site.access_points.contains_key(&ap.name) => {
+                    errors.push(record.error(format!("duplicate access point {}", ap.name)))
+                }
+                Some(site) => {
+                    site.access_points.insert(ap.name.clone(), ap);
+                }
+                None => errors.push(record.error(format!("unknown site {site}"))),
+            },
+            Err(e) => errors.push(e),
+        }
+    }
+
+    let mut device_ids = HashSet::new();
+    let mut circuits = HashMap::<String, usize>::new();
+    for record in records.iter().filter(|r| r.kind == RecordKind::Device) {
+        let device = (|| {
+            let circuit_id = record.required("circuit_id")?;
+            let site_id = record.required("site")?;
+            let site = inventory
+                .sites
+                .get(&site_id)
+                .ok_or_else(|| record.error(format!("unknown site {site_id}")))?;
+            let access_point = record.optional("access_point").replace(',', "_");
+            if !access_point.is_empty() && !site.access_points.contains_key(&access_point) {
+                return Err(record.error(format!(
+                    "unknown access point {access_point} in site {site_id}"
+                )));
+            }
+            let ip = record.required("ip")?;
+            let address = ip.split('/').next().unwrap_or_default();
+            if address.parse::<IpAddr>().is_err() {
+                return Err(record.error(format!("invalid IP address {ip}")));
+            }
+            let download = record.mbps("download_mbps")? * 1_000_000;
+            let upload = record.mbps("upload_mbps")? * 1_000_000;
+            let device_id = record.required("device_id")?;
+            Ok(LqClientDevice {
+                id: device_id.clone(),
+                hostname: Some(record.optional("device_name"))
+                    .filter(|n| !n.is_empty())
+                    .unwrap_or(device_id)
+                    .replace(',', "_"),
+                mac: record.optional("mac"),
+                model: record.optional("model"),
+                ip,
+                access_point_id: access_point.clone(),
+                access_point_name: access_point,
+                parent_site_id: site.id.clone(),
+                parent_site_name: site.name.clone(),
+                upload,
+                download,
+                is_access_point: false,
+                is_bridge: false,
+            })
+            .map(|d| (circuit_id, d))
+        })();
+
+        let (circuit_id, device) = match device {
+            Ok(d) => d,
+            Err(e) => {
+                errors.push(e);
+                continue;
+            }
+        };
+        if !device_ids.insert(device.id.clone()) {
+            errors.push(record.error(format!("duplicate device id {}", device.id)));
+            continue;
+        }
+        let idx = *circuits.entry(circuit_id.clone()).or_insert_with(|| {
+            let name = Some(record.optional("circuit_name"))
+                .filter(|n| !n.is_empty())
+                .unwrap_or_else(|| circuit_id.clone());
+            inventory.circuits.push(LqClientSite {
+                id: circuit_id.clone(),
+                name: name.replace(',', "_"),
+                download: device.download,
+                upload: device.upload,
+                suspended: false,
+                devices: Vec::new(),
+            });
+            inventory.circuits.len() - 1
+        });
+        let circuit = &mut inventory.circuits[idx];
+        if circuit.download != device.download || circuit.upload != device.upload {
+            errors.push(record.error(format!(
+                "rates differ from earlier devices in circuit {circuit_id}"
+            )));
+            continue;
+        }
+        circuit.devices.push(device);
+    }
+
+    inventory
+}
+
+/// Loads an inventory from a CSV directory or a JSON Lines file (`.json` or
+/// `.jsonl`). Fails with every row-level error, one per line, if the input
+/// doesn't validate.
+pub fn load_inventory_file<P: AsRef<Path>>(path: P) -> Result<Inventory> {
+    let path = path.as_ref();
+    let mut errors = Vec::new();
+    let records = if path.is_dir() {
+        read_csv_dir(path, &mut errors)?
+    } else {
+        read_json_lines(path, &mut errors)?
+    };
+    let inventory = build_inventory(&records, &mut errors);
+    if errors.is_empty() {
+        Ok(inventory)
+    } else {
+        let messages: Vec<String> = errors.iter().map(|e| e.to_string()).collect();
+        Err(Error::msg(format!(
+            "{} problems in inventory:\n{}",
+            errors.len(),
+            messages.join("\n")
+        )))
+    }
+}
+
+/// A data source reading a documented file format, for small operators and
+/// lab testing. See the crate README for the format.
+pub struct FileIntegration {
+    path: PathBuf,
+}
+
+impl FileIntegration {
+    pub fn new<P: AsRef<Path>>(path: P) -> Self {
+        Self {
+            path: path.as_ref().to_path_buf(),
+        }
+    }
+}
+
+#[async_trait]
+impl Integration for FileIntegration {
+    fn name(&self) -> &str {
+        "file"
+    }
+
+    async fn fetch_inventory(&self, _metrics: &RunMetrics) -> Result<Inventory> {
+        load_inventory_file(&self.path)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    fn write(dir: &TempDir, name: &str, contents: &str) -> PathBuf {
+        let path = dir.path().join(name);
+        fs::write(&path, contents).unwrap();
+        path
+    }
+
+    /// The problems reported for an inventory, with the directory left out
+    /// of file names.
+    fn problems(dir: &TempDir, path: &Path) -> Vec<String> {
+        let error = load_inventory_file(path).unwrap_err().to_string();
+        let prefix = format!("{}/", dir.path().display());
+        error
+            .lines()
+            .skip(1)
+            .map(|line| line.replace(&prefix, ""))
+            .collect()
+    }
+
+    /// Checks the inventory both happy-path tests describe.
+    fn check_inventory(inventory: &Inventory) {
+        let pop = &inventory.sites["pop1"];
+        assert_eq!(pop.name, "POP 1");
+        assert_eq!((pop.download_mbps, pop.upload_mbps), (10_000, 1_000));
+        let tower = &inventory.sites["tower"];
+        assert_eq!(tower.name, "Tower_ North");
+        assert_eq!(tower.parent.as_deref(), Some("pop1"));
+        let ap = &tower.access_points["Sector A"];
+        assert_eq!((ap.download_mbps, ap.upload_mbps), (500, 100));
+
+        assert_eq!(inventory.circuits.len(), 2);
+        let jane = &inventory.circuits[0];
+        assert_eq!((jane.id.as_str(), jane.name.as_str()), ("c1", "Doe_ Jane"));
+        assert_eq!((jane.download, jane.upload), (100_000_000, 20_000_000));
+        let devices: Vec<(&str, &str, &str)> = jane
+            .devices
+            .iter()
+            .map(|d| (d.id.as_str(), d.ip.as_str(), d.access_point_id.as_str()))
+            .collect();
+        assert_eq!(
+            devices,
+            vec![
+                ("d1", "100.64.0.10", "Sector A"),
+                ("d2", "2001:db8::/64", "Sector A"),
+            ]
+        );
+        assert_eq!(jane.devices[0].parent_site_name, "Tower_ North");
+        // A circuit without a name is named after its ID, and a device
+        // without an access point sits on its site.
+        let office = &inventory.circuits[1];
+        assert_eq!(office.name, "c2");
+        assert_eq!(office.devices[0].hostname, "d3");
+        assert_eq!(office.devices[0].access_point_id, "");
+        assert_eq!(office.devices[0].parent_site_id, "pop1");
+    }
+
+    #[test]
+    fn csv_directories_are_loaded() {
+        let dir = TempDir::new().unwrap();
+        write(
+            &dir,
+            "sites.csv",
+            "id,name,parent,download_mbps,upload_mbps\n\
+             # The core\n\
+             pop1,POP 1,,10000,1000\n\
+             tower,\"Tower, North\",pop1,,\n",
+        );
+        write(
+            &dir,
+            "access_points.csv",
+            "name,site,download_mbps,upload_mbps\nSector A,tower,500,100\n",
+        );
+        write(
+            &dir,
+            "devices.csv",
+            "circuit_id,circuit_name,device_id,device_name,site,access_point,mac,ip,download_mbps,upload_mbps\n\
+             c1,\"Doe, Jane\",d1,Router,tower,Sector A,00:11:22:33:44:55,100.64.0.10,100,20\n\
+             c1,\"Doe, Jane\",d2,Router,tower,Sector A,00:11:22:33:44:55,2001:db8::/64,100,20\n\
+             c2,,d3,,pop1,,,100.64.0.11,50,50\n",
+        );
+        check_inventory(&load_inventory_file(dir.path()).unwrap());
+    }
+
+    #[test]
+    fn json_lines_are_loaded() {
+        let dir = TempDir::new().unwrap();
+        let path = write(
+            &dir,
+            "inventory.jsonl",
+            r#"// The core
+{"type": "site", "id": "pop1", "name": "POP 1", "download_mbps": 10000, "upload_mbps": 1000}
+{"type": "site", "id": "tower", "name": "Tower, North", "parent": "pop1"}
+
+{"type": "access_point", "site": "tower", "name": "Sector A", "download_mbps": 500, "upload_mbps": 100}
+{"type": "device", "circuit_id": "c1", "circuit_name": "Doe, Jane", "device_id": "d1", "site": "tower", "access_point": "Sector A", "ip": "100.64.0.10", "download_mbps": 100, "upload_mbps": 20}
+{"type": "device", "circuit_id": "c1", "circuit_name": "Doe, Jane", "device_id": "d2", "site": "tower", "access_point": "Sector A", "ip": "2001:db8::/64", "download_mbps": 100, "upload_mbps": 20}
+{"type": "device", "circuit_id": "c2", "device_id": "d3", "site": "pop1", "ip": "100.64.0.11", "download_mbps": 50, "upload_mbps": 50}
+"#,
+        );
+        check_inventory(&load_inventory_file(path).unwrap());
+    }
+
+    #[test]
+    fn csv_problems_are_reported_by_line() {
+        let dir = TempDir::new().unwrap();
+        write(&dir, "sites.csv", "id,name\npop1,POP 1\npop1,POP 1 again\n");
+        write(&dir, "access_points.csv", "site,name\npop1,Sector A\n");
+        write(
+            &dir,
+            "devices.csv",
+            "circuit_id,device_id,site,access_point,ip,download_mbps,upload_mbps\n\
+             c1,d1,pop1,Sector A,100.64.0.300,10,10\n\
+             c1,d2,pop9,,100.64.0.2,10,10\n\
+             c1,d3,pop1,Sector B,100.64.0.3,10,10\n\
+             c1,d4,pop1,,100.64.0.4,10,10\n\
+             c1,d4,pop1,,100.64.0.5,10,10\n\
+             c1,d6,pop1,,100.64.0.6,20,10\n",
+        );
+        assert_eq!(
+            problems(&dir, dir.path()),
+            vec![
+                "sites.csv:3: duplicate site id pop1",
+                "devices.csv:2: invalid IP address 100.64.0.300",
+                "devices.csv:3: unknown site pop9",
+                "devices.csv:4: unknown access point Sector B in site pop1",
+                "devices.csv:6: duplicate device id d4",
+                "devices.csv:7: rates differ from earlier devices in circuit c1",
+            ]
+        );
+    }
+
+    #[test]
+    fn json_lines_problems_are_reported_by_line() {
+        let dir = TempDir::new().unwrap();
+        let path = write(
+            &dir,
+            "inventory.jsonl",
+            r#"// Comments and blank lines still count towards line numbers.
+
+{"type": "site", "id": "pop1", "name": "POP 1"}
+{"type": "site", "id": "pop1", "name": "POP 1 again"}
+{"type": "access_point", "site": "pop9", "name": "Sector A"}
+{"type": "router"}
+{"type": "device", "circuit_id": "c1", "device_id": "d1", "site": "pop1", "ip": "not an ip"}
+{"type": "device", "circuit_id": "c1", "device_id": "d2", "site": "pop1", "ip": "100.64.0.2"}
+{"type": "device", "circuit_id": "c1", "device_id": "d2", "site": "pop1", "ip": "100.64.0.3"}
+{"type": "device", "circuit_id": "c1", "device_id": "d4", "site": "pop1", "ip": "100.64.0.4", "upload_mbps": 5}
+{"type": "device", "circuit_id": "c2", "device_id": "d5", "site": "pop1", "access_point": "Sector A", "ip": "100.64.0.5"}
+"#,
+        );
+        assert_eq!(
+            problems(&dir, &path),
+            vec![
+                "inventory.jsonl:6: type must be site, access_point or device",
+                "inventory.jsonl:4: duplicate site id pop1",
+                "inventory.jsonl:5: unknown site pop9",
+                "inventory.jsonl:7: invalid IP address not an ip",
+                "inventory.jsonl:9: duplicate device id d2",
+                "inventory.jsonl:10: rates differ from earlier devices in circuit c1",
+                "inventory.jsonl:11: unknown access point Sector A in site pop1",
+            ]
+        );
+    }
+}
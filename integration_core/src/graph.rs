@@ -0,0 +1,244 @@
+use crate::{
+    node_loads, write_atomic, CapacityLimits, LoadLevel, LqClientSite, LqSite, NodeLoad,
+    OutputConfig, UNPARENTED,
+};
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+/// Diagrams of the site tree, for spotting mis-parented sites.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct GraphConfig {
+    /// Path of a Graphviz DOT file to write.
+    pub dot: Option<String>,
+    /// Path of a Mermaid flowchart to write.
+    pub mermaid: Option<String>,
+    /// Show the number of circuits beneath each node.
+    pub client_counts: bool,
+}
+
+impl GraphConfig {
+    /// Resolves the diagram paths against the output directory.
+    pub fn resolve_paths(&mut self, output: &OutputConfig) {
+        output.resolve(&mut self.dot);
+        output.resolve(&mut self.mermaid);
+    }
+}
+
+impl Default for GraphConfig {
+    fn default() -> Self {
+        Self {
+            dot: None,
+            mermaid: None,
+            client_counts: true,
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum Level {
+    Ok,
+    Warn,
+    Critical,
+    /// Nodes that aren't shaped against a capacity, like "Unparented".
+    Other,
+}
+
+impl Level {
+    fn color(self) -> &'static str {
+        match self {
+            Self::Ok => "#c8e6c9",
+            Self::Warn => "#ffe0b2",
+            Self::Critical => "#ffcdd2",
+            Self::Other => "#e0e0e0",
+        }
+    }
+
+    fn class(self) -> &'static str {
+        match self {
+            Self::Ok => "ok",
+            Self::Warn => "warn",
+            Self::Critical => "critical",
+            Self::Other => "other",
+        }
+    }
+}
+
+/// A node of the diagram, in tree order.
+struct Node {
+    label: String,
+    level: Level,
+    /// Index of the parent node, and the edge's label.
+    parent: Option<(usize, String)>,
+}
+
+fn label(name: &str, load: Option<&NodeLoad>, config: &GraphConfig) -> String {
+    match load {
+        Some(load) if config.client_counts => format!("{name}\n{} circuits", load.circuits),
+        _ => name.to_string(),
+    }
+}
+
+fn level(load: Option<&NodeLoad>, limits: &CapacityLimits) -> Level {
+    match load.map(|l| limits.level(l)) {
+        None => Level::Other,
+        Some(LoadLevel::Critical) => Level::Critical,
+        Some(LoadLevel::Warn) => Level::Warn,
+        Some(LoadLevel::Normal) => Level::Ok,
+    }
+}
+
+fn capacity(download_mbps: usize, upload_mbps: usize) -> String {
+    format!("{download_mbps}/{upload_mbps} Mbps")
+}
+
+fn add_site(
+    site: &LqSite,
+    parent: Option<usize>,
+    loads: &(HashMap<&str, &NodeLoad>, HashMap<&str, &NodeLoad>),
+    config: &GraphConfig,
+    limits: &CapacityLimits,
+    nodes: &mut Vec<Node>,
+) {
+    let load = loads.0.get(site.id.as_str()).copied();
+    let index = nodes.len();
+    nodes.push(Node {
+        label: label(&site.name, load, config),
+        level: level(load, limits),
+        parent: parent.map(|p| (p, capacity(site.download_mbps, site.upload_mbps))),
+    });
+    for (id, ap) in site.access_points.iter() {
+        let load = loads.1.get(id.as_str()).copied();
+        // The parentless devices' node isn't shaped, so shows no capacity.
+        let (label, edge) = if ap.name == UNPARENTED {
+            let label = if config.client_counts {
+                format!("{}\n{} devices", ap.name, ap.clients.len())
+            } else {
+                ap.name.clone()
+            };
+            (label, String::new())
+        } else {
+            (
+                label(&ap.name, load, config),
+                capacity(ap.download_mbps, ap.upload_mbps),
+            )
+        };
+        nodes.push(Node {
+            label,
+            level: level(load, limits),
+            parent: Some((index, edge)),
+        });
+    }
+    for child in site.children.iter() {
+        add_site(child, Some(index), loads, config, limits, nodes);
+    }
+}
+
+fn diagram_nodes(
+    roots: &[LqSite],
+    clients: &[LqClientSite],
+    config: &GraphConfig,
+    limits: &CapacityLimits,
+) -> Vec<Node> {
+    let (sites, access_points) = node_loads(roots, clients);
+    let loads = (
+        sites.iter().map(|l| (l.id.as_str(), l)).collect(),
+        access_points.iter().map(|l| (l.id.as_str(), l)).collect(),
+    );
+    let mut nodes = Vec::new();
+    for root in roots.iter() {
+        add_site(root, None, &loads, config, limits, &mut nodes);
+    }
+    nodes
+}
+
+fn dot_escape(text: &str) -> String {
+    text.replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+/// Renders the tree as a Graphviz digraph. Edges run from parent to child
+/// and are labeled with the child's capacity; nodes are filled by how far
+/// the circuits beneath them exceed that capacity, against `limits`.
+pub fn dot_graph(
+    roots: &[LqSite],
+    clients: &[LqClientSite],
+    config: &GraphConfig,
+    limits: &CapacityLimits,
+) -> String {
+    let mut dot =
+        "digraph network {\n    rankdir=LR;\n    node [shape=box, style=\"rounded,filled\"];\n"
+            .to_string();
+    let nodes = diagram_nodes(roots, clients, config, limits);
+    for (i, node) in nodes.iter().enumerate() {
+        dot += &format!(
+            "    n{i} [label=\"{}\", fillcolor=\"{}\"];\n",
+            dot_escape(&node.label),
+            node.level.color()
+        );
+    }
+    for (i, node) in nodes.iter().enumerate() {
+        if let Some((parent, label)) = &node.parent {
+            dot += &format!("    n{parent} -> n{i} [label=\"{}\"];\n", dot_escape(label));
+        }
+    }
+    dot += "}\n";
+    dot
+}
+
+fn mermaid_escape(text: &str) -> String {
+    text.replace('"', "#quot;").replace('\n', "<br/>")
+}
+
+/// Renders the tree as a Mermaid flowchart, with the same edges and colors
+/// as [`dot_graph`].
+pub fn mermaid_graph(
+    roots: &[LqSite],
+    clients: &[LqClientSite],
+    config: &GraphConfig,
+    limits: &CapacityLimits,
+) -> String {
+    let mut mermaid = "flowchart LR\n".to_string();
+    for level in [Level::Ok, Level::Warn, Level::Critical, Level::Other] {
+        mermaid += &format!("    classDef {} fill:{}\n", level.class(), level.color());
+    }
+    let nodes = diagram_nodes(roots, clients, config, limits);
+    for (i, node) in nodes.iter().enumerate() {
+        mermaid += &format!(
+            "    n{i}[\"{}\"]:::{}\n",
+            mermaid_escape(&node.label),
+            node.level.class()
+        );
+    }
+    for (i, node) in nodes.iter().enumerate() {
+        if let Some((parent, label)) = &node.parent {
+            if label.is_empty() {
+                mermaid += &format!("    n{parent} --> n{i}\n");
+            } else {
+                mermaid += &format!("    n{parent} -->|\"{}\"| n{i}\n", mermaid_escape(label));
+            }
+        }
+    }
+    mermaid
+}
+
+/// Writes whichever diagrams are configured.
+pub fn write_graphs(
+    config: &GraphConfig,
+    limits: &CapacityLimits,
+    roots: &[LqSite],
+    clients: &[LqClientSite],
+) -> Result<()> {
+    if let Some(path) = &config.dot {
+        write_atomic(path, dot_graph(roots, clients, config, limits).as_bytes())?;
+    }
+    if let Some(path) = &config.mermaid {
+        write_atomic(
+            path,
+            mermaid_graph(roots, clients, config, limits).as_bytes(),
+        )?;
+    }
+    Ok(())
+}
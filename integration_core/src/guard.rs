@@ -0,0 +1,376 @@
+use crate::{LqSite, Outputs};
+use anyhow::{Error, Result};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::HashMap;
+
+/// Limits on how much the published outputs may change in one run, as a
+/// percentage of the previous generation. A run that exceeds any of them
+/// isn't published unless forced.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ChangeGuard {
+    pub enabled: bool,
+    /// Change in the number of circuits in `ShapedDevices.csv`.
+    pub max_circuit_change_percent: f64,
+    /// Change in the number of sites in the tree (not counting access
+    /// points). Older configurations call it `max_node_change_percent`.
+    #[serde(alias = "max_node_change_percent")]
+    pub max_site_change_percent: f64,
+    /// Change in the total download or upload bandwidth provisioned to circuits.
+    pub max_bandwidth_change_percent: f64,
+    /// The guard only applies once the previous generation had at least
+    /// this many circuits, so small networks can grow freely.
+    pub min_previous_circuits: usize,
+}
+
+impl Default for ChangeGuard {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            max_circuit_change_percent: 25.0,
+            max_site_change_percent: 25.0,
+            max_bandwidth_change_percent: 25.0,
+            min_previous_circuits: 10,
+        }
+    }
+}
+
+/// The supporting file a run's [`OutputSummary`] is saved in, so the next
+/// run can compare against it.
+pub const SUMMARY_FILE: &str = "OutputSummary.json";
+
+/// Headline figures for a set of outputs, used to compare generations.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct OutputSummary {
+    pub circuits: usize,
+    /// Sites in the tree, not counting access points. `None` for outputs
+    /// published before the summary was saved.
+    pub sites: Option<usize>,
+    /// Total maximum download and upload across circuits, in Mbps.
+    pub download_mbps: usize,
+    pub upload_mbps: usize,
+}
+
+fn count_sites(sites: &[LqSite]) -> usize {
+    sites.iter().map(|s| 1 + count_sites(&s.children)).sum()
+}
+
+impl OutputSummary {
+    /// Summarizes a run about to be published: sites from the site tree,
+    /// circuits and bandwidth from the `ShapedDevices.csv` in `outputs`.
+    pub fn new(roots: &[LqSite], outputs: &Outputs) -> Result<Self> {
+        let shaped_devices = outputs
+            .get("ShapedDevices.csv")
+            .ok_or_else(|| Error::msg("ShapedDevices.csv isn't in the outputs"))?;
+        Ok(Self {
+            sites: Some(count_sites(roots)),
+            ..Self::from_shaped_devices(shaped_devices)?
+        })
+    }
+
+    /// Summarizes a set of published outputs. Returns `None` if nothing has
+    /// been published, as on a first run, and an error if the published
+    /// files can't be read.
+    pub fn from_outputs(outputs: &Outputs) -> Result<Option<Self>> {
+        let (network, shaped_devices) = match (
+            outputs.get("network.json"),
+            outputs.get("ShapedDevices.csv"),
+        ) {
+            (None, None) => return Ok(None),
+            (Some(network), Some(shaped_devices)) => (network, shaped_devices),
+            (None, Some(_)) => return Err(Error::msg("network.json is missing")),
+            (Some(_), None) => return Err(Error::msg("ShapedDevices.csv is missing")),
+        };
+        serde_json::from_str::<Value>(network)
+            .map_err(|e| Error::msg(format!("network.json doesn't parse: {e}")))?;
+        let mut summary = Self::from_shaped_devices(shaped_devices)?;
+        if let Some(saved) = outputs.get(SUMMARY_FILE) {
+            let saved: Self = serde_json::from_str(saved)
+                .map_err(|e| Error::msg(format!("{SUMMARY_FILE} doesn't parse: {e}")))?;
+            summary.sites = saved.sites;
+        }
+        Ok(Some(summary))
+    }
+
+    fn from_shaped_devices(shaped_devices: &str) -> Result<Self> {
+        let mut reader = csv::Reader::from_reader(shaped_devices.as_bytes());
+        let header = reader.headers()?.clone();
+        let column = |name: &str| {
+            header
+                .iter()
+                .position(|c| c == name)
+                .ok_or_else(|| Error::msg(format!("ShapedDevices.csv has no '{name}' column")))
+        };
+        let (id_col, download_col, upload_col) = (
+            column("Circuit ID")?,
+            column("Download Max Mbps")?,
+            column("Upload Max Mbps")?,
+        );
+        let mut circuits = HashMap::<String, (usize, usize)>::new();
+        for record in reader.records() {
+            let record =
+                record.map_err(|e| Error::msg(format!("ShapedDevices.csv doesn't parse: {e}")))?;
+            let rate = |col: usize| record.get(col).and_then(|v| v.parse().ok()).unwrap_or(0);
+            let entry = circuits
+                .entry(record.get(id_col).unwrap_or_default().to_string())
+                .or_default();
+            entry.0 = entry.0.max(rate(download_col));
+            entry.1 = entry.1.max(rate(upload_col));
+        }
+
+        Ok(Self {
+            circuits: circuits.len(),
+            sites: None,
+            download_mbps: circuits.values().map(|r| r.0).sum(),
+            upload_mbps: circuits.values().map(|r| r.1).sum(),
+        })
+    }
+}
+
+impl Outputs {
+    /// Adds the [`OutputSummary`] of these outputs as a supporting file, for
+    /// the next run's [`ChangeGuard`]. Call it once `ShapedDevices.csv` has
+    /// been added.
+    pub fn add_summary(&mut self, roots: &[LqSite]) -> Result<()> {
+        let summary = OutputSummary::new(roots, self)?;
+        self.add_supporting(SUMMARY_FILE, serde_json::to_string_pretty(&summary)?);
+        Ok(())
+    }
+}
+
+fn percent_change(old: usize, new: usize) -> f64 {
+    if old == 0 {
+        if new == 0 {
+            0.0
+        } else {
+            f64::INFINITY
+        }
+    } else {
+        (new as f64 - old as f64).abs() * 100.0 / old as f64
+    }
+}
+
+impl ChangeGuard {
+    /// Describes each limit the new outputs exceed compared with the
+    /// previous ones. Empty if the guard is disabled, there's nothing (or
+    /// too little) to compare against, or the change is within limits.
+    /// Previous outputs that can't be read are a problem in themselves.
+    /// Outputs without a `ShapedDevices.csv` aren't checked.
+    pub fn check(&self, previous: &Outputs, next: &Outputs) -> Vec<String> {
+        let mut problems = Vec::new();
+        // Without a ShapedDevices.csv there is nothing to guard.
+        if !self.enabled || next.get("ShapedDevices.csv").is_none() {
+            return problems;
+        }
+        let old = match OutputSummary::from_outputs(previous) {
+            Ok(Some(old)) if old.circuits >= self.min_previous_circuits => old,
+            Ok(_) => return problems,
+            Err(e) => {
+                problems.push(format!("the published outputs can't be read ({e})"));
+                return problems;
+            }
+        };
+        let new = match OutputSummary::from_outputs(next) {
+            Ok(Some(new)) => new,
+            Ok(None) => return problems,
+            Err(e) => {
+                problems.push(format!("the new outputs can't be read ({e})"));
+                return problems;
+            }
+        };
+        let checks = [
+            (
+                "circuits",
+                old.circuits,
+                new.circuits,
+                self.max_circuit_change_percent,
+            ),
+            (
+                "sites",
+                old.sites.unwrap_or_default(),
+                new.sites.unwrap_or_default(),
+                // Outputs published before sites were counted can't be compared.
+                if old.sites.is_some() && new.sites.is_some() {
+                    self.max_site_change_percent
+                } else {
+                    f64::INFINITY
+                },
+            ),
+            (
+                "download Mbps",
+                old.download_mbps,
+                new.download_mbps,
+                self.max_bandwidth_change_percent,
+            ),
+            (
+                "upload Mbps",
+                old.upload_mbps,
+                new.upload_mbps,
+                self.max_bandwidth_change_percent,
+            ),
+        ];
+        for (what, old, new, limit) in checks {
+            let change = percent_change(old, new);
+            if change > limit {
+                problems.push(format!(
+                    "{what} changed from {old} to {new} ({change:.1}%, limit {limit}%)"
+                ));
+            }
+        }
+        problems
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{network_json, LqAccessPoint, SHAPED_DEVICES_HEADER};
+    use std::collections::BTreeMap;
+
+    /// Outputs with `circuits` circuits of two devices each at `mbps`, and a
+    /// tree of `sites` sites, the first with `aps` access points.
+    fn outputs(circuits: usize, sites: usize, aps: usize, mbps: usize) -> Outputs {
+        let site = |i: usize, aps: usize| LqSite {
+            id: format!("s{i}"),
+            name: format!("Site \"{i}\""),
+            parent: None,
+            children: Vec::new(),
+            access_points: (0..aps)
+                .map(|a| {
+                    let ap = LqAccessPoint {
+                        name: format!("AP {a}"),
+                        download_mbps: 100,
+                        upload_mbps: 100,
+                        clients: Vec::new(),
+                    };
+                    (format!("ap{a}"), ap)
+                })
+                .collect::<BTreeMap<_, _>>(),
+            download_mbps: 1000,
+            upload_mbps: 1000,
+        };
+        let mut root = site(0, aps);
+        root.children = (1..sites).map(|i| site(i, 0)).collect();
+        let mut shaped_devices = format!("{SHAPED_DEVICES_HEADER}\n");
+        for circuit in 0..circuits {
+            for device in 0..2 {
+                // The second device has a lower rate; circuits count their highest.
+                let rate = mbps - device;
+                shaped_devices += &format!(
+                    "c{circuit},Circuit {circuit},d{circuit}-{device},Device,AP 0,,100.64.0.{device},,1,1,{rate},{rate},\n"
+                );
+            }
+        }
+        let roots = [root];
+        let mut outputs = Outputs::default();
+        outputs.add("network.json", network_json(&roots));
+        outputs.add("ShapedDevices.csv", shaped_devices);
+        outputs.add_summary(&roots).unwrap();
+        outputs
+    }
+
+    #[test]
+    fn summaries_count_circuits_sites_and_bandwidth() {
+        let summary = OutputSummary {
+            circuits: 3,
+            sites: Some(2),
+            download_mbps: 150,
+            upload_mbps: 150,
+        };
+        let outputs = outputs(3, 2, 4, 50);
+        assert_eq!(
+            OutputSummary::from_outputs(&outputs).unwrap(),
+            Some(summary)
+        );
+        assert_eq!(
+            OutputSummary::from_outputs(&Outputs::default()).unwrap(),
+            None
+        );
+    }
+
+    #[test]
+    fn changes_within_limits_pass() {
+        let guard = ChangeGuard::default();
+        assert!(guard
+            .check(&outputs(20, 4, 4, 100), &outputs(24, 5, 4, 100))
+            .is_empty());
+    }
+
+    #[test]
+    fn changes_beyond_limits_are_described() {
+        let guard = ChangeGuard::default();
+        assert_eq!(
+            guard.check(&outputs(20, 4, 4, 100), &outputs(10, 4, 4, 100)),
+            vec![
+                "circuits changed from 20 to 10 (50.0%, limit 25%)",
+                "download Mbps changed from 2000 to 1000 (50.0%, limit 25%)",
+                "upload Mbps changed from 2000 to 1000 (50.0%, limit 25%)",
+            ]
+        );
+        assert_eq!(
+            guard.check(&outputs(20, 4, 4, 100), &outputs(20, 2, 4, 100)),
+            vec!["sites changed from 4 to 2 (50.0%, limit 25%)"]
+        );
+    }
+
+    #[test]
+    fn access_points_are_not_sites() {
+        let guard = ChangeGuard::default();
+        assert!(guard
+            .check(&outputs(20, 4, 4, 100), &outputs(20, 4, 0, 100))
+            .is_empty());
+    }
+
+    #[test]
+    fn unreadable_previous_outputs_are_refused() {
+        let guard = ChangeGuard::default();
+        let next = outputs(20, 4, 4, 100);
+        let mut previous = Outputs::default();
+        previous.add("network.json", "{\"Site\": ".to_string());
+        previous.add(
+            "ShapedDevices.csv",
+            next.get("ShapedDevices.csv").unwrap().into(),
+        );
+        let problems = guard.check(&previous, &next);
+        assert_eq!(problems.len(), 1);
+        assert!(
+            problems[0]
+                .starts_with("the published outputs can't be read (network.json doesn't parse"),
+            "{problems:?}"
+        );
+
+        let mut previous = Outputs::default();
+        previous.add("ShapedDevices.csv", "Circuit,Rate\nc1,10\n".to_string());
+        previous.add("network.json", "{}".to_string());
+        assert_eq!(
+            guard.check(&previous, &next),
+            vec!["the published outputs can't be read (ShapedDevices.csv has no 'Circuit ID' column)"]
+        );
+    }
+
+    #[test]
+    fn outputs_without_a_summary_skip_the_site_check() {
+        let guard = ChangeGuard::default();
+        let mut previous = outputs(20, 8, 4, 100);
+        previous.supporting.clear();
+        assert!(guard.check(&previous, &outputs(20, 4, 4, 100)).is_empty());
+    }
+
+    #[test]
+    fn guard_is_skipped_when_disabled_or_without_history() {
+        let (old, new) = (outputs(20, 4, 4, 100), outputs(1, 1, 0, 100));
+        let disabled = ChangeGuard {
+            enabled: false,
+            ..Default::default()
+        };
+        assert!(disabled.check(&old, &new).is_empty());
+        // A first run, and a network too small to guard.
+        assert!(ChangeGuard::default()
+            .check(&Outputs::default(), &new)
+            .is_empty());
+        assert!(ChangeGuard::default()
+            .check(&outputs(9, 4, 4, 100), &new)
+            .is_empty());
+    }
+}
@@ -0,0 +1,144 @@
+use crate::{Location, LqClientSite, LqSite, Outputs, RunMetrics};
+use anyhow::{Context, Result};
+use async_trait::async_trait;
+use futures::future::try_join_all;
+use std::collections::{BTreeMap, HashMap};
+use tracing::{info, info_span, Instrument};
+
+/// Everything a data source knows about the network: a flat map of sites
+/// (keyed by ID, with any known access points already attached) and the
+/// circuits to be shaped beneath them, with the locations of any sites and
+/// devices (keyed by ID) the source knows, and any supporting files it
+/// generated, such as a hierarchy report, to publish with the outputs.
+#[derive(Debug, Clone, Default)]
+pub struct Inventory {
+    pub sites: HashMap<String, LqSite>,
+    pub circuits: Vec<LqClientSite>,
+    pub locations: HashMap<String, Location>,
+    pub files: Outputs,
+}
+
+impl Inventory {
+    /// Appends another inventory to this one. Sites with the same ID are
+    /// replaced by the incoming copy.
+    pub fn extend(&mut self, other: Inventory) {
+        self.sites.extend(other.sites);
+        self.circuits.extend(other.circuits);
+        self.locations.extend(other.locations);
+        self.files.extend(other.files);
+    }
+
+    /// Prefixes every site, circuit, device and access point ID, so that
+    /// inventories from separate instances of the same system can't collide.
+    pub fn prefix_ids(&mut self, prefix: &str) {
+        let add = |id: &str| {
+            if id.is_empty() {
+                String::new()
+            } else {
+                format!("{prefix}{id}")
+            }
+        };
+        self.sites = self
+            .sites
+            .drain()
+            .map(|(id, mut site)| {
+                site.id = add(&site.id);
+                site.parent = site.parent.map(|p| add(&p));
+                site.access_points = std::mem::take(&mut site.access_points)
+                    .into_iter()
+                    .map(|(id, ap)| (add(&id), ap))
+                    .collect();
+                (add(&id), site)
+            })
+            .collect();
+        for circuit in self.circuits.iter_mut() {
+            circuit.id = add(&circuit.id);
+            for device in circuit.devices.iter_mut() {
+                device.id = add(&device.id);
+                device.parent_site_id = add(&device.parent_site_id);
+                device.access_point_id = add(&device.access_point_id);
+            }
+        }
+        self.locations = self
+            .locations
+            .drain()
+            .map(|(id, location)| (add(&id), location))
+            .collect();
+    }
+
+    /// Rewrites site parents that don't match a site ID but do match a site
+    /// name, so configuration can refer to parents by name.
+    pub fn resolve_parent_names(&mut self) {
+        let names: HashMap<String, String> = self
+            .sites
+            .values()
+            .map(|s| (s.name.clone(), s.id.clone()))
+            .collect();
+        let ids: Vec<String> = self.sites.keys().cloned().collect();
+        for site in self.sites.values_mut() {
+            if let Some(parent) = &site.parent {
+                if !ids.contains(parent) {
+                    if let Some(id) = names.get(parent) {
+                        site.parent = Some(id.clone());
+                    }
+                }
+            }
+        }
+    }
+
+    /// Adds a top-level site with the given name, unless one already exists,
+    /// to act as a common root for several sources.
+    pub fn ensure_root(&mut self, name: &str) {
+        if !self.sites.values().any(|s| s.name == name) {
+            self.sites.insert(
+                "root".to_string(),
+                LqSite {
+                    id: "root".to_string(),
+                    name: name.to_string(),
+                    parent: None,
+                    children: Vec::new(),
+                    access_points: BTreeMap::new(),
+                    download_mbps: 1_000,
+                    upload_mbps: 1_000,
+                },
+            );
+        }
+    }
+}
+
+/// A pluggable data source. Implementations fetch from their API (or files)
+/// and convert to the shared models; building the tree and writing outputs
+/// is left to the common pipeline.
+#[async_trait]
+pub trait Integration: Send + Sync {
+    /// A short name used in progress messages and reports, e.g. `"uisp"`.
+    fn name(&self) -> &str;
+
+    /// Fetches the source's current inventory, recording API timings and
+    /// rejected data in `metrics`.
+    async fn fetch_inventory(&self, metrics: &RunMetrics) -> Result<Inventory>;
+}
+
+/// Fetches every source concurrently, each in its own `source` span,
+/// returning each inventory alongside the name of the source that produced it.
+pub async fn fetch_inventories(
+    sources: &[Box<dyn Integration>],
+    metrics: &RunMetrics,
+) -> Result<Vec<(String, Inventory)>> {
+    try_join_all(sources.iter().map(|source| {
+        async move {
+            let inventory = source
+                .fetch_inventory(metrics)
+                .await
+                .with_context(|| format!("Fetching inventory from {}", source.name()))?;
+            info!(
+                sites = inventory.sites.len(),
+                circuits = inventory.circuits.len(),
+                "Fetched inventory"
+            );
+            Ok::<_, anyhow::Error>((source.name().to_string(), inventory))
+        }
+        .instrument(info_span!("source", name = source.name()))
+    }))
+    .await
+}
@@ -0,0 +1,67 @@
+//! Integration-agnostic models and output writers shared by the LibreQoS
+//! integration tools in this workspace.
+//!
+//! An [`Integration`] converts its data source into an [`Inventory`]: a flat
+//! map of [`LqSite`]s and a list of [`LqClientSite`]s (circuits) holding
+//! [`LqClientDevice`]s. The [`TopologyBuilder`] places devices on their
+//! sites' access points and assembles the site tree, [`validate`] checks the
+//! result, and the writers produce `network.json` and `ShapedDevices.csv` for
+//! LibreQoS.
+
+mod access_point;
+mod builder;
+mod capacity_plan;
+mod client_device;
+mod client_site;
+mod file_import;
+mod graph;
+mod guard;
+mod integration;
+mod load;
+mod logging;
+mod merge;
+mod metrics;
+mod naming;
+mod network_json;
+mod parentless;
+mod publish;
+mod reload;
+mod report;
+mod shaped_devices;
+mod shaper_csv;
+mod site;
+mod validation;
+
+pub use access_point::LqAccessPoint;
+pub use builder::{
+    ParentlessDevice, ParentlessReason, RootSelection, Topology, TopologyBuilder, UNPARENTED,
+};
+pub use capacity_plan::{
+    capacity_plan, write_capacity_plan, CapacityPlan, CapacityPlanConfig, PlanEntry,
+};
+pub use client_device::LqClientDevice;
+pub use client_site::LqClientSite;
+pub use file_import::{load_inventory_file, FileIntegration, ImportError};
+pub use graph::{dot_graph, mermaid_graph, write_graphs, GraphConfig};
+pub use guard::{ChangeGuard, OutputSummary};
+pub use integration::{fetch_inventories, Integration, Inventory};
+pub use load::{node_loads, node_loads_with_top, CapacityLimits, Contributor, LoadLevel, NodeLoad};
+pub use logging::{init_logging, LogConfig, LogFormat};
+pub use merge::{
+    load_id_map, merge_inventories, MergeReport, MergeRules, RateConflict, RatePolicy,
+};
+pub use metrics::{write_metrics, Counter, MetricsConfig, RunMetrics};
+pub use naming::{assign_unique_names, NodeNaming};
+pub use network_json::{forest_to_json, network_json, write_network_json, NetworkNode};
+pub use parentless::{
+    diagnose_parentless, parentless_csv, Location, ParentlessEntry, SuggestedParent,
+};
+pub use publish::{
+    generations, publish, rollback, write_atomic, OutputConfig, Outputs, PublishOutcome,
+};
+pub use reload::ReloadHook;
+pub use report::{html_report, write_report, Excluded, ReportConfig};
+pub use shaped_devices::{shaped_devices_csv, write_shaped_devices_csv, SHAPED_DEVICES_HEADER};
+pub use shaper_csv::{shaper_csv, write_shaper_csv};
+pub use site::LqSite;
+pub use validation::{validate, ValidationIssue};
@@ -0,0 +1,293 @@
+use crate::{shaped_devices::bps_to_mbps, LqClientSite, LqSite, UNPARENTED};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeSet, HashMap};
+
+/// Oversubscription ratios at which nodes are flagged in diagrams and
+/// reports.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct CapacityLimits {
+    pub warn_ratio: f64,
+    pub critical_ratio: f64,
+}
+
+impl Default for CapacityLimits {
+    fn default() -> Self {
+        Self {
+            warn_ratio: 2.0,
+            critical_ratio: 4.0,
+        }
+    }
+}
+
+/// How a node's provisioned bandwidth compares with the [`CapacityLimits`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LoadLevel {
+    Normal,
+    Warn,
+    Critical,
+}
+
+impl LoadLevel {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Normal => "normal",
+            Self::Warn => "warn",
+            Self::Critical => "critical",
+        }
+    }
+}
+
+impl CapacityLimits {
+    pub fn level(&self, load: &NodeLoad) -> LoadLevel {
+        let ratio = load.oversubscription();
+        if ratio > self.critical_ratio {
+            LoadLevel::Critical
+        } else if ratio > self.warn_ratio {
+            LoadLevel::Warn
+        } else {
+            LoadLevel::Normal
+        }
+    }
+}
+
+/// A circuit's share of a node's load.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct Contributor {
+    pub id: String,
+    pub name: String,
+    pub download_mbps: usize,
+    pub upload_mbps: usize,
+}
+
+/// The bandwidth provisioned to circuits beneath a tree node, against the
+/// node's own capacity.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct NodeLoad {
+    /// The site's ID, or the access point's key in its site's map.
+    pub id: String,
+    pub name: String,
+    /// Configured capacity, in Mbps.
+    pub download_mbps: usize,
+    pub upload_mbps: usize,
+    /// Total of the maximum rates of the circuits beneath the node, in Mbps.
+    pub provisioned_download_mbps: usize,
+    pub provisioned_upload_mbps: usize,
+    pub circuits: usize,
+    /// The circuits with the highest rates beneath the node, highest first,
+    /// if requested.
+    pub top_circuits: Vec<Contributor>,
+}
+
+impl NodeLoad {
+    /// Provisioned bandwidth as a multiple of capacity, taking the worse of
+    /// download and upload. Zero for nodes without a capacity.
+    pub fn oversubscription(&self) -> f64 {
+        let ratio = |provisioned: usize, capacity: usize| {
+            if capacity == 0 {
+                0.0
+            } else {
+                provisioned as f64 / capacity as f64
+            }
+        };
+        f64::max(
+            ratio(self.provisioned_download_mbps, self.download_mbps),
+            ratio(self.provisioned_upload_mbps, self.upload_mbps),
+        )
+    }
+}
+
+/// Each circuit's shaped (download, upload) Mbps, by device ID, so nodes
+/// can find the circuits their devices belong to.
+struct CircuitRates<'a> {
+    by_device: HashMap<&'a str, &'a str>,
+    rates: HashMap<&'a str, (usize, usize)>,
+    names: HashMap<&'a str, &'a str>,
+    /// How many top contributors to list for each node.
+    top: usize,
+}
+
+impl<'a> CircuitRates<'a> {
+    fn new(clients: &'a [LqClientSite], top: usize) -> Self {
+        let mut by_device = HashMap::new();
+        let mut rates = HashMap::new();
+        let mut names = HashMap::new();
+        for circuit in clients.iter() {
+            names.insert(circuit.id.as_str(), circuit.name.as_str());
+            let rate: &mut (usize, usize) = rates.entry(circuit.id.as_str()).or_default();
+            for device in circuit.devices.iter() {
+                by_device.insert(device.id.as_str(), circuit.id.as_str());
+                rate.0 = rate.0.max(bps_to_mbps(device.download));
+                rate.1 = rate.1.max(bps_to_mbps(device.upload));
+            }
+        }
+        Self {
+            by_device,
+            rates,
+            names,
+            top,
+        }
+    }
+
+    fn load(
+        &self,
+        id: &str,
+        name: &str,
+        capacity: (usize, usize),
+        circuits: &BTreeSet<&str>,
+    ) -> NodeLoad {
+        let (download, upload) = circuits
+            .iter()
+            .filter_map(|c| self.rates.get(c))
+            .fold((0, 0), |(d, u), r| (d + r.0, u + r.1));
+        let mut top_circuits: Vec<Contributor> = Vec::new();
+        if self.top > 0 {
+            top_circuits = circuits
+                .iter()
+                .filter_map(|c| {
+                    let (download_mbps, upload_mbps) = *self.rates.get(c)?;
+                    Some(Contributor {
+                        id: c.to_string(),
+                        name: self.names.get(c).unwrap_or(c).to_string(),
+                        download_mbps,
+                        upload_mbps,
+                    })
+                })
+                .collect();
+            top_circuits.sort_by(|a, b| {
+                (b.download_mbps, b.upload_mbps)
+                    .cmp(&(a.download_mbps, a.upload_mbps))
+                    .then_with(|| a.name.cmp(&b.name))
+            });
+            top_circuits.truncate(self.top);
+        }
+        NodeLoad {
+            id: id.to_string(),
+            name: name.to_string(),
+            download_mbps: capacity.0,
+            upload_mbps: capacity.1,
+            provisioned_download_mbps: download,
+            provisioned_upload_mbps: upload,
+            circuits: circuits.len(),
+            top_circuits,
+        }
+    }
+}
+
+/// Adds the load of `site` and everything beneath it to `sites` and
+/// `access_points`, returning the circuits found beneath it.
+fn walk<'a>(
+    site: &'a LqSite,
+    rates: &CircuitRates<'a>,
+    sites: &mut Vec<NodeLoad>,
+    access_points: &mut Vec<NodeLoad>,
+) -> BTreeSet<&'a str> {
+    let mut beneath = BTreeSet::new();
+    for (id, ap) in site
+        .access_points
+        .iter()
+        .filter(|(_, ap)| ap.name != UNPARENTED)
+    {
+        let circuits: BTreeSet<&str> = ap
+            .clients
+            .iter()
+            .filter_map(|d| rates.by_device.get(d.id.as_str()).copied())
+            .collect();
+        access_points.push(rates.load(id, &ap.name, (ap.download_mbps, ap.upload_mbps), &circuits));
+        beneath.extend(circuits);
+    }
+    for child in site.children.iter() {
+        beneath.extend(walk(child, rates, sites, access_points));
+    }
+    sites.push(rates.load(
+        &site.id,
+        &site.name,
+        (site.download_mbps, site.upload_mbps),
+        &beneath,
+    ));
+    beneath
+}
+
+/// The load on every site and access point in the tree. A site's load
+/// includes every circuit beneath it, at any depth; a circuit with devices
+/// on several nodes is counted once per node. The parentless devices' node
+/// is left out. Both lists are in tree order, children before parents.
+pub fn node_loads(roots: &[LqSite], clients: &[LqClientSite]) -> (Vec<NodeLoad>, Vec<NodeLoad>) {
+    node_loads_with_top(roots, clients, 0)
+}
+
+/// Like [`node_loads`], but lists up to `top` of the circuits with the
+/// highest rates beneath each node.
+pub fn node_loads_with_top(
+    roots: &[LqSite],
+    clients: &[LqClientSite],
+    top: usize,
+) -> (Vec<NodeLoad>, Vec<NodeLoad>) {
+    let rates = CircuitRates::new(clients, top);
+    let (mut sites, mut access_points) = (Vec::new(), Vec::new());
+    for root in roots.iter() {
+        walk(root, &rates, &mut sites, &mut access_points);
+    }
+    (sites, access_points)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{LqAccessPoint, LqClientDevice};
+    use std::collections::BTreeMap;
+
+    fn device(id: &str, mbps: usize) -> LqClientDevice {
+        LqClientDevice {
+            id: id.to_string(),
+            hostname: id.to_string(),
+            mac: String::new(),
+            model: String::new(),
+            ip: String::new(),
+            access_point_id: "ap-1".to_string(),
+            access_point_name: "Sector A".to_string(),
+            parent_site_id: "tower".to_string(),
+            parent_site_name: "Tower".to_string(),
+            upload: mbps * 1_000_000,
+            download: mbps * 1_000_000,
+            is_access_point: false,
+            is_bridge: false,
+        }
+    }
+
+    #[test]
+    fn access_points_are_identified_by_their_key() {
+        let clients = vec![LqClientSite {
+            id: "c1".to_string(),
+            name: "Circuit".to_string(),
+            download: 50_000_000,
+            upload: 50_000_000,
+            suspended: false,
+            devices: vec![device("d1", 50), device("d2", 20)],
+        }];
+        let tower = LqSite {
+            id: "tower".to_string(),
+            name: "Tower".to_string(),
+            parent: None,
+            children: Vec::new(),
+            access_points: BTreeMap::from([(
+                "ap-1".to_string(),
+                LqAccessPoint {
+                    name: "Sector A".to_string(),
+                    download_mbps: 100,
+                    upload_mbps: 100,
+                    clients: clients[0].devices.clone(),
+                },
+            )]),
+            download_mbps: 1_000,
+            upload_mbps: 1_000,
+        };
+        let (sites, access_points) = node_loads(&[tower], &clients);
+        let ap = &access_points[0];
+        assert_eq!((ap.id.as_str(), ap.name.as_str()), ("ap-1", "Sector A"));
+        // The circuit is counted once, at its highest device rate.
+        assert_eq!((ap.circuits, ap.provisioned_download_mbps), (1, 50));
+        assert_eq!((sites[0].id.as_str(), sites[0].circuits), ("tower", 1));
+    }
+}
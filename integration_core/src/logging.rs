@@ -0,0 +1,58 @@
+use anyhow::{Error, Result};
+use serde::{Deserialize, Serialize};
+use tracing_subscriber::{fmt::format::FmtSpan, EnvFilter};
+
+/// How log lines are written.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum LogFormat {
+    /// Human-readable lines.
+    #[default]
+    Text,
+    /// One JSON object per line, for log shippers.
+    Json,
+}
+
+/// Logging settings from the configuration file.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct LogConfig {
+    /// One of `error`, `warn`, `info`, `debug` or `trace`.
+    pub level: String,
+    pub format: LogFormat,
+}
+
+impl Default for LogConfig {
+    fn default() -> Self {
+        Self {
+            level: "info".to_string(),
+            format: LogFormat::Text,
+        }
+    }
+}
+
+const LEVELS: [&str; 5] = ["error", "warn", "info", "debug", "trace"];
+
+/// Sets up logging to standard output. The configured level is raised by
+/// `verbosity` steps (or lowered, if negative), and `json` forces JSON
+/// output. A `RUST_LOG` environment variable overrides the level entirely.
+/// Each pipeline stage's span logs its duration when it closes.
+pub fn init_logging(config: &LogConfig, verbosity: i8, json: bool) -> Result<()> {
+    let base = LEVELS
+        .iter()
+        .position(|l| l.eq_ignore_ascii_case(&config.level))
+        .ok_or_else(|| Error::msg(format!("Unknown log level '{}'", config.level)))?;
+    let level = (base as i8 + verbosity).clamp(0, LEVELS.len() as i8 - 1) as usize;
+    let filter =
+        EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new(LEVELS[level]));
+
+    let builder = tracing_subscriber::fmt()
+        .with_env_filter(filter)
+        .with_span_events(FmtSpan::CLOSE)
+        .with_target(false);
+    let result = if json || config.format == LogFormat::Json {
+        builder.json().try_init()
+    } else {
+        builder.try_init()
+    };
+    result.map_err(|e| Error::msg(format!("Unable to set up logging: {e}")))
+}
@@ -0,0 +1,706 @@
+use crate::{Inventory, LqClientSite, LqSite};
+use anyhow::{Error, Result};
+use serde::{Deserialize, Serialize};
+use std::{collections::HashMap, fs, path::Path};
+
+/// How to resolve a rate that differs between sources.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Default)]
+pub enum RatePolicy {
+    /// Keep the rate from the first source listed.
+    #[default]
+    First,
+    /// Use the highest rate reported by any source.
+    Highest,
+    /// Use the lowest rate reported by any source.
+    Lowest,
+}
+
+impl RatePolicy {
+    fn resolve(&self, existing: usize, incoming: usize) -> usize {
+        match self {
+            Self::First => existing,
+            Self::Highest => existing.max(incoming),
+            Self::Lowest => existing.min(incoming),
+        }
+    }
+}
+
+/// Rules for matching sites across sources when merging inventories.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct MergeRules {
+    /// Optional CSV file of `Source,Source ID,Canonical ID` rows, mapping a
+    /// source's site and circuit IDs onto the IDs used by another source.
+    pub id_map_file: Option<String>,
+    /// Treat sites with the same name (ignoring case) as the same site when
+    /// the ID map doesn't say otherwise. Off by default, as separate systems
+    /// (such as two UISP instances) often reuse names like "Office".
+    pub match_by_name: bool,
+    /// Names of sites that are matched across sources even without
+    /// `match_by_name`, such as a root site every source shares.
+    pub match_names: Vec<String>,
+    pub rate_policy: RatePolicy,
+}
+
+impl Default for MergeRules {
+    fn default() -> Self {
+        Self {
+            id_map_file: None,
+            match_by_name: false,
+            match_names: Vec::new(),
+            rate_policy: RatePolicy::First,
+        }
+    }
+}
+
+/// A rate that differed between two sources, and the value that was kept.
+#[derive(Debug, Clone)]
+pub struct RateConflict {
+    pub kind: &'static str,
+    pub id: String,
+    pub name: String,
+    pub sources: (String, String),
+    pub values: (usize, usize),
+    pub resolved: usize,
+}
+
+/// What happened during a merge: which sources each site and circuit came
+/// from, and where rates disagreed.
+#[derive(Debug, Clone, Default)]
+pub struct MergeReport {
+    /// (kind, canonical ID, name, sources)
+    pub entities: Vec<(&'static str, String, String, Vec<String>)>,
+    pub conflicts: Vec<RateConflict>,
+}
+
+impl MergeReport {
+    /// Sites and circuits that only one source knows about.
+    pub fn single_source(
+        &self,
+    ) -> impl Iterator<Item = &(&'static str, String, String, Vec<String>)> {
+        self.entities.iter().filter(|e| e.3.len() == 1)
+    }
+
+    /// Renders the report as `MergeReport.csv`.
+    pub fn to_csv(&self) -> Result<String> {
+        let mut writer = csv::Writer::from_writer(Vec::new());
+        writer.write_record(["Kind", "ID", "Name", "Sources", "Note"])?;
+        for (kind, id, name, sources) in self.single_source() {
+            writer.write_record([
+                kind,
+                id.as_str(),
+                name.as_str(),
+                &sources.join(" "),
+                "Only in one source",
+            ])?;
+        }
+        for c in self.conflicts.iter() {
+            writer.write_record([
+                c.kind,
+                &c.id,
+                &c.name,
+                &format!("{} {}", c.sources.0, c.sources.1),
+                &format!(
+                    "Rate conflict: {} vs {} - using {}",
+                    c.values.0, c.values.1, c.resolved
+                ),
+            ])?;
+        }
+        Ok(String::from_utf8(writer.into_inner()?)?)
+    }
+}
+
+/// Loads an ID map: `Source,Source ID,Canonical ID` with a header row, and
+/// optional `#` comment lines. Returns a map from (source, source ID) to
+/// canonical ID.
+pub fn load_id_map<P: AsRef<Path>>(path: P) -> Result<HashMap<(String, String), String>> {
+    parse_id_map(&fs::read_to_string(path.as_ref())?)
+        .map_err(|e| Error::msg(format!("{}:{e}", path.as_ref().display())))
+}
+
+fn parse_id_map(data: &str) -> Result<HashMap<(String, String), String>> {
+    let mut reader = csv::ReaderBuilder::new()
+        .comment(Some(b'#'))
+        .flexible(true)
+        .trim(csv::Trim::All)
+        .from_reader(data.as_bytes());
+    let mut result = HashMap::new();
+    for record in reader.records() {
+        let record = record?;
+        let line = record.position().map(|p| p.line()).unwrap_or_default();
+        match (record.get(0), record.get(1), record.get(2)) {
+            (Some(source), Some(id), Some(canonical))
+                if !source.is_empty() && !id.is_empty() && !canonical.is_empty() =>
+            {
+                result.insert((source.to_string(), id.to_string()), canonical.to_string());
+            }
+            _ => {
+                return Err(Error::msg(format!(
+                    "{line}: expected Source,Source ID,Canonical ID"
+                )))
+            }
+        }
+    }
+    Ok(result)
+}
+
+/// Merges inventories from several sources into one, in priority order.
+///
+/// Each site is given a canonical ID: from the ID map if listed, otherwise
+/// the ID of an already-merged site with the same name (if `match_by_name`),
+/// otherwise its own ID (prefixed with the source name if that ID is already
+/// taken by another source). Circuits are given canonical IDs the same way,
+/// apart from matching by name. Parents and device parent sites are
+/// rewritten to the canonical IDs. Matched sites share access points, and
+/// matched circuits share devices; differing rates are resolved by the rate
+/// policy and recorded in the report.
+pub fn merge_inventories(
+    inventories: Vec<(String, Inventory)>,
+    rules: &MergeRules,
+) -> Result<(Inventory, MergeReport)> {
+    let id_map = match &rules.id_map_file {
+        Some(path) => load_id_map(path)?,
+        None => HashMap::new(),
+    };
+
+    let mut merged = Inventory::default();
+    let mut report = MergeReport::default();
+    let mut site_sources = HashMap::<String, Vec<String>>::new();
+    let mut circuit_sources = HashMap::<String, Vec<String>>::new();
+    let mut names = HashMap::<String, (String, String)>::new();
+    let mut circuit_index = HashMap::<String, usize>::new();
+    let match_names: Vec<String> = rules.match_names.iter().map(|n| n.to_lowercase()).collect();
+
+    for (source, inventory) in inventories {
+        // Assign canonical IDs first, so parents can refer to any site.
+        let mut site_ids: Vec<&String> = inventory.sites.keys().collect();
+        site_ids.sort();
+        let mut remap = HashMap::<String, String>::new();
+        for id in site_ids {
+            let site = &inventory.sites[id];
+            let canonical = if let Some(mapped) = id_map.get(&(source.clone(), id.clone())) {
+                mapped.clone()
+            } else if let Some((existing, _)) = names
+                .get(&site.name.to_lowercase())
+                .filter(|_| rules.match_by_name || match_names.contains(&site.name.to_lowercase()))
+                .filter(|(_, from)| *from != source)
+            {
+                existing.clone()
+            } else if merged.sites.contains_key(id) {
+                format!("{source}:{id}")
+            } else {
+                id.clone()
+            };
+            remap.insert(id.clone(), canonical);
+        }
+        merged.locations.extend(
+            inventory
+                .locations
+                .into_iter()
+                .map(|(id, location)| (remap.get(&id).cloned().unwrap_or(id), location)),
+        );
+        merged.files.extend(inventory.files);
+
+        let mut sites: Vec<LqSite> = inventory.sites.into_values().collect();
+        sites.sort_by(|a, b| a.id.cmp(&b.id));
+        for mut site in sites {
+            site.id = remap[&site.id].clone();
+            site.parent = site.parent.map(|p| remap.get(&p).cloned().unwrap_or(p));
+            let sources = site_sources.entry(site.id.clone()).or_default();
+            if let Some(existing) = merged.sites.get_mut(&site.id) {
+                merge_site(
+                    existing,
+                    site,
+                    (&sources[0], &source),
+                    rules.rate_policy,
+                    &mut report,
+                );
+            } else {
+                names.insert(site.name.to_lowercase(), (site.id.clone(), source.clone()));
+                merged.sites.insert(site.id.clone(), site);
+            }
+            if !sources.contains(&source) {
+                sources.push(source.clone());
+            }
+        }
+
+        for mut circuit in inventory.circuits {
+            circuit.id = if let Some(mapped) = id_map.get(&(source.clone(), circuit.id.clone())) {
+                mapped.clone()
+            } else if circuit_sources
+                .get(&circuit.id)
+                .is_some_and(|from| !from.contains(&source))
+            {
+                format!("{source}:{}", circuit.id)
+            } else {
+                circuit.id
+            };
+            for device in circuit.devices.iter_mut() {
+                if let Some(id) = remap.get(&device.parent_site_id) {
+                    device.parent_site_id = id.clone();
+                }
+            }
+            let sources = circuit_sources.entry(circuit.id.clone()).or_default();
+            if let Some(idx) = circuit_index.get(&circuit.id) {
+                let existing = &mut merged.circuits[*idx];
+                merge_circuit(
+                    existing,
+                    circuit,
+                    (&sources[0], &source),
+                    rules.rate_policy,
+                    &mut report,
+                );
+            } else {
+                circuit_index.insert(circuit.id.clone(), merged.circuits.len());
+                merged.circuits.push(circuit);
+            }
+            if !sources.contains(&source) {
+                sources.push(source.clone());
+            }
+        }
+    }
+
+    let mut entities: Vec<_> = merged
+        .sites
+        .values()
+        .map(|s| {
+            (
+                "site",
+                s.id.clone(),
+                s.name.clone(),
+                site_sources[&s.id].clone(),
+            )
+        })
+        .chain(merged.circuits.iter().map(|c| {
+            (
+                "circuit",
+                c.id.clone(),
+                c.name.clone(),
+                circuit_sources[&c.id].clone(),
+            )
+        }))
+        .collect();
+    entities.sort();
+    report.entities = entities;
+
+    Ok((merged, report))
+}
+
+/// The entity being merged, for recording conflicts.
+struct Matched<'a> {
+    id: &'a str,
+    name: &'a str,
+    sources: (&'a str, &'a str),
+}
+
+impl MergeReport {
+    fn resolve(
+        &mut self,
+        policy: RatePolicy,
+        kind: &'static str,
+        matched: &Matched,
+        existing: usize,
+        incoming: usize,
+    ) -> usize {
+        let resolved = policy.resolve(existing, incoming);
+        if existing != incoming {
+            self.conflicts.push(RateConflict {
+                kind,
+                id: matched.id.to_string(),
+                name: matched.name.to_string(),
+                sources: (matched.sources.0.to_string(), matched.sources.1.to_string()),
+                values: (existing, incoming),
+                resolved,
+            });
+        }
+        resolved
+    }
+}
+
+fn merge_site(
+    existing: &mut LqSite,
+    incoming: LqSite,
+    sources: (&str, &str),
+    policy: RatePolicy,
+    report: &mut MergeReport,
+) {
+    let site = Matched {
+        id: &existing.id,
+        name: &existing.name,
+        sources,
+    };
+    let download = report.resolve(
+        policy,
+        "site download",
+        &site,
+        existing.download_mbps,
+        incoming.download_mbps,
+    );
+    let upload = report.resolve(
+        policy,
+        "site upload",
+        &site,
+        existing.upload_mbps,
+        incoming.upload_mbps,
+    );
+    existing.download_mbps = download;
+    existing.upload_mbps = upload;
+    if existing.parent.is_none() {
+        existing.parent = incoming.parent;
+    }
+    for (id, ap) in incoming.access_points {
+        if let Some(current) = existing.access_points.get_mut(&id) {
+            let name = current.name.clone();
+            let matched = Matched {
+                id: &id,
+                name: &name,
+                sources,
+            };
+            current.download_mbps = report.resolve(
+                policy,
+                "access point download",
+                &matched,
+                current.download_mbps,
+                ap.download_mbps,
+            );
+            current.upload_mbps = report.resolve(
+                policy,
+                "access point upload",
+                &matched,
+                current.upload_mbps,
+                ap.upload_mbps,
+            );
+            current.clients.extend(ap.clients);
+        } else {
+            existing.access_points.insert(id, ap);
+        }
+    }
+}
+
+fn merge_circuit(
+    existing: &mut LqClientSite,
+    incoming: LqClientSite,
+    sources: (&str, &str),
+    policy: RatePolicy,
+    report: &mut MergeReport,
+) {
+    let circuit = Matched {
+        id: &existing.id,
+        name: &existing.name,
+        sources,
+    };
+    let download = report.resolve(
+        policy,
+        "circuit download",
+        &circuit,
+        existing.download,
+        incoming.download,
+    );
+    let upload = report.resolve(
+        policy,
+        "circuit upload",
+        &circuit,
+        existing.upload,
+        incoming.upload,
+    );
+    existing.download = download;
+    existing.upload = upload;
+    existing.suspended |= incoming.suspended;
+    for device in incoming.devices {
+        if !existing.devices.iter().any(|d| d.id == device.id) {
+            existing.devices.push(device);
+        }
+    }
+    for device in existing.devices.iter_mut() {
+        device.download = download;
+        device.upload = upload;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::LqClientDevice;
+    use std::collections::BTreeMap;
+
+    fn site(id: &str, name: &str, parent: Option<&str>, mbps: usize) -> LqSite {
+        LqSite {
+            id: id.to_string(),
+            name: name.to_string(),
+            parent: parent.map(|p| p.to_string()),
+            children: Vec::new(),
+            access_points: BTreeMap::new(),
+            download_mbps: mbps,
+            upload_mbps: mbps,
+        }
+    }
+
+    fn circuit(id: &str, device: &str, site_id: &str, bps: usize) -> LqClientSite {
+        LqClientSite {
+            id: id.to_string(),
+            name: format!("Circuit {id}"),
+            download: bps,
+            upload: bps,
+            suspended: false,
+            devices: vec![LqClientDevice {
+                id: device.to_string(),
+                hostname: device.to_string(),
+                mac: String::new(),
+                model: String::new(),
+                ip: String::new(),
+                access_point_id: String::new(),
+                access_point_name: String::new(),
+                parent_site_id: site_id.to_string(),
+                parent_site_name: String::new(),
+                upload: bps,
+                download: bps,
+                is_access_point: false,
+                is_bridge: false,
+            }],
+        }
+    }
+
+    fn inventory(sites: Vec<LqSite>, circuits: Vec<LqClientSite>) -> Inventory {
+        Inventory {
+            sites: sites.into_iter().map(|s| (s.id.clone(), s)).collect(),
+            circuits,
+            ..Default::default()
+        }
+    }
+
+    fn rules(match_by_name: bool, rate_policy: RatePolicy) -> MergeRules {
+        MergeRules {
+            id_map_file: None,
+            match_by_name,
+            match_names: Vec::new(),
+            rate_policy,
+        }
+    }
+
+    #[test]
+    fn id_map_is_parsed_as_csv() {
+        let map = parse_id_map(
+            "Source,Source ID,Canonical ID\n# comment\n\"uisp\",\"a,b\", main \n\nsplynx,7,main\n",
+        )
+        .unwrap();
+        assert_eq!(map.len(), 2);
+        assert_eq!(map[&("uisp".to_string(), "a,b".to_string())], "main");
+        assert_eq!(map[&("splynx".to_string(), "7".to_string())], "main");
+
+        let error = parse_id_map("Source,Source ID,Canonical ID\nuisp,a,b\nuisp,c\n").unwrap_err();
+        assert!(error.to_string().starts_with("3:"), "{error}");
+    }
+
+    #[test]
+    fn colliding_site_ids_are_prefixed() {
+        let a = inventory(vec![site("1", "North", None, 100)], Vec::new());
+        let b = inventory(
+            vec![
+                site("1", "South", None, 100),
+                site("2", "Hill", Some("1"), 50),
+            ],
+            vec![circuit("c", "d", "1", 1)],
+        );
+        let (merged, _) = merge_inventories(
+            vec![("a".to_string(), a), ("b".to_string(), b)],
+            &rules(false, RatePolicy::First),
+        )
+        .unwrap();
+        assert_eq!(merged.sites["1"].name, "North");
+        assert_eq!(merged.sites["b:1"].name, "South");
+        assert_eq!(merged.sites["2"].parent.as_deref(), Some("b:1"));
+        assert_eq!(merged.circuits[0].devices[0].parent_site_id, "b:1");
+    }
+
+    #[test]
+    fn sites_match_by_name_only_when_enabled() {
+        let sources = || {
+            vec![
+                (
+                    "a".to_string(),
+                    inventory(vec![site("1", "Main Tower", None, 100)], Vec::new()),
+                ),
+                (
+                    "b".to_string(),
+                    inventory(vec![site("9", "main tower", None, 200)], Vec::new()),
+                ),
+            ]
+        };
+        let (merged, report) =
+            merge_inventories(sources(), &rules(true, RatePolicy::Highest)).unwrap();
+        assert_eq!(merged.sites.len(), 1);
+        assert_eq!(merged.sites["1"].download_mbps, 200);
+        assert_eq!(report.conflicts.len(), 2);
+
+        let (merged, report) =
+            merge_inventories(sources(), &rules(false, RatePolicy::Highest)).unwrap();
+        assert_eq!(merged.sites.len(), 2);
+        assert!(report.conflicts.is_empty());
+        assert_eq!(report.single_source().count(), 2);
+    }
+
+    #[test]
+    fn report_names_access_points_and_escapes_fields() {
+        let with_ap = |mbps: usize| {
+            let mut tower = site("1", "Tower, \"Main\"", None, 100);
+            tower.access_points.insert(
+                "ap-1".to_string(),
+                crate::LqAccessPoint {
+                    name: "Sector A".to_string(),
+                    download_mbps: mbps,
+                    upload_mbps: 10,
+                    clients: Vec::new(),
+                },
+            );
+            inventory(vec![tower], Vec::new())
+        };
+        let (_, report) = merge_inventories(
+            vec![
+                ("a".to_string(), with_ap(100)),
+                ("b".to_string(), with_ap(200)),
+                (
+                    "c".to_string(),
+                    inventory(vec![site("9", "Hill, East", None, 50)], Vec::new()),
+                ),
+            ],
+            &rules(true, RatePolicy::Highest),
+        )
+        .unwrap();
+        let conflict = &report.conflicts[0];
+        assert_eq!(
+            (conflict.kind, conflict.id.as_str(), conflict.name.as_str()),
+            ("access point download", "ap-1", "Sector A")
+        );
+
+        let csv = report.to_csv().unwrap();
+        let mut reader = csv::Reader::from_reader(csv.as_bytes());
+        let rows: Vec<Vec<String>> = reader
+            .records()
+            .map(|r| r.unwrap().iter().map(|f| f.to_string()).collect())
+            .collect();
+        assert_eq!(
+            rows,
+            vec![
+                vec!["site", "9", "Hill, East", "c", "Only in one source"],
+                vec![
+                    "access point download",
+                    "ap-1",
+                    "Sector A",
+                    "a b",
+                    "Rate conflict: 100 vs 200 - using 200"
+                ],
+            ]
+        );
+    }
+
+    #[test]
+    fn colliding_circuit_ids_are_kept_apart() {
+        let a = inventory(
+            vec![site("s", "Site", None, 100)],
+            vec![circuit("123", "d1", "s", 10)],
+        );
+        let b = inventory(Vec::new(), vec![circuit("123", "d2", "s", 20)]);
+        let (merged, report) = merge_inventories(
+            vec![("a".to_string(), a), ("b".to_string(), b)],
+            &rules(false, RatePolicy::First),
+        )
+        .unwrap();
+        let ids: Vec<&str> = merged.circuits.iter().map(|c| c.id.as_str()).collect();
+        assert_eq!(ids, vec!["123", "b:123"]);
+        assert_eq!(merged.circuits[0].download, 10);
+        assert_eq!(merged.circuits[1].download, 20);
+        assert!(report.conflicts.is_empty());
+    }
+
+    #[test]
+    fn id_map_matches_sites_and_circuits() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let map = dir.path().join("IdMap.csv");
+        fs::write(
+            &map,
+            "Source,Source ID,Canonical ID\nb,tower-b,tower-a\nb,cust-b,cust-a\n",
+        )
+        .unwrap();
+        let a = inventory(
+            vec![site("tower-a", "Tower", None, 100)],
+            vec![circuit("cust-a", "d1", "tower-a", 10)],
+        );
+        let b = inventory(
+            vec![site("tower-b", "Tower B", None, 300)],
+            vec![circuit("cust-b", "d2", "tower-b", 30)],
+        );
+        let rules = MergeRules {
+            id_map_file: Some(map.to_string_lossy().to_string()),
+            rate_policy: RatePolicy::Lowest,
+            ..MergeRules::default()
+        };
+        let (merged, report) =
+            merge_inventories(vec![("a".to_string(), a), ("b".to_string(), b)], &rules).unwrap();
+        assert_eq!(merged.sites.len(), 1);
+        assert_eq!(merged.sites["tower-a"].download_mbps, 100);
+        assert_eq!(merged.circuits.len(), 1);
+        let circuit = &merged.circuits[0];
+        assert_eq!(circuit.download, 10);
+        let devices: Vec<(&str, &str, usize)> = circuit
+            .devices
+            .iter()
+            .map(|d| (d.id.as_str(), d.parent_site_id.as_str(), d.download))
+            .collect();
+        assert_eq!(devices, vec![("d1", "tower-a", 10), ("d2", "tower-a", 10)]);
+        let kinds: Vec<&str> = report.conflicts.iter().map(|c| c.kind).collect();
+        assert_eq!(
+            kinds,
+            vec![
+                "site download",
+                "site upload",
+                "circuit download",
+                "circuit upload"
+            ]
+        );
+        assert!(report.single_source().next().is_none());
+    }
+
+    #[test]
+    fn only_shared_names_match_by_default() {
+        let instance = |prefix: &str| {
+            inventory(
+                vec![
+                    site(&format!("{prefix}:top"), "Root", None, 1_000),
+                    site(
+                        &format!("{prefix}:office"),
+                        "Office",
+                        Some(&format!("{prefix}:top")),
+                        100,
+                    ),
+                ],
+                Vec::new(),
+            )
+        };
+        let rules = MergeRules {
+            match_names: vec!["root".to_string()],
+            ..MergeRules::default()
+        };
+        let (merged, _) = merge_inventories(
+            vec![
+                ("east".to_string(), instance("east")),
+                ("west".to_string(), instance("west")),
+            ],
+            &rules,
+        )
+        .unwrap();
+        let mut sites: Vec<(&str, &str, Option<&str>)> = merged
+            .sites
+            .values()
+            .map(|s| (s.id.as_str(), s.name.as_str(), s.parent.as_deref()))
+            .collect();
+        sites.sort();
+        assert_eq!(
+            sites,
+            vec![
+                ("east:office", "Office", Some("east:top")),
+                ("east:top", "Root", None),
+                ("west:office", "Office", Some("east:top")),
+            ]
+        );
+    }
+}
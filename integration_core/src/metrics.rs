@@ -0,0 +1,317 @@
+use crate::{node_loads, write_atomic, LqClientSite, LqSite, UNPARENTED};
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::BTreeMap,
+    fmt::Write,
+    fs,
+    future::Future,
+    sync::Mutex,
+    time::{Instant, SystemTime, UNIX_EPOCH},
+};
+
+/// Where run metrics are written.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct MetricsConfig {
+    /// Path of a Prometheus text file, such as
+    /// `/var/lib/node_exporter/textfile_collector/libreqos_integration.prom`.
+    /// Nothing is written if unset.
+    pub textfile: Option<String>,
+}
+
+/// Problems found while converting source data, reported as metrics and in
+/// the run report.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Counter {
+    /// Circuits left out because they have no usable rate or address.
+    RejectedCircuits,
+    /// Client sites with several uplinks that couldn't be placed.
+    UnresolvedMultihomedSites,
+}
+
+impl Counter {
+    const ALL: [Counter; 2] = [
+        Counter::RejectedCircuits,
+        Counter::UnresolvedMultihomedSites,
+    ];
+
+    fn metric(self) -> (&'static str, &'static str) {
+        match self {
+            Self::RejectedCircuits => (
+                "rejected_circuits",
+                "Circuits left out because they have no usable rate or address.",
+            ),
+            Self::UnresolvedMultihomedSites => (
+                "unresolved_multihomed_sites",
+                "Client sites with several uplinks that couldn't be placed.",
+            ),
+        }
+    }
+}
+
+/// Measurements taken during the run, before the topology exists.
+#[derive(Default)]
+struct Recorded {
+    /// (source, endpoint) -> seconds.
+    fetches: BTreeMap<(String, String), f64>,
+    /// What each counter was recorded for, such as site names.
+    counters: BTreeMap<Counter, Vec<String>>,
+}
+
+/// Collects fetch timings and counters over one run. Created at the start
+/// of the run and passed to the sources, then to the metrics and report
+/// writers. Sources fetch concurrently, so recording only needs `&self`.
+#[derive(Default)]
+pub struct RunMetrics {
+    recorded: Mutex<Recorded>,
+}
+
+impl RunMetrics {
+    /// Awaits an API request, recording how long it took under the source
+    /// and endpoint names.
+    pub async fn timed_fetch<F: Future>(
+        &self,
+        source: &str,
+        endpoint: &str,
+        request: F,
+    ) -> F::Output {
+        let start = Instant::now();
+        let result = request.await;
+        if let Ok(mut recorded) = self.recorded.lock() {
+            recorded.fetches.insert(
+                (source.to_string(), endpoint.to_string()),
+                start.elapsed().as_secs_f64(),
+            );
+        }
+        result
+    }
+
+    /// Adds one to a counter, noting what it was for.
+    pub fn record(&self, counter: Counter, subject: &str) {
+        if let Ok(mut recorded) = self.recorded.lock() {
+            recorded
+                .counters
+                .entry(counter)
+                .or_default()
+                .push(subject.to_string());
+        }
+    }
+
+    /// Everything a counter has been recorded for, in order.
+    pub fn recorded(&self, counter: Counter) -> Vec<String> {
+        self.recorded
+            .lock()
+            .ok()
+            .and_then(|r| r.counters.get(&counter).cloned())
+            .unwrap_or_default()
+    }
+}
+
+fn escape(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+/// Accumulates metrics in the Prometheus text format.
+#[derive(Default)]
+struct Exposition(String);
+
+impl Exposition {
+    fn family(&mut self, name: &str, help: &str) {
+        let _ = writeln!(self.0, "# HELP libreqos_integration_{name} {help}");
+        let _ = writeln!(self.0, "# TYPE libreqos_integration_{name} gauge");
+    }
+
+    fn sample(&mut self, name: &str, labels: &[(&str, &str)], value: f64) {
+        let labels: Vec<String> = labels
+            .iter()
+            .map(|(k, v)| format!("{k}=\"{}\"", escape(v)))
+            .collect();
+        let labels = if labels.is_empty() {
+            String::new()
+        } else {
+            format!("{{{}}}", labels.join(","))
+        };
+        let _ = writeln!(self.0, "libreqos_integration_{name}{labels} {value}");
+    }
+
+    fn gauge(&mut self, name: &str, help: &str, value: f64) {
+        self.family(name, help);
+        self.sample(name, &[], value);
+    }
+}
+
+/// The last success time in a previously written metrics file, so that a
+/// failed run doesn't lose it.
+fn previous_success(path: &str) -> Option<f64> {
+    let text = fs::read_to_string(path).ok()?;
+    text.lines()
+        .find_map(|line| line.strip_prefix("libreqos_integration_last_success_timestamp_seconds "))
+        .and_then(|value| value.trim().parse().ok())
+}
+
+/// Renders the run's metrics. `topology` is the built tree and circuits for
+/// a successful run, or `None` if the run failed, in which case only the
+/// fetch timings and counters gathered so far are included.
+fn metrics_text(
+    metrics: &RunMetrics,
+    topology: Option<(&[LqSite], &[LqClientSite])>,
+    last_success: Option<f64>,
+) -> String {
+    let mut out = Exposition::default();
+    let recorded = metrics.recorded.lock().ok();
+
+    out.family(
+        "fetch_duration_seconds",
+        "Time taken by each API request, by source and endpoint.",
+    );
+    if let Some(recorded) = recorded.as_ref() {
+        for ((source, endpoint), seconds) in recorded.fetches.iter() {
+            out.sample(
+                "fetch_duration_seconds",
+                &[("source", source), ("endpoint", endpoint)],
+                *seconds,
+            );
+        }
+    }
+    for counter in Counter::ALL {
+        let (name, help) = counter.metric();
+        let value = recorded
+            .as_ref()
+            .and_then(|r| r.counters.get(&counter).map(|s| s.len()))
+            .unwrap_or(0);
+        out.gauge(name, help, value as f64);
+    }
+
+    out.gauge(
+        "last_run_success",
+        "Whether the last run published (or confirmed) its outputs.",
+        if topology.is_some() { 1.0 } else { 0.0 },
+    );
+    if let Some(time) = last_success {
+        out.gauge(
+            "last_success_timestamp_seconds",
+            "Unix time of the last successful run.",
+            time,
+        );
+    }
+
+    let Some((roots, clients)) = topology else {
+        return out.0;
+    };
+    let (mut loads, access_points) = node_loads(roots, clients);
+    let devices: Vec<_> = clients.iter().flat_map(|c| c.devices.iter()).collect();
+    out.gauge("sites", "Sites in the network tree.", loads.len() as f64);
+    out.gauge(
+        "access_points",
+        "Access points in the network tree.",
+        access_points.len() as f64,
+    );
+    out.gauge("circuits", "Circuits being shaped.", clients.len() as f64);
+    out.gauge("devices", "Devices being shaped.", devices.len() as f64);
+    out.gauge(
+        "parentless_devices",
+        "Devices that couldn't be placed in the tree.",
+        devices
+            .iter()
+            .filter(|d| d.access_point_name == UNPARENTED)
+            .count() as f64,
+    );
+
+    loads.sort_by(|a, b| a.name.cmp(&b.name).then_with(|| a.id.cmp(&b.id)));
+    out.family(
+        "site_capacity_mbps",
+        "Each site's configured capacity, by direction.",
+    );
+    for load in loads.iter() {
+        for (direction, mbps) in [("down", load.download_mbps), ("up", load.upload_mbps)] {
+            let labels = [
+                ("site", load.name.as_str()),
+                ("site_id", &load.id),
+                ("direction", direction),
+            ];
+            out.sample("site_capacity_mbps", &labels, mbps as f64);
+        }
+    }
+    out.family(
+        "site_provisioned_mbps",
+        "Total maximum rate of the circuits beneath each site, by direction.",
+    );
+    for load in loads.iter() {
+        for (direction, mbps) in [
+            ("down", load.provisioned_download_mbps),
+            ("up", load.provisioned_upload_mbps),
+        ] {
+            let labels = [
+                ("site", load.name.as_str()),
+                ("site_id", &load.id),
+                ("direction", direction),
+            ];
+            out.sample("site_provisioned_mbps", &labels, mbps as f64);
+        }
+    }
+    out.0
+}
+
+/// Writes the run's metrics to the configured text file, if any, replacing
+/// it atomically so the collector never reads a partial file. A successful
+/// run (`topology` is `Some`) updates the last success time; a failed run
+/// keeps the previous one.
+pub fn write_metrics(
+    config: &MetricsConfig,
+    metrics: &RunMetrics,
+    topology: Option<(&[LqSite], &[LqClientSite])>,
+) -> Result<()> {
+    let Some(path) = &config.textfile else {
+        return Ok(());
+    };
+    let last_success = if topology.is_some() {
+        SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .ok()
+            .map(|d| d.as_secs() as f64)
+    } else {
+        previous_success(path)
+    };
+    write_atomic(
+        path,
+        metrics_text(metrics, topology, last_success).as_bytes(),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn each_run_records_its_own_metrics() {
+        let first = RunMetrics::default();
+        first.record(Counter::RejectedCircuits, "Site A");
+        futures::executor::block_on(first.timed_fetch("uisp", "sites", async {}));
+
+        let second = RunMetrics::default();
+        second.record(Counter::UnresolvedMultihomedSites, "Site B");
+
+        assert_eq!(first.recorded(Counter::RejectedCircuits), vec!["Site A"]);
+        assert!(first
+            .recorded(Counter::UnresolvedMultihomedSites)
+            .is_empty());
+        assert!(second.recorded(Counter::RejectedCircuits).is_empty());
+
+        let text = metrics_text(&first, None, Some(1_700_000_000.0));
+        assert!(text.contains(
+            "libreqos_integration_fetch_duration_seconds{source=\"uisp\",endpoint=\"sites\"}"
+        ));
+        assert!(text.contains("libreqos_integration_rejected_circuits 1\n"));
+        assert!(text.contains("libreqos_integration_unresolved_multihomed_sites 0\n"));
+        assert!(text.contains("libreqos_integration_last_run_success 0\n"));
+        assert!(text.contains("libreqos_integration_last_success_timestamp_seconds 1700000000\n"));
+
+        let text = metrics_text(&second, None, None);
+        assert!(!text.contains("source=\"uisp\""));
+        assert!(text.contains("libreqos_integration_unresolved_multihomed_sites 1\n"));
+    }
+}
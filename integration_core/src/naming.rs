@@ -0,0 +1,251 @@
+use crate::{LqClientSite, LqSite, UNPARENTED};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap, HashSet};
+
+/// How tree nodes are named in the output.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct NodeNaming {
+    /// Template for access point node names. `{site}` is replaced by the
+    /// site's (unique) name and `{ap}` by the access point's name, such as
+    /// `"{site} / {ap}"`.
+    pub access_point: String,
+}
+
+impl Default for NodeNaming {
+    fn default() -> Self {
+        Self {
+            access_point: "{ap}".to_string(),
+        }
+    }
+}
+
+/// Hands out names, suffixing repeats with " (2)", " (3)" and so on.
+struct UniqueNames(HashSet<String>);
+
+impl UniqueNames {
+    fn claim(&mut self, base: &str) -> String {
+        let mut name = base.to_string();
+        let mut n = 1;
+        while self.0.contains(&name) {
+            n += 1;
+            name = format!("{base} ({n})");
+        }
+        self.0.insert(name.clone());
+        name
+    }
+}
+
+fn name_sites(site: &mut LqSite, names: &mut UniqueNames) {
+    site.name = names.claim(&site.name);
+    for child in site.children.iter_mut() {
+        name_sites(child, names);
+    }
+}
+
+/// Renames a site's access points, recording `(site ID, access point ID) ->
+/// new name`.
+/// The renamed access points are collected into a new map, so a new name
+/// can't replace an access point that hasn't been renamed yet.
+fn name_access_points(
+    site: &mut LqSite,
+    naming: &NodeNaming,
+    names: &mut UniqueNames,
+    renamed: &mut HashMap<(String, String), String>,
+) {
+    let (unparented, access_points): (BTreeMap<_, _>, BTreeMap<_, _>) =
+        std::mem::take(&mut site.access_points)
+            .into_iter()
+            .partition(|(_, ap)| ap.name == UNPARENTED);
+    // The parentless devices' node keeps its key, which no name may reuse.
+    names.0.extend(unparented.keys().cloned());
+    site.access_points = unparented;
+    for (id, mut ap) in access_points {
+        let base = naming
+            .access_point
+            .replace("{site}", &site.name)
+            .replace("{ap}", &ap.name);
+        let name = names.claim(&base);
+        for client in ap.clients.iter_mut() {
+            client.access_point_name = name.clone();
+        }
+        renamed.insert((site.id.clone(), id), name.clone());
+        ap.name = name.clone();
+        site.access_points.insert(name, ap);
+    }
+    for child in site.children.iter_mut() {
+        name_access_points(child, naming, names, renamed);
+    }
+}
+
+/// Makes every site and access point name in the tree unique, so nodes
+/// don't collide in `network.json`. Sites are named first, walking the tree
+/// from each root with siblings in name order, then access points, using the
+/// naming template. Repeated names get a numeric suffix, and nothing but the
+/// parentless devices' node may be called [`UNPARENTED`].
+///
+/// Each client device's `access_point_name` is set to the final name of the
+/// node it's shaped beneath, so [`LqClientDevice::parent_node`] agrees with
+/// the tree in every output.
+///
+/// [`LqClientDevice::parent_node`]: crate::LqClientDevice::parent_node
+pub fn assign_unique_names(
+    roots: &mut [LqSite],
+    clients: &mut [LqClientSite],
+    naming: &NodeNaming,
+) {
+    let mut names = UniqueNames(HashSet::from([UNPARENTED.to_string()]));
+    for root in roots.iter_mut() {
+        name_sites(root, &mut names);
+    }
+    let mut renamed = HashMap::new();
+    for root in roots.iter_mut() {
+        name_access_points(root, naming, &mut names, &mut renamed);
+    }
+
+    for device in clients.iter_mut().flat_map(|c| c.devices.iter_mut()) {
+        let key = (device.parent_site_id.clone(), device.parent_node_id());
+        if let Some(name) = renamed.get(&key) {
+            device.access_point_name = name.clone();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{LqAccessPoint, LqClientDevice};
+
+    fn device(id: &str, site_id: &str, ap: &str) -> LqClientDevice {
+        LqClientDevice {
+            id: id.to_string(),
+            hostname: id.to_string(),
+            mac: String::new(),
+            model: String::new(),
+            ip: String::new(),
+            access_point_id: String::new(),
+            access_point_name: ap.to_string(),
+            parent_site_id: site_id.to_string(),
+            parent_site_name: String::new(),
+            upload: 0,
+            download: 0,
+            is_access_point: false,
+            is_bridge: false,
+        }
+    }
+
+    fn site(id: &str, name: &str, aps: &[&str], children: Vec<LqSite>) -> LqSite {
+        LqSite {
+            id: id.to_string(),
+            name: name.to_string(),
+            parent: None,
+            children,
+            access_points: aps
+                .iter()
+                .map(|ap| {
+                    let access_point = LqAccessPoint {
+                        name: ap.to_string(),
+                        download_mbps: 100,
+                        upload_mbps: 100,
+                        clients: vec![device(&format!("{id}/{ap}"), id, ap)],
+                    };
+                    (ap.to_string(), access_point)
+                })
+                .collect(),
+            download_mbps: 1_000,
+            upload_mbps: 1_000,
+        }
+    }
+
+    fn circuits(sites: &[&LqSite]) -> Vec<LqClientSite> {
+        sites
+            .iter()
+            .flat_map(|s| s.access_points.values())
+            .map(|ap| LqClientSite {
+                id: ap.clients[0].id.clone(),
+                name: ap.clients[0].id.clone(),
+                download: 0,
+                upload: 0,
+                suspended: false,
+                devices: ap.clients.clone(),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn generated_names_do_not_replace_existing_access_points() {
+        // "AP" is taken by the root, so the child's "AP" becomes "AP (2)",
+        // which is also the key of the child's other access point.
+        let child = site("child", "Child", &["AP", "AP (2)"], Vec::new());
+        let root = site("root", "Root", &["AP"], vec![child.clone()]);
+        let mut clients = circuits(&[&root, &child]);
+        let mut roots = vec![root];
+        assign_unique_names(&mut roots, &mut clients, &NodeNaming::default());
+
+        let child = &roots[0].children[0];
+        assert_eq!(
+            child.access_points.keys().collect::<Vec<_>>(),
+            vec!["AP (2)", "AP (2) (2)"]
+        );
+        let device_ids: Vec<&str> = child
+            .access_points
+            .values()
+            .map(|ap| ap.clients[0].id.as_str())
+            .collect();
+        assert_eq!(device_ids, vec!["child/AP", "child/AP (2)"]);
+        for device in clients.iter().flat_map(|c| c.devices.iter()) {
+            let expected = match device.id.as_str() {
+                "root/AP" => "AP",
+                "child/AP" => "AP (2)",
+                _ => "AP (2) (2)",
+            };
+            assert_eq!(device.access_point_name, expected, "{}", device.id);
+        }
+    }
+
+    #[test]
+    fn unparented_key_is_never_reused() {
+        let mut root = site("root", "Root", &["0"], Vec::new());
+        root.access_points.insert(
+            "0".to_string(),
+            LqAccessPoint {
+                name: UNPARENTED.to_string(),
+                download_mbps: 100,
+                upload_mbps: 100,
+                clients: Vec::new(),
+            },
+        );
+        root.access_points.insert(
+            "AP".to_string(),
+            LqAccessPoint {
+                name: "0".to_string(),
+                download_mbps: 100,
+                upload_mbps: 100,
+                clients: Vec::new(),
+            },
+        );
+        let mut roots = vec![root];
+        assign_unique_names(&mut roots, &mut [], &NodeNaming::default());
+        assert_eq!(roots[0].access_points["0"].name, UNPARENTED);
+        assert_eq!(roots[0].access_points["0 (2)"].name, "0 (2)");
+    }
+
+    #[test]
+    fn template_and_duplicate_site_names() {
+        let a = site("a", "Tower", &["AP"], Vec::new());
+        let b = site("b", "Tower", &["AP"], Vec::new());
+        let mut clients = circuits(&[&a, &b]);
+        let mut roots = vec![site("root", "Root", &[], vec![a, b])];
+        let naming = NodeNaming {
+            access_point: "{site} / {ap}".to_string(),
+        };
+        assign_unique_names(&mut roots, &mut clients, &naming);
+        let names: Vec<Vec<&String>> = roots[0]
+            .children
+            .iter()
+            .map(|s| s.access_points.keys().collect())
+            .collect();
+        assert_eq!(names, vec![vec!["Tower / AP"], vec!["Tower (2) / AP"]]);
+        assert_eq!(clients[1].devices[0].access_point_name, "Tower (2) / AP");
+    }
+}
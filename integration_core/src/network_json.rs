@@ -0,0 +1,165 @@
+use crate::{publish::write_atomic, LqAccessPoint, LqSite};
+use anyhow::Result;
+use std::path::Path;
+
+pub struct NetworkNode {
+    pub name: String,
+    pub download_bandwidth_mbps: usize,
+    pub upload_bandwidth_mbps: usize,
+    pub children: Vec<NetworkNode>,
+}
+
+impl NetworkNode {
+    pub fn from_lq_site(site: &LqSite) -> Self {
+        let mut result = NetworkNode {
+            name: site.name.clone(),
+            download_bandwidth_mbps: site.download_mbps,
+            upload_bandwidth_mbps: site.upload_mbps,
+            children: Vec::new(),
+        };
+
+        for ap in site.access_points.iter() {
+            result.children.push(NetworkNode::from_lq_ap(ap.1));
+        }
+
+        for cs in site.children.iter() {
+            result.children.push(NetworkNode::from_lq_site(cs));
+        }
+
+        result
+    }
+
+    fn from_lq_ap(ap: &LqAccessPoint) -> Self {
+        Self {
+            name: ap.name.clone(),
+            download_bandwidth_mbps: ap.download_mbps,
+            upload_bandwidth_mbps: ap.upload_mbps,
+            children: Vec::new(),
+        }
+    }
+
+    fn to_json(&self, base: usize, add_comma: bool) -> String {
+        // This is messy because the network.json format isn't very strong-type friendly.
+        // Serde-json didn't want to go with the free-from look.
+        // Doing my best to match https://github.com/rchac/LibreQoS/blob/main/v1.1/network.json
+        let mut js = String::new();
+        // Names are quoted and escaped as JSON strings.
+        let name = serde_json::Value::from(self.name.as_str());
+        js += &pad_line_add_eol(base + 1, &format!("{name}:"));
+        js += &pad_line_add_eol(base + 1, "{");
+        js += &pad_line_add_eol(
+            base + 1,
+            &format!(
+                "\"downloadBandwidthMbps\":{},",
+                self.download_bandwidth_mbps
+            ),
+        );
+        if self.children.is_empty() {
+            js += &pad_line_add_eol(
+                base + 1,
+                &format!("\"uploadBandwidthMbps\":{}", self.upload_bandwidth_mbps),
+            );
+        } else {
+            js += &pad_line_add_eol(
+                base + 1,
+                &format!("\"uploadBandwidthMbps\":{},", self.upload_bandwidth_mbps),
+            );
+        }
+        if !self.children.is_empty() {
+            js += &pad_line_add_eol(base + 1, "\"children\":");
+            js += &pad_line_add_eol(base + 2, "{");
+            let len = self.children.len();
+            for (i, c) in self.children.iter().enumerate() {
+                js += &c.to_json(base + 3, i < len - 1);
+            }
+            js += &pad_line_add_eol(base + 2, "}");
+        }
+        if add_comma {
+            js += &pad_line_add_eol(base + 1, "},");
+        } else {
+            js += &pad_line_add_eol(base + 1, "}");
+        }
+        js
+    }
+
+    /// Renders the tree in LibreQOS's `network.json` format.
+    pub fn to_json_string(&self) -> String {
+        forest_to_json(std::slice::from_ref(self))
+    }
+
+    /// Writes `network.json` to the given path.
+    pub fn write_to_path<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        write_atomic(path, self.to_json_string().as_bytes())
+    }
+
+    /// Writes `network.json` to the current directory.
+    pub fn write_to_file(&self) -> Result<()> {
+        self.write_to_path("network.json")
+    }
+}
+
+/// Renders several trees as top-level nodes of one `network.json`.
+pub fn forest_to_json(nodes: &[NetworkNode]) -> String {
+    let mut js = pad_line_add_eol(0, "{");
+    let len = nodes.len();
+    for (i, node) in nodes.iter().enumerate() {
+        js += &node.to_json(0, i < len - 1);
+    }
+    js += &pad_line_add_eol(0, "}");
+    js
+}
+
+/// Converts one or more site trees to `network.json`. Each root becomes a
+/// top-level node.
+pub fn network_json(roots: &[LqSite]) -> String {
+    let nodes: Vec<NetworkNode> = roots.iter().map(NetworkNode::from_lq_site).collect();
+    forest_to_json(&nodes)
+}
+
+/// Converts one or more site trees to `network.json` and writes it to the
+/// given path.
+pub fn write_network_json<P: AsRef<Path>>(roots: &[LqSite], path: P) -> Result<()> {
+    write_atomic(path, network_json(roots).as_bytes())
+}
+
+fn pad_line_add_eol(tabs: usize, line: &str) -> String {
+    let mut spacing = String::new();
+    for _ in 0..tabs {
+        spacing += "   ";
+    }
+    format!("{spacing}{line}\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::{json, Value};
+
+    #[test]
+    fn names_are_escaped() {
+        let node = NetworkNode {
+            name: "Tower \"North\" \\ 1".to_string(),
+            download_bandwidth_mbps: 100,
+            upload_bandwidth_mbps: 50,
+            children: vec![NetworkNode {
+                name: "AP\t2".to_string(),
+                download_bandwidth_mbps: 10,
+                upload_bandwidth_mbps: 5,
+                children: Vec::new(),
+            }],
+        };
+        let parsed: Value = serde_json::from_str(&node.to_json_string()).unwrap();
+        assert_eq!(
+            parsed,
+            json!({
+                "Tower \"North\" \\ 1": {
+                    "downloadBandwidthMbps": 100,
+                    "uploadBandwidthMbps": 50,
+                    "children": {
+                        "AP\t2": {"downloadBandwidthMbps": 10, "uploadBandwidthMbps": 5}
+                    }
+                }
+            })
+        );
+    }
+}
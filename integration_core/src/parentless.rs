@@ -0,0 +1,177 @@
+use crate::{LqClientSite, LqSite, ParentlessDevice, UNPARENTED};
+use anyhow::Result;
+use std::collections::HashMap;
+
+/// A position in decimal degrees.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Location {
+    pub latitude: f64,
+    pub longitude: f64,
+}
+
+impl Location {
+    /// Great-circle distance to another location, in kilometres.
+    pub fn distance_km(&self, other: &Location) -> f64 {
+        const EARTH_RADIUS_KM: f64 = 6_371.0;
+        let (lat1, lat2) = (self.latitude.to_radians(), other.latitude.to_radians());
+        let d_lat = lat2 - lat1;
+        let d_lon = (other.longitude - self.longitude).to_radians();
+        let a = (d_lat / 2.0).sin().powi(2) + lat1.cos() * lat2.cos() * (d_lon / 2.0).sin().powi(2);
+        2.0 * EARTH_RADIUS_KM * a.sqrt().asin()
+    }
+}
+
+/// A site a parentless device could be attached to.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SuggestedParent {
+    pub site_id: String,
+    pub site_name: String,
+    /// How the site was chosen, e.g. `"data link to AP-1"`.
+    pub basis: String,
+}
+
+/// What is known about a device that couldn't be placed in the tree.
+#[derive(Debug, Clone)]
+pub struct ParentlessEntry {
+    pub device_id: String,
+    pub hostname: String,
+    pub mac: String,
+    pub ip: String,
+    pub model: String,
+    /// Name of the circuit the device belongs to.
+    pub client_site: String,
+    /// Why it couldn't be placed, most important first.
+    pub reasons: Vec<&'static str>,
+    pub suggested_parent: Option<SuggestedParent>,
+}
+
+/// The site of the access point a device was linked to: the site holding
+/// the access point device, or an access point of that name.
+fn linked_site<'a>(
+    device: &ParentlessDevice,
+    device_sites: &HashMap<&str, &'a LqSite>,
+    sites: &'a HashMap<String, LqSite>,
+) -> Option<&'a LqSite> {
+    if device.access_point_id.is_empty() {
+        return None;
+    }
+    if let Some(site) = device_sites.get(device.access_point_id.as_str()) {
+        return Some(site);
+    }
+    let mut named: Vec<&LqSite> = sites
+        .values()
+        .filter(|s| s.access_points.contains_key(&device.access_point_name))
+        .collect();
+    named.sort_by(|a, b| a.id.cmp(&b.id));
+    named.first().copied()
+}
+
+/// The closest site with a known location, and its distance in kilometres.
+fn nearest_site<'a>(
+    location: &Location,
+    sites: &'a HashMap<String, LqSite>,
+    locations: &HashMap<String, Location>,
+) -> Option<(&'a LqSite, f64)> {
+    sites
+        .values()
+        .filter_map(|s| Some((s, location.distance_km(locations.get(&s.id)?))))
+        .min_by(|a, b| a.1.total_cmp(&b.1).then_with(|| a.0.id.cmp(&b.0.id)))
+}
+
+/// Describes each parentless device, suggesting a parent site where one can
+/// be inferred: first from the access point it is linked to, then from the
+/// nearest site to the device's `locations` entry.
+pub fn diagnose_parentless(
+    parentless: &[ParentlessDevice],
+    clients: &[LqClientSite],
+    sites: &HashMap<String, LqSite>,
+    locations: &HashMap<String, Location>,
+) -> Vec<ParentlessEntry> {
+    let mut circuit_names = HashMap::<&str, &str>::new();
+    let mut device_sites = HashMap::<&str, &LqSite>::new();
+    for circuit in clients.iter() {
+        for device in circuit.devices.iter() {
+            circuit_names.insert(device.id.as_str(), circuit.name.as_str());
+            if device.access_point_name != UNPARENTED {
+                if let Some(site) = sites.get(&device.parent_site_id) {
+                    device_sites.insert(device.id.as_str(), site);
+                }
+            }
+        }
+    }
+
+    parentless
+        .iter()
+        .map(|p| {
+            let device = &p.device;
+            let mut reasons = vec![p.reason.as_str()];
+            if p.access_point_id.is_empty() {
+                reasons.push("no data link");
+            }
+            let suggested_parent = linked_site(p, &device_sites, sites)
+                .map(|site| SuggestedParent {
+                    site_id: site.id.clone(),
+                    site_name: site.name.clone(),
+                    basis: format!("data link to {}", p.access_point_name),
+                })
+                .or_else(|| {
+                    let location = locations.get(&device.id)?;
+                    let (site, km) = nearest_site(location, sites, locations)?;
+                    Some(SuggestedParent {
+                        site_id: site.id.clone(),
+                        site_name: site.name.clone(),
+                        basis: format!("nearest site ({km:.1} km)"),
+                    })
+                });
+            ParentlessEntry {
+                device_id: device.id.clone(),
+                hostname: device.hostname.clone(),
+                mac: device.mac.clone(),
+                ip: device.ip.clone(),
+                model: device.model.clone(),
+                client_site: circuit_names
+                    .get(device.id.as_str())
+                    .unwrap_or(&"")
+                    .to_string(),
+                reasons,
+                suggested_parent,
+            }
+        })
+        .collect()
+}
+
+/// One row per parentless device, with reasons separated by semicolons.
+pub fn parentless_csv(entries: &[ParentlessEntry]) -> Result<String> {
+    let mut writer = csv::Writer::from_writer(Vec::new());
+    writer.write_record([
+        "Device ID",
+        "Hostname",
+        "MAC",
+        "IP",
+        "Model",
+        "Client Site",
+        "Reason",
+        "Suggested Parent",
+        "Suggested Parent ID",
+        "Suggestion Basis",
+    ])?;
+    for entry in entries.iter() {
+        let (name, id, basis) = match &entry.suggested_parent {
+            Some(s) => (s.site_name.as_str(), s.site_id.as_str(), s.basis.as_str()),
+            None => ("", "", ""),
+        };
+        writer.write_record([
+            entry.device_id.as_str(),
+            &entry.hostname,
+            &entry.mac,
+            &entry.ip,
+            &entry.model,
+            &entry.client_site,
+            &entry.reasons.join("; "),
+            name,
+            id,
+            basis,
+        ])?;
+    }
+    Ok(String::from_utf8(writer.into_inner()?)?)
+}
@@ -0,0 +1,505 @@
+use crate::{ChangeGuard, ReloadHook};
+use anyhow::{Error, Result};
+use serde::{Deserialize, Serialize};
+use std::{
+    fs,
+    io::Write,
+    path::{Path, PathBuf},
+};
+use tracing::warn;
+
+/// Present in the output directory while published files are waiting for a
+/// successful reload, so a failed reload is retried by the next run.
+const RELOAD_PENDING: &str = ".reload-pending";
+
+/// Where generated files are published, and how many previous generations
+/// are kept.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct OutputConfig {
+    /// Directory that `network.json`, `Shaper.csv`, `ShapedDevices.csv` and
+    /// the integrations' other generated files, such as `Sites.csv`, are
+    /// written to.
+    pub directory: String,
+    /// Number of previous generations kept in `{directory}/backups`.
+    pub keep_generations: usize,
+    /// Refuses to publish outputs that differ too much from the last ones.
+    pub guard: ChangeGuard,
+    /// Run after new outputs are published or a generation is restored.
+    pub reload: Option<ReloadHook>,
+}
+
+impl Default for OutputConfig {
+    fn default() -> Self {
+        Self {
+            directory: ".".to_string(),
+            keep_generations: 5,
+            guard: ChangeGuard::default(),
+            reload: None,
+        }
+    }
+}
+
+impl OutputConfig {
+    pub fn path(&self, name: &str) -> PathBuf {
+        Path::new(&self.directory).join(name)
+    }
+
+    /// Makes a configured file path relative to the output directory rather
+    /// than the working directory. Absolute paths are left alone.
+    pub fn resolve(&self, path: &mut Option<String>) {
+        if let Some(path) = path {
+            *path = self.path(path).to_string_lossy().to_string();
+        }
+    }
+
+    fn backups(&self) -> PathBuf {
+        Path::new(&self.directory).join("backups")
+    }
+}
+
+/// A set of generated files, by file name, published together.
+#[derive(Clone, Debug, Default)]
+pub struct Outputs {
+    /// The files LibreQoS reads. A change to any of them publishes a new
+    /// generation and runs the reload hook.
+    pub files: Vec<(String, String)>,
+    /// Files for the operator, such as `Sites.csv` or `Parentless.csv`. They
+    /// are only written if the publish goes ahead, and are saved in the same
+    /// generations, but a change to them alone doesn't make a new generation.
+    pub supporting: Vec<(String, String)>,
+}
+
+impl Outputs {
+    pub fn add(&mut self, name: &str, contents: String) {
+        self.files.push((name.to_string(), contents));
+    }
+
+    pub fn add_supporting(&mut self, name: &str, contents: String) {
+        self.supporting.push((name.to_string(), contents));
+    }
+
+    /// Adds another set's files to this one.
+    pub fn extend(&mut self, other: Outputs) {
+        self.files.extend(other.files);
+        self.supporting.extend(other.supporting);
+    }
+
+    /// The contents of a file in this set, main or supporting.
+    pub fn get(&self, name: &str) -> Option<&str> {
+        self.files
+            .iter()
+            .chain(self.supporting.iter())
+            .find(|(n, _)| n == name)
+            .map(|(_, c)| c.as_str())
+    }
+
+    /// Reads the currently published versions of the same files. Files that
+    /// don't exist yet are left out.
+    pub fn load_published(&self, config: &OutputConfig) -> Outputs {
+        let mut result = Outputs::default();
+        for (name, _) in self.files.iter() {
+            if let Ok(contents) = fs::read_to_string(config.path(name)) {
+                result.add(name, contents);
+            }
+        }
+        for (name, _) in self.supporting.iter() {
+            if let Ok(contents) = fs::read_to_string(config.path(name)) {
+                result.add_supporting(name, contents);
+            }
+        }
+        result
+    }
+}
+
+/// What [`publish`] did.
+#[derive(Debug)]
+pub struct PublishOutcome {
+    /// False if every file already had the same contents, in which case
+    /// nothing was written.
+    pub changed: bool,
+    /// The generation the previous files were saved as, if there were any.
+    pub backup: Option<String>,
+}
+
+/// Writes `contents` to a temporary file beside `path`, then renames it into
+/// place, so readers never see a partly written file.
+pub fn write_atomic<P: AsRef<Path>>(path: P, contents: &[u8]) -> Result<()> {
+    let path = path.as_ref();
+    let file_name = path
+        .file_name()
+        .ok_or_else(|| Error::msg(format!("Not a file path: {}", path.display())))?
+        .to_string_lossy();
+    let temp = path.with_file_name(format!(".{file_name}.tmp"));
+    let mut f = fs::File::create(&temp)?;
+    f.write_all(contents)?;
+    f.sync_all()?;
+    fs::rename(&temp, path)?;
+    Ok(())
+}
+
+/// Copies the currently published versions of `names` into a new
+/// timestamped generation directory, returning its name.
+fn back_up(config: &OutputConfig, names: &[&str]) -> Result<Option<String>> {
+    let existing: Vec<&str> = names
+        .iter()
+        .filter(|n| config.path(n).exists())
+        .cloned()
+        .collect();
+    if existing.is_empty() {
+        return Ok(None);
+    }
+    // Generations are named by UTC time, and must sort after every existing
+    // one, even if two runs land in the same second.
+    let stamp = chrono::Utc::now().format("%Y%m%d-%H%M%S").to_string();
+    let generation = match generations(config)?.first() {
+        Some(newest) if newest.as_str() >= stamp.as_str() => {
+            let (time, count) = newest.split_at(newest.len().min(stamp.len()));
+            let count: usize = count.trim_start_matches('-').parse().unwrap_or(1);
+            format!("{time}-{:03}", count + 1)
+        }
+        _ => stamp,
+    };
+    let dir = config.backups().join(&generation);
+    fs::create_dir_all(&dir)?;
+    for name in existing {
+        fs::copy(config.path(name), dir.join(name))?;
+    }
+    Ok(Some(generation))
+}
+
+/// Deletes all but the newest `keep_generations` backups.
+fn prune(config: &OutputConfig) -> Result<()> {
+    for generation in generations(config)?.iter().skip(config.keep_generations) {
+        fs::remove_dir_all(config.backups().join(generation))?;
+    }
+    Ok(())
+}
+
+/// Publishes a set of files to the output directory. If any of the main
+/// files differs from what's there, the current files (supporting ones
+/// included) are first saved as a backup generation, then every file is
+/// replaced atomically and old generations beyond `keep_generations` are
+/// removed. Finally the reload hook, if any, is run; a failing reload fails
+/// the publish, though the files stay in place and the reload is retried by
+/// the next publish, even if nothing changed.
+///
+/// If only supporting files changed, they are rewritten in place. Unless
+/// `force` is set, fails without writing anything if the change from the
+/// published files exceeds the configured [`ChangeGuard`] limits.
+pub fn publish(config: &OutputConfig, outputs: &Outputs, force: bool) -> Result<PublishOutcome> {
+    fs::create_dir_all(&config.directory)?;
+    let published = outputs.load_published(config);
+    let changed = outputs
+        .files
+        .iter()
+        .any(|(name, contents)| published.get(name) != Some(contents.as_str()));
+    if !changed {
+        for (name, contents) in outputs.supporting.iter() {
+            if fs::read_to_string(config.path(name)).ok().as_deref() != Some(contents.as_str()) {
+                write_atomic(config.path(name), contents.as_bytes())?;
+            }
+        }
+        if reload_pending(config) {
+            warn!("Retrying the reload that failed after the last publish");
+            reload(config)?;
+        }
+        return Ok(PublishOutcome {
+            changed,
+            backup: None,
+        });
+    }
+
+    let problems = config.guard.check(&published, outputs);
+    if !problems.is_empty() {
+        if !force {
+            return Err(Error::msg(format!(
+                "Refusing to publish: {}. The previous files were kept; re-run with --force to publish anyway.",
+                problems.join("; ")
+            )));
+        }
+        for problem in problems.iter() {
+            warn!("Publishing despite large change (--force): {problem}");
+        }
+    }
+
+    let all_files = || outputs.files.iter().chain(outputs.supporting.iter());
+    let names: Vec<&str> = all_files().map(|(n, _)| n.as_str()).collect();
+    let backup = back_up(config, &names)?;
+    mark_reload_pending(config)?;
+    for (name, contents) in all_files() {
+        write_atomic(config.path(name), contents.as_bytes())?;
+    }
+    prune(config)?;
+    reload(config)?;
+    Ok(PublishOutcome { changed, backup })
+}
+
+fn reload_pending(config: &OutputConfig) -> bool {
+    config.reload.is_some() && config.path(RELOAD_PENDING).exists()
+}
+
+/// Records that the files about to be written need a reload, if a reload
+/// hook is configured.
+fn mark_reload_pending(config: &OutputConfig) -> Result<()> {
+    if config.reload.is_some() {
+        write_atomic(config.path(RELOAD_PENDING), b"")?;
+    }
+    Ok(())
+}
+
+/// Runs the reload hook, if one is configured, clearing the pending marker
+/// once it succeeds.
+fn reload(config: &OutputConfig) -> Result<()> {
+    if let Some(hook) = &config.reload {
+        hook.run()?;
+        let marker = config.path(RELOAD_PENDING);
+        if marker.exists() {
+            fs::remove_file(marker)?;
+        }
+    }
+    Ok(())
+}
+
+/// Saved generations, newest first.
+pub fn generations(config: &OutputConfig) -> Result<Vec<String>> {
+    let dir = config.backups();
+    if !dir.exists() {
+        return Ok(Vec::new());
+    }
+    let mut result: Vec<String> = fs::read_dir(dir)?
+        .filter_map(|entry| entry.ok())
+        .filter(|entry| entry.path().is_dir())
+        .map(|entry| entry.file_name().to_string_lossy().to_string())
+        .collect();
+    result.sort_by(|a, b| b.cmp(a));
+    Ok(result)
+}
+
+/// Restores a saved generation (the newest if `generation` is `None`) to the
+/// output directory. The files being replaced are saved as a new generation
+/// first, so a rollback can itself be undone, and the reload hook is run
+/// afterwards. Returns the restored generation's name.
+pub fn rollback(config: &OutputConfig, generation: Option<&str>) -> Result<String> {
+    let available = generations(config)?;
+    let generation = match generation {
+        Some(name) if available.iter().any(|g| g == name) => name.to_string(),
+        Some(name) => return Err(Error::msg(format!("No saved generation named '{name}'"))),
+        None => available
+            .first()
+            .cloned()
+            .ok_or_else(|| Error::msg("No saved generations to roll back to"))?,
+    };
+
+    let dir = config.backups().join(&generation);
+    let mut files = Vec::new();
+    for entry in fs::read_dir(&dir)? {
+        let entry = entry?;
+        files.push((
+            entry.file_name().to_string_lossy().to_string(),
+            fs::read(entry.path())?,
+        ));
+    }
+    let names: Vec<&str> = files.iter().map(|(n, _)| n.as_str()).collect();
+    back_up(config, &names)?;
+    mark_reload_pending(config)?;
+    for (name, contents) in files.iter() {
+        write_atomic(config.path(name), contents)?;
+    }
+    prune(config)?;
+    reload(config)?;
+    Ok(generation)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    fn config(dir: &TempDir, reload: Option<&str>) -> OutputConfig {
+        OutputConfig {
+            directory: dir.path().to_string_lossy().to_string(),
+            reload: reload.map(|script| ReloadHook {
+                command: vec!["sh".to_string(), "-c".to_string(), script.to_string()],
+                timeout_seconds: 1,
+            }),
+            ..OutputConfig::default()
+        }
+    }
+
+    fn outputs(contents: &str) -> Outputs {
+        let mut outputs = Outputs::default();
+        outputs.add("network.json", contents.to_string());
+        outputs
+    }
+
+    fn published(config: &OutputConfig) -> String {
+        fs::read_to_string(config.path("network.json")).unwrap()
+    }
+
+    #[test]
+    fn publishes_and_reloads() {
+        let dir = TempDir::new().unwrap();
+        let marker = dir.path().join("reloaded");
+        let config = config(&dir, Some(&format!("touch {}", marker.display())));
+
+        let outcome = publish(&config, &outputs("{}"), false).unwrap();
+        assert!(outcome.changed);
+        assert_eq!(published(&config), "{}");
+        assert!(marker.exists());
+        assert!(!reload_pending(&config));
+    }
+
+    #[test]
+    fn failed_reload_is_retried_by_the_next_run() {
+        let dir = TempDir::new().unwrap();
+        let failing = config(&dir, Some("exit 1"));
+        assert!(publish(&failing, &outputs("{}"), false).is_err());
+        // The files stay published, waiting for a reload.
+        assert_eq!(published(&failing), "{}");
+        assert!(reload_pending(&failing));
+
+        // Nothing changed, but the reload still fails, so the run does too.
+        assert!(publish(&failing, &outputs("{}"), false).is_err());
+
+        let working = config(&dir, Some("true"));
+        let outcome = publish(&working, &outputs("{}"), false).unwrap();
+        assert!(!outcome.changed);
+        assert!(!reload_pending(&working));
+        // Once reloaded, unchanged runs don't reload again.
+        let failing = config(&dir, Some("exit 1"));
+        assert!(publish(&failing, &outputs("{}"), false).is_ok());
+    }
+
+    #[test]
+    fn reload_timeout_fails_the_publish() {
+        let dir = TempDir::new().unwrap();
+        let config = config(&dir, Some("sleep 5"));
+        let error = publish(&config, &outputs("{}"), false).unwrap_err();
+        assert!(error.to_string().contains("timed out"), "{error}");
+        assert!(reload_pending(&config));
+    }
+
+    #[test]
+    fn unchanged_outputs_are_not_rewritten() {
+        let dir = TempDir::new().unwrap();
+        let config = config(&dir, None);
+        assert!(publish(&config, &outputs("{}"), false).unwrap().changed);
+        let outcome = publish(&config, &outputs("{}"), false).unwrap();
+        assert!(!outcome.changed);
+        assert!(generations(&config).unwrap().is_empty());
+    }
+
+    #[test]
+    fn rollback_restores_and_can_be_undone() {
+        let dir = TempDir::new().unwrap();
+        let marker = dir.path().join("reloaded");
+        let config = config(&dir, Some(&format!("touch {}", marker.display())));
+        publish(&config, &outputs("first"), false).unwrap();
+        let backup = publish(&config, &outputs("second"), false)
+            .unwrap()
+            .backup
+            .unwrap();
+        assert_eq!(generations(&config).unwrap(), vec![backup.clone()]);
+        fs::remove_file(&marker).unwrap();
+
+        assert_eq!(rollback(&config, None).unwrap(), backup);
+        assert_eq!(published(&config), "first");
+        assert!(marker.exists());
+
+        // The replaced files were saved as a newer generation.
+        let newest = generations(&config).unwrap()[0].clone();
+        assert_ne!(newest, backup);
+        rollback(&config, Some(&newest)).unwrap();
+        assert_eq!(published(&config), "second");
+    }
+
+    /// `network.json` plus a `ShapedDevices.csv` with `circuits` circuits.
+    fn with_circuits(network: &str, circuits: usize) -> Outputs {
+        let mut outputs = outputs(network);
+        let mut shaped_devices = format!("{}\n", crate::SHAPED_DEVICES_HEADER);
+        for circuit in 0..circuits {
+            shaped_devices +=
+                &format!("c{circuit},Circuit,d{circuit},Device,,,100.64.0.{circuit},,1,1,10,10,\n");
+        }
+        outputs.add("ShapedDevices.csv", shaped_devices);
+        outputs
+    }
+
+    #[test]
+    fn supporting_files_are_only_written_with_the_publish() {
+        let dir = TempDir::new().unwrap();
+        let mut config = config(&dir, None);
+        config.guard.min_previous_circuits = 0;
+        let sites = || fs::read_to_string(config.path("Sites.csv")).unwrap();
+        let run = |network: &str, circuits: usize, supporting: &str| {
+            let mut outputs = with_circuits(network, circuits);
+            outputs.add_supporting("Sites.csv", supporting.to_string());
+            publish(&config, &outputs, false)
+        };
+
+        assert!(run("{}", 2, "first").unwrap().changed);
+        assert_eq!(sites(), "first");
+
+        // A supporting file alone is rewritten without a new generation.
+        assert!(!run("{}", 2, "second").unwrap().changed);
+        assert_eq!(sites(), "second");
+        assert!(generations(&config).unwrap().is_empty());
+
+        // A refused run leaves it alone.
+        assert!(run("{}", 0, "refused").is_err());
+        assert_eq!(sites(), "second");
+
+        // A published run backs it up with the rest of the generation.
+        let backup = run("{ }", 2, "third").unwrap().backup.unwrap();
+        assert_eq!(sites(), "third");
+        let saved = config.backups().join(backup).join("Sites.csv");
+        assert_eq!(fs::read_to_string(saved).unwrap(), "second");
+    }
+
+    #[test]
+    fn unreadable_published_files_need_force() {
+        let dir = TempDir::new().unwrap();
+        let config = config(&dir, None);
+        publish(&config, &with_circuits("{}", 20), false).unwrap();
+        fs::write(config.path("network.json"), "{\"Site\": ").unwrap();
+        let error = publish(&config, &with_circuits("{}", 20), false).unwrap_err();
+        assert!(error.to_string().contains("can't be read"), "{error}");
+        assert!(
+            publish(&config, &with_circuits("{}", 20), true)
+                .unwrap()
+                .changed
+        );
+        assert_eq!(published(&config), "{}");
+    }
+
+    #[test]
+    fn relative_paths_resolve_to_the_output_directory() {
+        let config = OutputConfig {
+            directory: "/opt/libreqos/src".to_string(),
+            ..OutputConfig::default()
+        };
+        let (mut relative, mut absolute, mut unset) = (
+            Some("report.html".to_string()),
+            Some("/var/www/report.html".to_string()),
+            None,
+        );
+        config.resolve(&mut relative);
+        config.resolve(&mut absolute);
+        config.resolve(&mut unset);
+        assert_eq!(relative.as_deref(), Some("/opt/libreqos/src/report.html"));
+        assert_eq!(absolute.as_deref(), Some("/var/www/report.html"));
+        assert_eq!(unset, None);
+    }
+
+    #[test]
+    fn rollback_rejects_unknown_generations() {
+        let dir = TempDir::new().unwrap();
+        let config = config(&dir, None);
+        assert!(rollback(&config, None).is_err());
+        publish(&config, &outputs("first"), false).unwrap();
+        publish(&config, &outputs("second"), false).unwrap();
+        assert!(rollback(&config, Some("19700101-000000")).is_err());
+        assert_eq!(published(&config), "second");
+    }
+}
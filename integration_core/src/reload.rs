@@ -0,0 +1,90 @@
+use anyhow::{Error, Result};
+use serde::{Deserialize, Serialize};
+use std::{
+    io::{BufRead, BufReader, Read},
+    process::{Command, Stdio},
+    thread,
+    time::{Duration, Instant},
+};
+use tracing::{info, info_span};
+
+/// A command run after new outputs are published, such as the LibreQoS
+/// refresh script, so the new files take effect.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct ReloadHook {
+    /// The program and its arguments.
+    pub command: Vec<String>,
+    /// Seconds to wait before the command is killed and the run fails.
+    #[serde(default = "ReloadHook::default_timeout")]
+    pub timeout_seconds: u64,
+}
+
+/// Logs each line a child process writes, tagged with `label`.
+fn forward<R: Read + Send + 'static>(stream: R, label: &'static str) -> thread::JoinHandle<()> {
+    let span = tracing::Span::current();
+    thread::spawn(move || {
+        let _span = span.enter();
+        for line in BufReader::new(stream).lines().map_while(|l| l.ok()) {
+            info!(stream = label, "{line}");
+        }
+    })
+}
+
+impl ReloadHook {
+    fn default_timeout() -> u64 {
+        300
+    }
+
+    /// Runs the command, logging its output as it goes. Fails if it can't
+    /// be started, exits unsuccessfully or runs past the timeout.
+    pub fn run(&self) -> Result<()> {
+        let (program, args) = self
+            .command
+            .split_first()
+            .ok_or_else(|| Error::msg("The reload command is empty"))?;
+        let _span = info_span!("reload").entered();
+        info!(command = self.command.join(" "), "Running reload command");
+        let start = Instant::now();
+        let mut child = Command::new(program)
+            .args(args)
+            .stdin(Stdio::null())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()
+            .map_err(|e| Error::msg(format!("Unable to run reload command {program}: {e}")))?;
+        let readers = [
+            child.stdout.take().map(|s| forward(s, "stdout")),
+            child.stderr.take().map(|s| forward(s, "stderr")),
+        ];
+
+        let timeout = Duration::from_secs(self.timeout_seconds);
+        let status = loop {
+            if let Some(status) = child.try_wait()? {
+                break Some(status);
+            }
+            if start.elapsed() >= timeout {
+                child.kill()?;
+                child.wait()?;
+                break None;
+            }
+            thread::sleep(Duration::from_millis(100));
+        };
+        // Let the readers finish printing, but not past the timeout: anything
+        // the command left running in the background may hold its output open.
+        while readers.iter().flatten().any(|r| !r.is_finished()) && start.elapsed() < timeout {
+            thread::sleep(Duration::from_millis(10));
+        }
+
+        match status {
+            Some(status) if status.success() => {
+                info!("Reload completed");
+                Ok(())
+            }
+            Some(status) => Err(Error::msg(format!("Reload command failed: {status}"))),
+            None => Err(Error::msg(format!(
+                "Reload command timed out after {} seconds",
+                self.timeout_seconds
+            ))),
+        }
+    }
+}
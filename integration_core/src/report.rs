@@ -0,0 +1,397 @@
+use crate::{
+    node_loads, write_atomic, CapacityLimits, CapacityPlan, Counter, LoadLevel, LqClientSite,
+    LqSite, NodeLoad, OutputConfig, ParentlessEntry, RunMetrics, Topology, UNPARENTED,
+};
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+/// A human-readable summary of each run.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ReportConfig {
+    /// Path of a self-contained HTML report to write after each run.
+    pub html: Option<String>,
+}
+
+impl ReportConfig {
+    /// Resolves the report path against the output directory.
+    pub fn resolve_paths(&mut self, output: &OutputConfig) {
+        output.resolve(&mut self.html);
+    }
+}
+
+/// What a run left out of the site tree, for the report.
+#[derive(Debug, Clone, Default)]
+pub struct Excluded {
+    /// Devices that couldn't be placed, as listed in `Parentless.csv`.
+    pub parentless: Vec<ParentlessEntry>,
+    /// Names of the sites that aren't beneath any root.
+    pub rejected_sites: Vec<String>,
+}
+
+impl Excluded {
+    /// The parentless devices and rejected sites of a built topology.
+    pub fn new(topology: &Topology, parentless: Vec<ParentlessEntry>) -> Self {
+        Self {
+            parentless,
+            rejected_sites: topology
+                .rejected_sites()
+                .iter()
+                .map(|s| s.name.clone())
+                .collect(),
+        }
+    }
+}
+
+const STYLE: &str = "body { font-family: sans-serif; margin: 2em; color: #222; }
+table { border-collapse: collapse; margin: 0.5em 0; }
+th, td { border: 1px solid #ccc; padding: 0.2em 0.6em; text-align: left; }
+td.n { text-align: right; }
+details { margin-left: 1.5em; }
+summary { cursor: pointer; padding: 0.15em 0; }
+.normal { background: #c8e6c9; }
+.warn { background: #ffe0b2; }
+.critical { background: #ffcdd2; }
+.muted { color: #777; }";
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+/// "provisioned of capacity" for both directions, and the ratio.
+fn load_text(load: &NodeLoad) -> String {
+    format!(
+        "{}/{} of {}/{} Mbps ({:.1}x)",
+        load.provisioned_download_mbps,
+        load.provisioned_upload_mbps,
+        load.download_mbps,
+        load.upload_mbps,
+        load.oversubscription()
+    )
+}
+
+struct Loads<'a> {
+    sites: HashMap<&'a str, &'a NodeLoad>,
+    access_points: HashMap<&'a str, &'a NodeLoad>,
+}
+
+fn site_html(
+    site: &LqSite,
+    depth: usize,
+    loads: &Loads,
+    limits: &CapacityLimits,
+    html: &mut String,
+) {
+    let open = if depth == 0 { " open" } else { "" };
+    match loads.sites.get(site.id.as_str()) {
+        Some(load) => {
+            *html += &format!(
+                "<details{open}><summary><span class=\"{}\">{}</span> &mdash; {} circuits &mdash; {}</summary>\n",
+                limits.level(load).as_str(),
+                escape(&site.name),
+                load.circuits,
+                load_text(load)
+            );
+        }
+        None => *html += &format!("<details{open}><summary>{}</summary>\n", escape(&site.name)),
+    }
+
+    let access_points: Vec<&NodeLoad> = site
+        .access_points
+        .iter()
+        .filter(|(_, ap)| ap.name != UNPARENTED)
+        .filter_map(|(id, _)| loads.access_points.get(id.as_str()).copied())
+        .collect();
+    if !access_points.is_empty() {
+        *html += "<table><tr><th>Access point</th><th>Circuits</th><th>Provisioned of capacity (down/up)</th></tr>\n";
+        for load in access_points {
+            *html += &format!(
+                "<tr><td class=\"{}\">{}</td><td class=\"n\">{}</td><td>{}</td></tr>\n",
+                limits.level(load).as_str(),
+                escape(&load.name),
+                load.circuits,
+                load_text(load)
+            );
+        }
+        *html += "</table>\n";
+    }
+    for child in site.children.iter() {
+        site_html(child, depth + 1, loads, limits, html);
+    }
+    *html += "</details>\n";
+}
+
+/// The nodes over the warning ratio, most oversubscribed first.
+fn plan_html(plan: &CapacityPlan, html: &mut String) {
+    let over: Vec<_> = plan
+        .nodes
+        .iter()
+        .filter(|e| e.level != LoadLevel::Normal)
+        .collect();
+    *html += &format!(
+        "<h2>Capacity planning ({} over {}x)</h2>\n",
+        over.len(),
+        plan.warn_ratio
+    );
+    if over.is_empty() {
+        *html += "<p class=\"muted\">None.</p>\n";
+        return;
+    }
+    *html += "<table><tr><th>Type</th><th>Name</th><th>Circuits</th><th>Provisioned of capacity (down/up)</th><th>Top circuits (down/up Mbps)</th></tr>\n";
+    for entry in over {
+        let kind = if entry.kind == "site" {
+            "Site"
+        } else {
+            "Access point"
+        };
+        let top: Vec<String> = entry
+            .load
+            .top_circuits
+            .iter()
+            .map(|c| {
+                escape(&format!(
+                    "{} ({}/{})",
+                    c.name, c.download_mbps, c.upload_mbps
+                ))
+            })
+            .collect();
+        *html += &format!(
+            "<tr><td>{kind}</td><td class=\"{}\">{}</td><td class=\"n\">{}</td><td>{}</td><td>{}</td></tr>\n",
+            entry.level.as_str(),
+            escape(&entry.load.name),
+            entry.load.circuits,
+            load_text(&entry.load),
+            top.join("<br>")
+        );
+    }
+    *html += "</table>\n";
+}
+
+/// The parentless devices, with the reasons and suggested parents given in
+/// `Parentless.csv`.
+fn parentless_html(parentless: &[ParentlessEntry], html: &mut String) {
+    *html += &format!("<h2>Parentless devices ({})</h2>\n", parentless.len());
+    if parentless.is_empty() {
+        *html += "<p class=\"muted\">None.</p>\n";
+        return;
+    }
+    *html += "<table><tr><th>Hostname</th><th>Device ID</th><th>MAC</th><th>IP</th><th>Model</th><th>Client site</th><th>Reason</th><th>Suggested parent</th></tr>\n";
+    for entry in parentless {
+        let suggestion = match &entry.suggested_parent {
+            Some(s) => format!("{} ({})", s.site_name, s.basis),
+            None => String::new(),
+        };
+        *html += "<tr>";
+        for value in [
+            &entry.hostname,
+            &entry.device_id,
+            &entry.mac,
+            &entry.ip,
+            &entry.model,
+            &entry.client_site,
+            &entry.reasons.join("; "),
+            &suggestion,
+        ] {
+            *html += &format!("<td>{}</td>", escape(value));
+        }
+        *html += "</tr>\n";
+    }
+    *html += "</table>\n";
+}
+
+fn list_html(title: &str, items: &[String], html: &mut String) {
+    *html += &format!("<h2>{title} ({})</h2>\n", items.len());
+    if items.is_empty() {
+        *html += "<p class=\"muted\">None.</p>\n";
+        return;
+    }
+    *html += "<ul>\n";
+    for item in items {
+        *html += &format!("<li>{}</li>\n", escape(item));
+    }
+    *html += "</ul>\n";
+}
+
+/// Renders a self-contained HTML page describing a run: headline counts,
+/// the site tree as collapsible sections with each site's and AP's circuits
+/// and provisioned bandwidth against capacity (colored by `limits`), the
+/// oversubscribed nodes from `plan`, if given, the devices and sites left
+/// out of the tree (`excluded`), and the circuits and multi-homed sites
+/// recorded in `metrics`.
+pub fn html_report(
+    roots: &[LqSite],
+    clients: &[LqClientSite],
+    limits: &CapacityLimits,
+    plan: Option<&CapacityPlan>,
+    metrics: &RunMetrics,
+    excluded: &Excluded,
+) -> String {
+    let (site_loads, ap_loads) = node_loads(roots, clients);
+    let loads = Loads {
+        sites: site_loads.iter().map(|l| (l.id.as_str(), l)).collect(),
+        access_points: ap_loads.iter().map(|l| (l.id.as_str(), l)).collect(),
+    };
+    let rejected = metrics.recorded(Counter::RejectedCircuits);
+    let multihomed = metrics.recorded(Counter::UnresolvedMultihomedSites);
+
+    let mut html = format!(
+        "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n<title>LibreQoS integration report</title>\n<style>\n{STYLE}\n</style>\n</head>\n<body>\n<h1>LibreQoS integration report</h1>\n<p class=\"muted\">Generated {} UTC</p>\n",
+        chrono::Utc::now().format("%Y-%m-%d %H:%M:%S")
+    );
+
+    html += "<h2>Summary</h2>\n<table>\n";
+    for (label, value) in [
+        ("Sites", site_loads.len()),
+        ("Access points", ap_loads.len()),
+        ("Circuits", clients.len()),
+        ("Devices", clients.iter().map(|c| c.devices.len()).sum()),
+        ("Parentless devices", excluded.parentless.len()),
+        ("Rejected sites", excluded.rejected_sites.len()),
+        ("Rejected circuits", rejected.len()),
+        ("Unresolved multi-homed sites", multihomed.len()),
+    ] {
+        html += &format!("<tr><th>{label}</th><td class=\"n\">{value}</td></tr>\n");
+    }
+    html += "</table>\n";
+
+    html += &format!(
+        "<h2>Network</h2>\n<p class=\"muted\">Provisioned is the total maximum rate of the circuits beneath each node. Amber is over {}x capacity, red over {}x.</p>\n",
+        limits.warn_ratio, limits.critical_ratio
+    );
+    for root in roots.iter() {
+        site_html(root, 0, &loads, limits, &mut html);
+    }
+
+    if let Some(plan) = plan {
+        plan_html(plan, &mut html);
+    }
+
+    parentless_html(&excluded.parentless, &mut html);
+    list_html("Rejected sites", &excluded.rejected_sites, &mut html);
+    list_html("Rejected circuits", &rejected, &mut html);
+    list_html("Unresolved multi-homed sites", &multihomed, &mut html);
+    html += "</body>\n</html>\n";
+    html
+}
+
+/// Writes the HTML report, if one is configured.
+pub fn write_report(
+    config: &ReportConfig,
+    limits: &CapacityLimits,
+    plan: Option<&CapacityPlan>,
+    roots: &[LqSite],
+    clients: &[LqClientSite],
+    metrics: &RunMetrics,
+    excluded: &Excluded,
+) -> Result<()> {
+    match &config.html {
+        Some(path) => write_atomic(
+            path,
+            html_report(roots, clients, limits, plan, metrics, excluded).as_bytes(),
+        ),
+        None => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{LqClientDevice, SuggestedParent, TopologyBuilder};
+    use std::collections::BTreeMap;
+
+    fn site(id: &str, name: &str, parent: Option<&str>) -> (String, LqSite) {
+        (
+            id.to_string(),
+            LqSite {
+                id: id.to_string(),
+                name: name.to_string(),
+                parent: parent.map(str::to_string),
+                children: Vec::new(),
+                access_points: BTreeMap::new(),
+                download_mbps: 1_000,
+                upload_mbps: 1_000,
+            },
+        )
+    }
+
+    fn circuit(id: &str, ap: &str, site: &str) -> LqClientSite {
+        LqClientSite {
+            id: id.to_string(),
+            name: id.to_string(),
+            download: 0,
+            upload: 0,
+            suspended: false,
+            devices: vec![LqClientDevice {
+                id: id.to_string(),
+                hostname: id.to_string(),
+                mac: String::new(),
+                model: String::new(),
+                ip: String::new(),
+                access_point_id: ap.to_string(),
+                access_point_name: ap.to_string(),
+                parent_site_id: site.to_string(),
+                parent_site_name: String::new(),
+                upload: 20_000_000,
+                download: 100_000_000,
+                is_access_point: false,
+                is_bridge: false,
+            }],
+        }
+    }
+
+    #[test]
+    fn report_lists_the_tree_and_what_was_left_out() {
+        let sites = HashMap::from([
+            site("tower", "Tower", None),
+            site("relay", "Relay & Co", Some("tower")),
+            site("orphan", "Orphan", Some("missing")),
+        ]);
+        let mut clients = vec![circuit("a", "Sector", "relay")];
+        let topology = TopologyBuilder::new(sites)
+            .build("Tower", &mut clients)
+            .unwrap();
+        let parentless = vec![ParentlessEntry {
+            device_id: "d1".to_string(),
+            hostname: "<script>&\"".to_string(),
+            mac: String::new(),
+            ip: "100.64.0.9".to_string(),
+            model: String::new(),
+            client_site: "Smith".to_string(),
+            reasons: vec!["no parent site", "no access point"],
+            suggested_parent: Some(SuggestedParent {
+                site_id: "relay".to_string(),
+                site_name: "Relay & Co".to_string(),
+                basis: "data link to Sector".to_string(),
+            }),
+        }];
+        let excluded = Excluded::new(&topology, parentless);
+        let html = html_report(
+            &topology.roots,
+            &clients,
+            &CapacityLimits::default(),
+            None,
+            &RunMetrics::default(),
+            &excluded,
+        );
+
+        assert!(!html.contains("<script>"));
+        assert!(html.contains("<td>&lt;script&gt;&amp;&quot;</td>"));
+        assert!(html.contains("<tr><th>Parentless devices</th><td class=\"n\">1</td></tr>"));
+        assert!(html.contains("<tr><th>Rejected sites</th><td class=\"n\">1</td></tr>"));
+        assert!(html.contains("<h2>Parentless devices (1)</h2>"));
+        assert!(html.contains("<td>no parent site; no access point</td>"));
+        assert!(html.contains("<td>Relay &amp; Co (data link to Sector)</td>"));
+        assert!(html.contains("<h2>Rejected sites (1)</h2>\n<ul>\n<li>Orphan</li>"));
+        assert!(html.contains("<h2>Rejected circuits (0)</h2>"));
+        assert!(html.contains("\">Relay &amp; Co</span> &mdash; 1 circuits"));
+        assert!(html.contains(">Sector</td><td class=\"n\">1</td><td>100/20 of"));
+        assert_eq!(
+            html.matches("<details").count(),
+            html.matches("</details>").count()
+        );
+    }
+}
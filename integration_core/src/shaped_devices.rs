@@ -0,0 +1,62 @@
+use crate::{client_site::output_order, publish::write_atomic, LqClientSite};
+use anyhow::Result;
+use std::path::Path;
+
+/// Header row for LibreQoS's `ShapedDevices.csv`.
+pub const SHAPED_DEVICES_HEADER: &str = "Circuit ID,Circuit Name,Device ID,Device Name,Parent Node,MAC,IPv4,IPv6,Download Min Mbps,Upload Min Mbps,Download Max Mbps,Upload Max Mbps,Comment";
+
+/// Renders clients in the `ShapedDevices.csv` format. Each client site is a
+/// circuit, and each of its devices is a row. Rates of zero are treated as
+/// unlimited (1 Gbps), and minimums are a quarter of the maximum. Rows are
+/// sorted by circuit name, then device ID.
+pub fn shaped_devices_csv(clients: &[LqClientSite]) -> String {
+    let mut csv = format!("{SHAPED_DEVICES_HEADER}\n");
+    for (circuit, devices) in output_order(clients) {
+        for device in devices {
+            let dl_mbps = bps_to_mbps(device.download);
+            let ul_mbps = bps_to_mbps(device.upload);
+            let parent = device.parent_node();
+            let (ipv4, ipv6) = split_ip(&device.ip);
+            csv += &format!(
+                "{},{},{},{},{},{},{},{},{},{},{},{},\n",
+                circuit.id,
+                circuit.name.replace(',', "_"),
+                device.id,
+                device.hostname.replace(',', "_"),
+                parent,
+                device.mac,
+                ipv4,
+                ipv6,
+                (dl_mbps as f32 / 4.0).ceil() as usize,
+                (ul_mbps as f32 / 4.0).ceil() as usize,
+                dl_mbps,
+                ul_mbps,
+            );
+        }
+    }
+    csv
+}
+
+/// Writes `ShapedDevices.csv` to the given path.
+pub fn write_shaped_devices_csv<P: AsRef<Path>>(clients: &[LqClientSite], path: P) -> Result<()> {
+    write_atomic(path, shaped_devices_csv(clients).as_bytes())
+}
+
+pub(crate) fn bps_to_mbps(bps: usize) -> usize {
+    if bps == 0 {
+        1_000
+    } else {
+        usize::max(1, bps / 1_000_000)
+    }
+}
+
+/// Places the address in the IPv4 or IPv6 column. A prefix length, such as
+/// `100.64.1.0/29`, is kept: LibreQoS shapes the whole prefix. Sources strip
+/// interface masks from single addresses before this point.
+fn split_ip(ip: &str) -> (String, String) {
+    if ip.contains(':') {
+        (String::new(), ip.to_string())
+    } else {
+        (ip.to_string(), String::new())
+    }
+}
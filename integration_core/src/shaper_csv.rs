@@ -1,4 +1,4 @@
-use super::LqClientSite;
+use crate::{client_site::output_order, publish::write_atomic, LqClientSite};
 use anyhow::Result;
 
 fn strip_ip(ip: &str) -> String {
@@ -9,24 +9,21 @@ fn strip_ip(ip: &str) -> String {
     }
 }
 
-pub fn write_shaper_csv(clients: &[LqClientSite]) -> Result<()> {
+/// Renders clients in the legacy `Shaper.csv` format.
+pub fn shaper_csv(clients: &[LqClientSite]) -> String {
     //let mut csv =
     //    "ID,AP,MAC,Hostname,IPv4,IPv6,Download Min,Upload Min, Download Max, Upload Max\n"
     //        .to_string();
     let mut csv = "deviceID, ParentNode, mac, hostname,ipv4, ipv6, downloadMin, uploadMin, downloadMax, uploadMax\n".to_string();
-    clients.iter().for_each(|s| {
-        s.devices.iter().for_each(|c| {
+    output_order(clients).into_iter().for_each(|(_, devices)| {
+        devices.into_iter().for_each(|c| {
             // If QoS returned 0 for speed plan, change it to 1gbps.
             let dl = if c.download == 0 { 1_000 } else { c.download };
             let ul = if c.upload == 0 { 1_000 } else { c.upload };
             let dl_mbps = dl / 1_000_000; // Convert to Mbps
             let ul_mbps = ul / 1_000_000;
 
-            let ap = if c.access_point_name.is_empty() {
-                format!("{}-NoAP", s.name.replace(",", "_"))
-            } else {
-                c.access_point_name.to_string()
-            };
+            let ap = c.parent_node();
             let hostname = &c.hostname;
             let ipv4 = strip_ip(&c.ip);
             let ipv6 = "";
@@ -43,9 +40,10 @@ pub fn write_shaper_csv(clients: &[LqClientSite]) -> Result<()> {
         });
     });
 
-    use std::fs::File;
-    use std::io::Write;
-    let mut f = File::create("Shaper.csv")?;
-    f.write_all(csv.as_bytes())?;
-    Ok(())
+    csv
+}
+
+/// Writes `Shaper.csv` to the current directory.
+pub fn write_shaper_csv(clients: &[LqClientSite]) -> Result<()> {
+    write_atomic("Shaper.csv", shaper_csv(clients).as_bytes())
 }
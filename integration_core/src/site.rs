@@ -0,0 +1,97 @@
+use crate::LqAccessPoint;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use tracing::warn;
+
+#[derive(Debug, Clone)]
+pub struct LqSite {
+    pub id: String,
+    pub name: String,
+    pub parent: Option<String>,
+    pub children: Vec<LqSite>,
+    pub access_points: BTreeMap<String, LqAccessPoint>,
+    pub download_mbps: usize,
+    pub upload_mbps: usize,
+}
+
+impl LqSite {
+    /// Attaches every site whose parent is this site, recursively, in name
+    /// order. A site that is already in the tree (because its parents form a
+    /// cycle) isn't attached again.
+    pub fn take_children(&mut self, sites: &HashMap<String, LqSite>) {
+        self.attach_children(sites, &mut HashSet::from([self.id.clone()]));
+    }
+
+    fn attach_children(&mut self, sites: &HashMap<String, LqSite>, visited: &mut HashSet<String>) {
+        let mut children: Vec<&LqSite> = sites
+            .values()
+            .filter(|s| s.parent.as_ref() == Some(&self.id))
+            .collect();
+        children.sort_by(|a, b| a.name.cmp(&b.name).then_with(|| a.id.cmp(&b.id)));
+        for child in children {
+            if !visited.insert(child.id.clone()) {
+                warn!(
+                    site = child.name,
+                    parent = self.name,
+                    "Site parents form a cycle; not attaching the site again"
+                );
+                continue;
+            }
+            let mut child = child.clone();
+            child.attach_children(sites, visited);
+            self.children.push(child);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn site(id: &str, parent: Option<&str>) -> (String, LqSite) {
+        let site = LqSite {
+            id: id.to_string(),
+            name: id.to_uppercase(),
+            parent: parent.map(|p| p.to_string()),
+            children: Vec::new(),
+            access_points: BTreeMap::new(),
+            download_mbps: 100,
+            upload_mbps: 100,
+        };
+        (id.to_string(), site)
+    }
+
+    fn shape(site: &LqSite) -> String {
+        let children: Vec<String> = site.children.iter().map(shape).collect();
+        if children.is_empty() {
+            site.name.clone()
+        } else {
+            format!("{}({})", site.name, children.join(","))
+        }
+    }
+
+    #[test]
+    fn children_are_attached_in_name_order() {
+        let sites = HashMap::from([
+            site("root", None),
+            site("b", Some("root")),
+            site("a", Some("root")),
+            site("c", Some("a")),
+        ]);
+        let mut root = sites["root"].clone();
+        root.take_children(&sites);
+        assert_eq!(shape(&root), "ROOT(A(C),B)");
+    }
+
+    #[test]
+    fn parent_cycles_are_not_followed() {
+        // The root's own parent is in its subtree.
+        let sites = HashMap::from([
+            site("root", Some("b")),
+            site("a", Some("root")),
+            site("b", Some("a")),
+        ]);
+        let mut root = sites["root"].clone();
+        root.take_children(&sites);
+        assert_eq!(shape(&root), "ROOT(A(B))");
+    }
+}
@@ -0,0 +1,112 @@
+use crate::{LqClientSite, LqSite};
+use std::{collections::HashMap, fmt};
+
+/// A problem found in generated output that LibreQoS would reject or
+/// misinterpret.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ValidationIssue {
+    /// Two nodes in the tree share a name, which `network.json` can't represent.
+    DuplicateNodeName(String),
+    /// The same IP address is assigned to more than one device.
+    DuplicateIp { ip: String, devices: Vec<String> },
+    /// The same device ID appears more than once.
+    DuplicateDeviceId(String),
+    /// A device has no IP address, so it can't be shaped.
+    MissingIp { device: String },
+    /// A device's parent node isn't present in the tree.
+    UnknownParent { device: String, parent: String },
+}
+
+impl fmt::Display for ValidationIssue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::DuplicateNodeName(name) => write!(f, "Duplicate node name: {name}"),
+            Self::DuplicateIp { ip, devices } => {
+                write!(f, "IP {ip} is used by: {}", devices.join(", "))
+            }
+            Self::DuplicateDeviceId(id) => write!(f, "Duplicate device ID: {id}"),
+            Self::MissingIp { device } => write!(f, "Device {device} has no IP address"),
+            Self::UnknownParent { device, parent } => {
+                write!(f, "Device {device} has unknown parent node {parent}")
+            }
+        }
+    }
+}
+
+/// Checks built trees and the client list that will be written alongside
+/// them. An empty result means the output is consistent.
+pub fn validate(roots: &[LqSite], clients: &[LqClientSite]) -> Vec<ValidationIssue> {
+    let mut issues = Vec::new();
+
+    let mut node_names = HashMap::<String, usize>::new();
+    for root in roots.iter() {
+        count_node_names(root, &mut node_names);
+    }
+    let mut duplicates: Vec<&String> = node_names
+        .iter()
+        .filter(|(_, n)| **n > 1)
+        .map(|(name, _)| name)
+        .collect();
+    duplicates.sort();
+    issues.extend(
+        duplicates
+            .into_iter()
+            .map(|name| ValidationIssue::DuplicateNodeName(name.clone())),
+    );
+
+    let mut ips = HashMap::<String, Vec<String>>::new();
+    let mut device_ids = HashMap::<&str, usize>::new();
+    for device in clients.iter().flat_map(|c| c.devices.iter()) {
+        *device_ids.entry(&device.id).or_default() += 1;
+        let ip = device.ip.split('/').next().unwrap_or_default();
+        if ip.is_empty() {
+            issues.push(ValidationIssue::MissingIp {
+                device: device.hostname.clone(),
+            });
+        } else {
+            ips.entry(ip.to_string())
+                .or_default()
+                .push(device.hostname.clone());
+        }
+        let parent = device.parent_node();
+        if !node_names.contains_key(&parent) {
+            issues.push(ValidationIssue::UnknownParent {
+                device: device.hostname.clone(),
+                parent,
+            });
+        }
+    }
+
+    let mut duplicate_ips: Vec<(String, Vec<String>)> =
+        ips.into_iter().filter(|(_, d)| d.len() > 1).collect();
+    duplicate_ips.sort();
+    issues.extend(
+        duplicate_ips
+            .into_iter()
+            .map(|(ip, devices)| ValidationIssue::DuplicateIp { ip, devices }),
+    );
+
+    let mut duplicate_ids: Vec<&str> = device_ids
+        .into_iter()
+        .filter(|(_, n)| *n > 1)
+        .map(|(id, _)| id)
+        .collect();
+    duplicate_ids.sort();
+    issues.extend(
+        duplicate_ids
+            .into_iter()
+            .map(|id| ValidationIssue::DuplicateDeviceId(id.to_string())),
+    );
+
+    issues
+}
+
+fn count_node_names(site: &LqSite, names: &mut HashMap<String, usize>) {
+    *names.entry(site.name.clone()).or_default() += 1;
+    for ap in site.access_points.values() {
+        *names.entry(ap.name.clone()).or_default() += 1;
+    }
+    for child in site.children.iter() {
+        count_node_names(child, names);
+    }
+}
@@ -0,0 +1,43 @@
+use clap::{Parser, Subcommand};
+
+/// Builds LibreQoS shaping files and publishes them to the output directory.
+#[derive(Parser)]
+#[command(version)]
+pub struct Cli {
+    /// Log more detail; repeat for even more.
+    #[arg(short, long, action = clap::ArgAction::Count)]
+    pub verbose: u8,
+
+    /// Log less; repeat for even less.
+    #[arg(short, long, action = clap::ArgAction::Count)]
+    pub quiet: u8,
+
+    /// Write logs as JSON lines.
+    #[arg(long)]
+    pub log_json: bool,
+
+    /// Publish even if the outputs changed more than the configured guard allows.
+    #[arg(long)]
+    pub force: bool,
+
+    #[command(subcommand)]
+    pub command: Option<Command>,
+}
+
+impl Cli {
+    /// Steps to move the configured log level by.
+    pub fn verbosity(&self) -> i8 {
+        self.verbose as i8 - self.quiet as i8
+    }
+}
+
+#[derive(Subcommand)]
+pub enum Command {
+    /// List saved generations of the published files, newest first.
+    Generations,
+    /// Restore a saved generation of the published files.
+    Rollback {
+        /// The generation to restore. Defaults to the newest.
+        generation: Option<String>,
+    },
+}
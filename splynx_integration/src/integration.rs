@@ -0,0 +1,80 @@
+use crate::{
+    splynx::*,
+    topology::{build_inventory, root_site},
+};
+use anyhow::Result;
+use async_trait::async_trait;
+use integration_core::{Integration, Inventory, RunMetrics};
+use std::time::Instant;
+use tokio::join;
+use tracing::info;
+
+/// Builds an inventory from Splynx customers and internet services.
+pub struct SplynxIntegration {
+    keys: Keys,
+}
+
+impl SplynxIntegration {
+    pub fn new(keys: Keys) -> Self {
+        Self { keys }
+    }
+}
+
+/// Connects to Splynx and downloads customers, internet services, tariffs,
+/// routers and IP assignments.
+/// Please ensure that you setup `keys.ron` correctly, or this won't work.
+async fn pre_load_splynx(
+    keys: &Keys,
+) -> Result<(
+    Vec<Customer>,
+    Vec<InternetService>,
+    Vec<Tariff>,
+    Vec<Router>,
+    Vec<IpAssignment>,
+)> {
+    let (key, secret, url) = keys.splynx();
+    let customers_future =
+        splynx_request_get_vec::<Customer>("customers/customer", key, secret, url);
+    let services_future = splynx_request_get_vec::<InternetService>(
+        "customers/customer/0/internet-services",
+        key,
+        secret,
+        url,
+    );
+    let tariffs_future = splynx_request_get_vec::<Tariff>("tariffs/internet", key, secret, url);
+    let routers_future = splynx_request_get_vec::<Router>("networking/routers", key, secret, url);
+    let ips_future = splynx_request_get_vec::<IpAssignment>("networking/ipv4-ip", key, secret, url);
+
+    let (customers, services, tariffs, routers, ips) = join!(
+        customers_future,
+        services_future,
+        tariffs_future,
+        routers_future,
+        ips_future
+    );
+    Ok((customers?, services?, tariffs?, routers?, ips?))
+}
+
+#[async_trait]
+impl Integration for SplynxIntegration {
+    fn name(&self) -> &str {
+        "splynx"
+    }
+
+    async fn fetch_inventory(&self, metrics: &RunMetrics) -> Result<Inventory> {
+        info!("Fetching customers, services, tariffs and routers from Splynx");
+        let start_fetch = Instant::now();
+        let (customers, services, tariffs, routers, ips) = pre_load_splynx(&self.keys).await?;
+        info!(elapsed = ?start_fetch.elapsed(), "Fetched all Splynx data");
+
+        build_inventory(
+            root_site(self.keys.root(), self.keys.root_rates()),
+            &customers,
+            &services,
+            &tariffs,
+            &routers,
+            &ips,
+            metrics,
+        )
+    }
+}
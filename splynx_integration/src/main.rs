@@ -0,0 +1,131 @@
+mod cli;
+mod integration;
+mod splynx;
+mod topology;
+use std::time::Instant;
+
+use anyhow::Result;
+use clap::Parser;
+use cli::{Cli, Command};
+use integration::SplynxIntegration;
+use integration_core::{
+    assign_unique_names, capacity_plan, diagnose_parentless, fetch_inventories, generations,
+    init_logging, network_json, publish, rollback, shaped_devices_csv, shaper_csv, validate,
+    write_capacity_plan, write_graphs, write_metrics, write_report, Excluded, Integration,
+    Inventory, LqClientSite, LqSite, RunMetrics, TopologyBuilder,
+};
+use splynx::Keys;
+use tracing::{info, info_span, warn, Instrument};
+
+#[tokio::main]
+async fn main() -> Result<()> {
+    let cli = Cli::parse();
+    let start = Instant::now();
+    let keys = Keys::load()?;
+    init_logging(keys.log(), cli.verbosity(), cli.log_json)?;
+    match cli.command {
+        Some(Command::Generations) => {
+            for generation in generations(keys.output())? {
+                println!("{generation}");
+            }
+            return Ok(());
+        }
+        Some(Command::Rollback { generation }) => {
+            let restored = rollback(keys.output(), generation.as_deref())?;
+            info!(generation = restored, "Restored generation");
+            return Ok(());
+        }
+        None => {}
+    }
+    let metrics = RunMetrics::default();
+    let result = rebuild(&cli, &keys, &metrics).await;
+    let topology = result.as_ref().ok();
+    let topology = topology.map(|(roots, clients)| (roots.as_slice(), clients.as_slice()));
+    if let Err(e) = write_metrics(keys.metrics(), &metrics, topology) {
+        warn!("Unable to write metrics: {e}");
+    }
+    result?;
+
+    // Complete
+    info!(elapsed = ?start.elapsed(), "Completed topology rebuild");
+    Ok(())
+}
+
+/// Fetches, builds and publishes the topology, returning the tree and
+/// circuits that were published.
+async fn rebuild(
+    cli: &Cli,
+    keys: &Keys,
+    metrics: &RunMetrics,
+) -> Result<(Vec<LqSite>, Vec<LqClientSite>)> {
+    let sources: Vec<Box<dyn Integration>> = vec![Box::new(SplynxIntegration::new(keys.clone()))];
+
+    let mut inventory = Inventory::default();
+    for (_, source_inventory) in fetch_inventories(&sources, metrics)
+        .instrument(info_span!("fetch"))
+        .await?
+    {
+        inventory.extend(source_inventory);
+    }
+
+    let (network_map, clients, excluded) = info_span!("topology").in_scope(
+        || -> Result<(Vec<LqSite>, Vec<LqClientSite>, Excluded)> {
+            let mut clients = inventory.circuits;
+            let (download_mbps, upload_mbps) = keys.root_rates();
+            let topology = TopologyBuilder::new(inventory.sites)
+                .access_point_rates(keys.router_rates().clone())
+                .default_rates(download_mbps, upload_mbps)
+                .build(keys.root(), &mut clients)?;
+            let parentless = diagnose_parentless(
+                &topology.parentless,
+                &clients,
+                &topology.sites,
+                &inventory.locations,
+            );
+            let excluded = Excluded::new(&topology, parentless);
+            let mut network_map = topology.roots;
+            assign_unique_names(&mut network_map, &mut clients, keys.naming());
+            for issue in validate(&network_map, &clients) {
+                warn!("{issue}");
+            }
+            Ok((network_map, clients, excluded))
+        },
+    )?;
+
+    info_span!("write").in_scope(|| -> Result<()> {
+        let mut outputs = inventory.files;
+        outputs.add("network.json", network_json(&network_map));
+        outputs.add("Shaper.csv", shaper_csv(&clients));
+        outputs.add("ShapedDevices.csv", shaped_devices_csv(&clients));
+        outputs.add_summary(&network_map)?;
+        let outcome = publish(keys.output(), &outputs, cli.force)?;
+        if !outcome.changed {
+            info!("Outputs unchanged; nothing published");
+        } else if let Some(backup) = outcome.backup {
+            info!(previous = backup, "Published new outputs");
+        } else {
+            info!("Published new outputs");
+        }
+        // Only written once the guard has let the run through.
+        write_graphs(keys.graph(), keys.capacity(), &network_map, &clients)?;
+        let plan_config = keys.capacity_plan();
+        let plan = capacity_plan(
+            &network_map,
+            &clients,
+            keys.capacity(),
+            plan_config.top_circuits,
+        );
+        write_capacity_plan(plan_config, &plan)?;
+        let report_plan = plan_config.in_report.then_some(&plan);
+        write_report(
+            keys.report(),
+            keys.capacity(),
+            report_plan,
+            &network_map,
+            &clients,
+            metrics,
+            &excluded,
+        )
+    })?;
+    Ok((network_map, clients))
+}
@@ -0,0 +1,17 @@
+use super::string_or_number;
+use serde::Deserialize;
+
+#[derive(Deserialize, Debug)]
+pub struct Customer {
+    #[serde(deserialize_with = "string_or_number")]
+    pub id: String,
+    pub name: String,
+    pub status: String,
+}
+
+impl Customer {
+    /// Blocked customers keep their services, but shouldn't be shaped as active.
+    pub fn is_active(&self) -> bool {
+        self.status == "active"
+    }
+}
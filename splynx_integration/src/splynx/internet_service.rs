@@ -0,0 +1,27 @@
+use super::string_or_number;
+use serde::Deserialize;
+
+#[derive(Deserialize, Debug)]
+pub struct InternetService {
+    #[serde(deserialize_with = "string_or_number")]
+    pub id: String,
+    #[serde(deserialize_with = "string_or_number")]
+    pub customer_id: String,
+    #[serde(deserialize_with = "string_or_number")]
+    pub tariff_id: String,
+    #[serde(default, deserialize_with = "string_or_number")]
+    pub router_id: String,
+    pub status: String,
+    #[serde(default)]
+    pub login: String,
+    #[serde(default)]
+    pub ipv4: String,
+    #[serde(default)]
+    pub mac: String,
+}
+
+impl InternetService {
+    pub fn is_active(&self) -> bool {
+        self.status == "active"
+    }
+}
@@ -0,0 +1,9 @@
+use super::string_or_number;
+use serde::Deserialize;
+
+#[derive(Deserialize, Debug)]
+pub struct IpAssignment {
+    pub ip: String,
+    #[serde(default, deserialize_with = "string_or_number")]
+    pub customer_id: String,
+}
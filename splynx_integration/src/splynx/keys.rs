@@ -0,0 +1,120 @@
+use anyhow::{Error, Result};
+use integration_core::{
+    CapacityLimits, CapacityPlanConfig, GraphConfig, LogConfig, MetricsConfig, NodeNaming,
+    OutputConfig, ReportConfig,
+};
+use ron::de::from_reader;
+use serde::{Deserialize, Serialize};
+use std::{collections::HashMap, fs::File, path::Path};
+
+/// Key store structure
+#[derive(Clone, Serialize, Deserialize, Default)]
+pub struct Keys {
+    api_key: String,
+    api_secret: String,
+    url: String,
+    root_site_name: String,
+    /// Capacity of the root site, in Mbps. Defaults to 1000.
+    #[serde(default)]
+    root_download_mbps: Option<usize>,
+    #[serde(default)]
+    root_upload_mbps: Option<usize>,
+    /// (download, upload) Mbps of each router's node, keyed by Splynx router
+    /// ID. Routers that aren't listed get the root's capacity.
+    #[serde(default)]
+    router_rates: HashMap<String, (usize, usize)>,
+    /// How tree nodes are named in the output.
+    #[serde(default)]
+    naming: NodeNaming,
+    /// Where `network.json` and the shaper files are published.
+    #[serde(default)]
+    output: OutputConfig,
+    /// Log level and format.
+    #[serde(default)]
+    log: LogConfig,
+    /// Where run metrics are written for Prometheus.
+    #[serde(default)]
+    metrics: MetricsConfig,
+    /// Diagrams of the site tree.
+    #[serde(default)]
+    graph: GraphConfig,
+    /// Oversubscription ratios at which sites and APs are flagged.
+    #[serde(default)]
+    capacity: CapacityLimits,
+    /// A human-readable report of each run.
+    #[serde(default)]
+    report: ReportConfig,
+    /// Where the capacity-planning report is written.
+    #[serde(default)]
+    capacity_plan: CapacityPlanConfig,
+}
+
+impl Keys {
+    pub fn load() -> Result<Self> {
+        let path = Path::new("keys.ron");
+        if !path.exists() {
+            return Err(Error::msg("Please setup keys.ron"));
+        }
+
+        let f = File::open(path)?;
+        let mut keys: Self = from_reader(f)?;
+        keys.url = format!("{}/api/2.0/admin", keys.url.trim_end_matches('/'));
+        keys.graph.resolve_paths(&keys.output);
+        keys.capacity_plan.resolve_paths(&keys.output);
+        keys.report.resolve_paths(&keys.output);
+        Ok(keys)
+    }
+
+    /// Returns the API key, secret and base URL.
+    pub fn splynx(&self) -> (&str, &str, &str) {
+        (&self.api_key, &self.api_secret, &self.url)
+    }
+
+    pub fn root(&self) -> &str {
+        &self.root_site_name
+    }
+
+    /// The root site's (download, upload) capacity in Mbps.
+    pub fn root_rates(&self) -> (usize, usize) {
+        (
+            self.root_download_mbps.unwrap_or(1_000),
+            self.root_upload_mbps.unwrap_or(1_000),
+        )
+    }
+
+    pub fn router_rates(&self) -> &HashMap<String, (usize, usize)> {
+        &self.router_rates
+    }
+
+    pub fn naming(&self) -> &NodeNaming {
+        &self.naming
+    }
+
+    pub fn output(&self) -> &OutputConfig {
+        &self.output
+    }
+
+    pub fn log(&self) -> &LogConfig {
+        &self.log
+    }
+
+    pub fn metrics(&self) -> &MetricsConfig {
+        &self.metrics
+    }
+
+    pub fn graph(&self) -> &GraphConfig {
+        &self.graph
+    }
+
+    pub fn capacity(&self) -> &CapacityLimits {
+        &self.capacity
+    }
+
+    pub fn report(&self) -> &ReportConfig {
+        &self.report
+    }
+
+    pub fn capacity_plan(&self) -> &CapacityPlanConfig {
+        &self.capacity_plan
+    }
+}
@@ -0,0 +1,15 @@
+mod customer;
+mod internet_service;
+mod ip_assignment;
+mod keys;
+mod rest;
+mod router;
+mod tariff;
+
+pub use customer::Customer;
+pub use internet_service::InternetService;
+pub use ip_assignment::IpAssignment;
+pub use keys::Keys;
+pub use rest::*;
+pub use router::Router;
+pub use tariff::Tariff;
@@ -0,0 +1,48 @@
+use serde::{de::DeserializeOwned, Deserialize, Deserializer};
+
+/// Submits a request to the Splynx API, returning a deserialized vector of type T.
+/// Authenticates with HTTP basic auth, using the API key and secret.
+pub async fn splynx_request_get_vec<T>(
+    url: &str,
+    key: &str,
+    secret: &str,
+    api: &str,
+) -> Result<Vec<T>, reqwest::Error>
+where
+    T: DeserializeOwned,
+{
+    let full_url = format!("{}/{}", api, url);
+    let client = reqwest::Client::new();
+
+    let res = client
+        .get(&full_url)
+        .header("'Content-Type", "application/json")
+        .basic_auth(key, Some(secret))
+        .send()
+        .await?;
+
+    res.json::<Vec<T>>().await
+}
+
+/// Splynx isn't consistent about quoting numbers, so IDs and speeds can arrive
+/// as either JSON strings or numbers (or null). This normalizes them to strings.
+pub fn string_or_number<'de, D>(deserializer: D) -> Result<String, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum StringOrNumber {
+        String(String),
+        Int(i64),
+        Float(f64),
+        Null,
+    }
+
+    Ok(match StringOrNumber::deserialize(deserializer)? {
+        StringOrNumber::String(s) => s,
+        StringOrNumber::Int(n) => n.to_string(),
+        StringOrNumber::Float(n) => n.to_string(),
+        StringOrNumber::Null => String::new(),
+    })
+}
@@ -0,0 +1,9 @@
+use super::string_or_number;
+use serde::Deserialize;
+
+#[derive(Deserialize, Debug)]
+pub struct Router {
+    #[serde(deserialize_with = "string_or_number")]
+    pub id: String,
+    pub title: String,
+}
@@ -0,0 +1,25 @@
+use super::string_or_number;
+use serde::Deserialize;
+
+#[derive(Deserialize, Debug)]
+pub struct Tariff {
+    #[serde(deserialize_with = "string_or_number")]
+    pub id: String,
+    /// Download speed in kbps
+    #[serde(deserialize_with = "string_or_number")]
+    pub speed_download: String,
+    /// Upload speed in kbps
+    #[serde(deserialize_with = "string_or_number")]
+    pub speed_upload: String,
+}
+
+impl Tariff {
+    /// Returns (download, upload) in bits per second, to match `LqClientSite`.
+    pub fn rates_bps(&self) -> (usize, usize) {
+        let kbps = |s: &str| s.trim().parse::<f64>().unwrap_or(0.0);
+        (
+            (kbps(&self.speed_download) * 1_000.0) as usize,
+            (kbps(&self.speed_upload) * 1_000.0) as usize,
+        )
+    }
+}
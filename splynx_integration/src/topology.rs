@@ -0,0 +1,244 @@
+use crate::splynx::{Customer, InternetService, IpAssignment, Router, Tariff};
+use anyhow::Result;
+use integration_core::{Counter, Inventory, LqClientDevice, LqClientSite, LqSite, RunMetrics};
+use std::collections::{BTreeMap, HashMap, HashSet};
+use tracing::warn;
+
+/// The single site at the top of the tree, with the given (download, upload)
+/// capacity in Mbps.
+pub fn root_site(name: &str, (download_mbps, upload_mbps): (usize, usize)) -> LqSite {
+    LqSite {
+        id: "splynx-root".to_string(),
+        name: name.to_string(),
+        parent: None,
+        children: Vec::new(),
+        access_points: BTreeMap::new(),
+        download_mbps,
+        upload_mbps,
+    }
+}
+
+/// Splynx doesn't have a site hierarchy, so the inventory is the `root` site,
+/// with customers attached to an access point node named after the router
+/// that their internet service is provisioned on.
+pub fn build_inventory(
+    root: LqSite,
+    customers: &[Customer],
+    services: &[InternetService],
+    tariffs: &[Tariff],
+    routers: &[Router],
+    ip_assignments: &[IpAssignment],
+    metrics: &RunMetrics,
+) -> Result<Inventory> {
+    let tariffs: HashMap<&str, &Tariff> = tariffs.iter().map(|t| (t.id.as_str(), t)).collect();
+    let routers: HashMap<&str, &Router> = routers.iter().map(|r| (r.id.as_str(), r)).collect();
+
+    let mut clients = Vec::new();
+    for customer in customers.iter().filter(|c| c.is_active()) {
+        let mut client = LqClientSite {
+            id: customer.id.clone(),
+            name: customer.name.replace(',', "_"),
+            download: 0,
+            upload: 0,
+            suspended: false,
+            devices: Vec::new(),
+        };
+
+        let customer_services: Vec<&InternetService> = services
+            .iter()
+            .filter(|s| s.customer_id == customer.id && s.is_active())
+            .collect();
+        // Services without their own address are given one of the
+        // customer's assignments that no other service uses, each only once.
+        let used: HashSet<&str> = customer_services.iter().map(|s| s.ipv4.as_str()).collect();
+        let mut spare_ips = ip_assignments
+            .iter()
+            .filter(|a| a.customer_id == customer.id && !used.contains(a.ip.as_str()))
+            .map(|a| a.ip.clone());
+
+        for service in customer_services {
+            let (download, upload) = if let Some(tariff) = tariffs.get(service.tariff_id.as_str()) {
+                tariff.rates_bps()
+            } else {
+                warn!(service = service.id, "Service has an unknown tariff");
+                (0, 0)
+            };
+            client.download = client.download.max(download);
+            client.upload = client.upload.max(upload);
+
+            let ip = if service.ipv4.is_empty() {
+                spare_ips.next().unwrap_or_default()
+            } else {
+                service.ipv4.clone()
+            };
+            if ip.is_empty() {
+                metrics.record(
+                    Counter::RejectedCircuits,
+                    &format!("{} (service {})", client.name, service.id),
+                );
+                warn!(service = service.id, "Service has no IP address");
+                continue;
+            }
+
+            // Services without a known router are left without a parent site,
+            // so the topology builder places them under "Unparented".
+            let (access_point_id, access_point_name, parent_site_id) =
+                if let Some(router) = routers.get(service.router_id.as_str()) {
+                    (
+                        router.id.clone(),
+                        router.title.replace(',', "_"),
+                        root.id.clone(),
+                    )
+                } else {
+                    (String::new(), String::new(), String::new())
+                };
+
+            let device = LqClientDevice {
+                id: format!("splynx-{}", service.id),
+                hostname: if service.login.is_empty() {
+                    client.name.clone()
+                } else {
+                    service.login.replace(',', "_")
+                },
+                mac: service.mac.clone(),
+                model: String::new(),
+                ip,
+                access_point_id,
+                access_point_name,
+                parent_site_id,
+                parent_site_name: root.name.clone(),
+                upload,
+                download,
+                is_access_point: false,
+                is_bridge: false,
+            };
+
+            client.devices.push(device);
+        }
+
+        if !client.devices.is_empty() {
+            clients.push(client);
+        }
+    }
+
+    Ok(Inventory {
+        sites: HashMap::from([(root.id.clone(), root)]),
+        circuits: clients,
+        ..Default::default()
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn service(id: &str, customer_id: &str, ipv4: &str) -> InternetService {
+        InternetService {
+            id: id.to_string(),
+            customer_id: customer_id.to_string(),
+            tariff_id: "t1".to_string(),
+            router_id: "r1".to_string(),
+            status: "active".to_string(),
+            login: String::new(),
+            ipv4: ipv4.to_string(),
+            mac: String::new(),
+        }
+    }
+
+    fn assignment(customer_id: &str, ip: &str) -> IpAssignment {
+        IpAssignment {
+            ip: ip.to_string(),
+            customer_id: customer_id.to_string(),
+        }
+    }
+
+    fn inventory(
+        services: &[InternetService],
+        ip_assignments: &[IpAssignment],
+        metrics: &RunMetrics,
+    ) -> Inventory {
+        let customers = [Customer {
+            id: "c1".to_string(),
+            name: "Customer".to_string(),
+            status: "active".to_string(),
+        }];
+        let tariffs = [Tariff {
+            id: "t1".to_string(),
+            speed_download: "10000".to_string(),
+            speed_upload: "1000".to_string(),
+        }];
+        let routers = [Router {
+            id: "r1".to_string(),
+            title: "Router".to_string(),
+        }];
+        build_inventory(
+            root_site("Root", (500, 100)),
+            &customers,
+            services,
+            &tariffs,
+            &routers,
+            ip_assignments,
+            metrics,
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn customer_ips_are_assigned_once() {
+        let services = [
+            service("1", "c1", ""),
+            service("2", "c1", "100.64.0.1"),
+            service("3", "c1", ""),
+            service("4", "c1", ""),
+        ];
+        let assignments = [
+            assignment("c1", "100.64.0.1"),
+            assignment("c2", "100.64.0.9"),
+            assignment("c1", "100.64.0.2"),
+            assignment("c1", "100.64.0.3"),
+        ];
+        // The service's own address isn't handed out again, and once the
+        // spare addresses run out the service is rejected.
+        let metrics = RunMetrics::default();
+        let inventory = inventory(&services, &assignments, &metrics);
+        let devices: Vec<(&str, &str)> = inventory
+            .circuits
+            .iter()
+            .flat_map(|c| c.devices.iter())
+            .map(|d| (d.id.as_str(), d.ip.as_str()))
+            .collect();
+        assert_eq!(
+            devices,
+            vec![
+                ("splynx-1", "100.64.0.2"),
+                ("splynx-2", "100.64.0.1"),
+                ("splynx-3", "100.64.0.3"),
+            ]
+        );
+        assert_eq!(
+            metrics.recorded(Counter::RejectedCircuits),
+            vec!["Customer (service 4)"]
+        );
+    }
+
+    #[test]
+    fn services_hang_off_their_router_beneath_the_root() {
+        let services = [service("1", "c1", "100.64.0.1")];
+        let inventory = inventory(&services, &[], &RunMetrics::default());
+        let root = &inventory.sites["splynx-root"];
+        assert_eq!(
+            (root.name.as_str(), root.download_mbps, root.upload_mbps),
+            ("Root", 500, 100)
+        );
+        let device = &inventory.circuits[0].devices[0];
+        assert_eq!(
+            (
+                device.access_point_id.as_str(),
+                device.access_point_name.as_str(),
+                device.parent_site_id.as_str(),
+            ),
+            ("r1", "Router", "splynx-root")
+        );
+        assert_eq!((device.download, device.upload), (10_000_000, 1_000_000));
+    }
+}
@@ -1,16 +1,14 @@
-mod client_site;
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
 
-pub use client_site::*;
-mod client_device;
-pub use client_device::*;
-mod csv;
 use crate::{
     topology::LqSite,
+    ucrm::CrmRates,
     unms::{DataLink, Device, Site},
 };
 use anyhow::Result;
-pub use csv::*;
+use integration_core::{Counter, RunMetrics};
+pub use integration_core::{LqClientDevice, LqClientSite};
+use tracing::{debug, warn};
 
 fn lookup_data_link(device: &mut LqClientDevice, all_data_links: &[DataLink]) -> Result<()> {
     //if !device.access_point_id.is_empty() {
@@ -35,7 +33,13 @@ fn lookup_data_link(device: &mut LqClientDevice, all_data_links: &[DataLink]) ->
     Ok(())
 }
 
-fn active_clients(all_sites: &[Site]) -> Vec<LqClientSite> {
+/// Client sites that are active, not suspended and have a rate. Sites
+/// without a rate are logged and counted as rejected in `metrics`.
+pub fn active_clients(
+    all_sites: &[Site],
+    crm: &CrmRates,
+    metrics: &RunMetrics,
+) -> Vec<LqClientSite> {
     all_sites
         .iter()
         .filter(|s| {
@@ -49,18 +53,19 @@ fn active_clients(all_sites: &[Site]) -> Vec<LqClientSite> {
             false
         })
         .filter(|s| s.is_active())
-        .filter_map(|s| s.as_lq_client_site())
+        .filter_map(|s| s.as_lq_client_site(crm, metrics))
+        .filter(|s| !s.suspended)
         .collect()
 }
 
 /// The easy case: the client site has one device present, in router mode (or unspecified)
 pub fn single_entry_clients(
-    all_sites: &[Site],
+    active: &[LqClientSite],
     all_devices: &[Device],
     all_data_links: &[DataLink],
 ) -> Result<Vec<LqClientSite>> {
     let mut result = Vec::<LqClientSite>::new();
-    active_clients(all_sites).iter().for_each(|client_site| {
+    active.iter().for_each(|client_site| {
         let devices: Vec<LqClientDevice> = all_devices
             .iter()
             .filter(|d| {
@@ -85,14 +90,15 @@ pub fn single_entry_clients(
 }
 
 pub fn complex_clients(
-    all_sites: &[Site],
+    active: &[LqClientSite],
     all_devices: &[Device],
     all_data_links: &[DataLink],
     network_sites: &mut HashMap<String, LqSite>,
+    metrics: &RunMetrics,
 ) -> Result<Vec<LqClientSite>> {
     let mut result = Vec::<LqClientSite>::new();
 
-    active_clients(all_sites).iter().for_each(|client_site| {
+    active.iter().for_each(|client_site| {
         let mut devices: Vec<LqClientDevice> = all_devices
             .iter()
             .filter(|d| {
@@ -148,7 +154,7 @@ pub fn complex_clients(
             let n_external_links = externals.len();
 
             if n_external_links == 0 {
-                println!("Orphan: {}", client_site.name);
+                debug!(site = client_site.name, "Orphan client site");
                 let mut cs = client_site.clone();
                 let mut device = devices[0].clone();
                 let _ = lookup_data_link(&mut device, all_data_links);
@@ -183,7 +189,7 @@ pub fn complex_clients(
                             download_mbps: client_site.download / 1_000_000,
                             upload_mbps: client_site.upload / 1_000_000,
                             children: Vec::new(),
-                            access_points: HashMap::new(),
+                            access_points: BTreeMap::new(),
                             parent: Some(externals.iter().next().unwrap().0.clone()),
                         },
                     );
@@ -198,9 +204,13 @@ pub fn complex_clients(
                     result.push(cs);
                 }
             } else {
-                println!("\nReally tough site: {}", client_site.name);
-                println!("External links: {}", n_external_links);
-                println!("{:#?}", externals);
+                metrics.record(Counter::UnresolvedMultihomedSites, &client_site.name);
+                warn!(
+                    site = client_site.name,
+                    external_links = n_external_links,
+                    "Unable to classify client site"
+                );
+                debug!("{:#?}", externals);
             }
         }
     });
@@ -214,12 +224,16 @@ pub fn create_network_infrastructure(
 ) -> Result<Vec<LqClientSite>> {
     let mut result = Vec::new();
 
-    for (i, (_, site)) in sites.iter().enumerate() {
+    // IDs are derived from the site ID, so they're the same every run.
+    let mut sites: Vec<&LqSite> = sites.values().collect();
+    sites.sort_by(|a, b| a.id.cmp(&b.id));
+    for site in sites {
         let mut ls = LqClientSite {
-            id: format!("inf{i}"),
+            id: format!("inf-{}", site.id),
             name: format!("{}Infrastructure", site.name),
             download: 1_000_000_000_000,
             upload: 1_000_000_000_000,
+            suspended: false,
             devices: Vec::new(),
         };
 
@@ -237,7 +251,7 @@ pub fn create_network_infrastructure(
             .collect();
 
         for d in devices.iter_mut() {
-            d.access_point_id = format!("infap{i}");
+            d.access_point_id = format!("infap-{}", site.id);
             d.access_point_name = format!("{}Infrastructure", site.name);
             d.parent_site_id = site.id.clone();
             d.parent_site_name = site.name.clone();
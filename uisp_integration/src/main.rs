@@ -1,55 +1,170 @@
+mod cli;
 mod clients;
-mod network_json;
+mod netbox;
+mod sources;
+#[cfg(test)]
+mod test_server;
 mod topology;
+mod ucrm;
 mod unms;
-use std::time::Instant;
+use std::{sync::Arc, time::Instant};
 
 use anyhow::Result;
-use clients::write_shaper_csv;
-use network_json::NetworkNode;
-use tokio::join;
+use clap::Parser;
+use cli::{Cli, Command};
+use integration_core::{
+    assign_unique_names, capacity_plan, fetch_inventories, generations, init_logging,
+    merge_inventories, network_json, publish, rollback, shaped_devices_csv, shaper_csv, validate,
+    write_capacity_plan, write_graphs, write_metrics, write_report, Excluded, Inventory,
+    LqClientSite, LqSite, RootSelection, RunMetrics,
+};
+use sources::{configured_sources, enrich_ips};
 use topology::build_topology;
+use tracing::{info, info_span, warn, Instrument};
 use unms::*;
 
-/// Connects to uISP and downloads all sites, devices and data-links.
-/// Please ensure that you setup `keys.ron` correctly, or this won't work.
-async fn pre_load_uisp() -> Result<(Vec<Site>, Vec<Device>, Vec<DataLink>)> {
-    let keys = Keys::load()?;
-    let (key, url) = keys.uisp();
-    let sites_future = nms_request_get_vec::<Site>("sites", key, url);
-    let devices_future = nms_request_get_vec::<Device>("devices?authorized=true", key, url);
-    let data_links_future = nms_request_get_vec::<DataLink>("data-links", key, url);
-
-    let (sites, devices, data_links) = join!(sites_future, devices_future, data_links_future);
-    Ok((sites?, devices?, data_links?))
-}
-
 #[tokio::main]
 async fn main() -> Result<()> {
+    let cli = Cli::parse();
     let start = Instant::now();
-    println!("Fetching sites, devices and data links from uISP.");
-    let start_fetch = Instant::now();
-    let (all_sites, all_devices, all_data_links) = pre_load_uisp().await?;
-    println!("Fetched all uISP data in {:?}", start_fetch.elapsed());
-
-    let mut network_sites = topology::build_site_list(&all_sites)?;
-    //let clients = clients::build_clients(&all_sites, &all_devices, &all_data_links)?;
-    let infrastructure = &clients::create_network_infrastructure(&network_sites, &all_devices)?;
-    let mut clients = clients::single_entry_clients(&all_sites, &all_devices, &all_data_links)?;
-    let complex_clients = clients::complex_clients(
-        &all_sites,
-        &all_devices,
-        &all_data_links,
-        &mut network_sites,
-    )?;
-    clients.extend_from_slice(&complex_clients);
-    clients.extend_from_slice(infrastructure);
-    let network_map = build_topology(&mut clients, &mut network_sites)?;
-    let network_json_data = NetworkNode::from_lq_site(&network_map);
-    network_json_data.write_to_file()?;
-    write_shaper_csv(&clients)?;
+    let keys = Keys::load()?;
+    init_logging(keys.log(), cli.verbosity(), cli.log_json)?;
+    match cli.command {
+        Some(Command::Generations) => {
+            for generation in generations(keys.output())? {
+                println!("{generation}");
+            }
+            return Ok(());
+        }
+        Some(Command::Rollback { generation }) => {
+            let restored = rollback(keys.output(), generation.as_deref())?;
+            info!(generation = restored, "Restored generation");
+            return Ok(());
+        }
+        None => {}
+    }
+
+    let metrics = RunMetrics::default();
+    let result = rebuild(&cli, &keys, &metrics).await;
+    let topology = result.as_ref().ok();
+    let topology = topology.map(|(roots, clients)| (roots.as_slice(), clients.as_slice()));
+    if let Err(e) = write_metrics(keys.metrics(), &metrics, topology) {
+        warn!("Unable to write metrics: {e}");
+    }
+    result?;
 
     // Complete
-    println!("Completed topology rebuild in {:?}", start.elapsed());
+    info!(elapsed = ?start.elapsed(), "Completed topology rebuild");
     Ok(())
 }
+
+/// Fetches, builds and publishes the topology, returning the tree and
+/// circuits that were published.
+async fn rebuild(
+    cli: &Cli,
+    keys: &Keys,
+    metrics: &RunMetrics,
+) -> Result<(Vec<LqSite>, Vec<LqClientSite>)> {
+    std::fs::create_dir_all(&keys.output().directory)?;
+    let inventory = fetch(keys, metrics).instrument(info_span!("fetch")).await?;
+    let mut outputs = inventory.files;
+
+    let (network_map, clients, excluded) = info_span!("topology").in_scope(
+        || -> Result<(Vec<LqSite>, Vec<LqClientSite>, Excluded)> {
+            let roots = keys.root_selection();
+            let mut clients = inventory.circuits;
+            let mut network_sites = inventory.sites;
+            let (mut network_map, excluded) = build_topology(
+                &mut clients,
+                &mut network_sites,
+                &inventory.locations,
+                &roots,
+                keys.output(),
+                &mut outputs,
+            )?;
+            assign_unique_names(&mut network_map, &mut clients, keys.naming());
+            for issue in validate(&network_map, &clients) {
+                warn!("{issue}");
+            }
+            Ok((network_map, clients, excluded))
+        },
+    )?;
+
+    info_span!("write").in_scope(|| -> Result<()> {
+        outputs.add("network.json", network_json(&network_map));
+        outputs.add("Shaper.csv", shaper_csv(&clients));
+        outputs.add("ShapedDevices.csv", shaped_devices_csv(&clients));
+        outputs.add_summary(&network_map)?;
+        let outcome = publish(keys.output(), &outputs, cli.force)?;
+        if !outcome.changed {
+            info!("Outputs unchanged; nothing published");
+        } else if let Some(backup) = outcome.backup {
+            info!(previous = backup, "Published new outputs");
+        } else {
+            info!("Published new outputs");
+        }
+        // Only written once the guard has let the run through.
+        write_graphs(keys.graph(), keys.capacity(), &network_map, &clients)?;
+        let plan_config = keys.capacity_plan();
+        let plan = capacity_plan(
+            &network_map,
+            &clients,
+            keys.capacity(),
+            plan_config.top_circuits,
+        );
+        write_capacity_plan(plan_config, &plan)?;
+        let report_plan = plan_config.in_report.then_some(&plan);
+        write_report(
+            keys.report(),
+            keys.capacity(),
+            report_plan,
+            &network_map,
+            &clients,
+            metrics,
+            &excluded,
+        )
+    })?;
+    Ok((network_map, clients))
+}
+
+/// Fetches and merges every configured source, then applies the root and
+/// NetBox settings.
+async fn fetch(keys: &Keys, metrics: &RunMetrics) -> Result<Inventory> {
+    let netbox = keys
+        .netbox()
+        .map(|config| Arc::new(netbox::NetBoxLoader::new(config.clone())));
+    let sources = configured_sources(keys, netbox.as_ref())?;
+    let (mut inventory, merge_report) = merge_inventories(
+        fetch_inventories(&sources, metrics).await?,
+        &keys.merge_rules(),
+    )?;
+    if sources.len() > 1 {
+        info!(
+            sources = sources.len(),
+            single_source = merge_report.single_source().count(),
+            conflicts = merge_report.conflicts.len(),
+            "Merged sources"
+        );
+        inventory
+            .files
+            .add_supporting("MergeReport.csv", merge_report.to_csv()?);
+    }
+    inventory.resolve_parent_names();
+    if let RootSelection::Named(names) = &keys.root_selection() {
+        if names.iter().any(|n| n == keys.root()) {
+            inventory.ensure_root(keys.root());
+        }
+    }
+    for root in keys.root_sites() {
+        for site in inventory.sites.values_mut().filter(|s| s.name == root.name) {
+            site.download_mbps = root.download_mbps.unwrap_or(site.download_mbps);
+            site.upload_mbps = root.upload_mbps.unwrap_or(site.upload_mbps);
+        }
+    }
+    if let Some(netbox) = netbox.filter(|nb| nb.config().enrich) {
+        let netbox_data = netbox.load(metrics).await?;
+        let updated = enrich_ips(&mut inventory, netbox_data, netbox.config().match_by);
+        info!(updated, "Updated device IP addresses from NetBox");
+    }
+    Ok(inventory)
+}
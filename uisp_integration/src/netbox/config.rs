@@ -0,0 +1,60 @@
+use serde::{Deserialize, Serialize};
+
+/// Which NetBox grouping provides the parents of sites.
+#[derive(Clone, Copy, Debug, Serialize, Deserialize, Default, PartialEq, Eq)]
+pub enum NetBoxHierarchy {
+    #[default]
+    Region,
+    SiteGroup,
+}
+
+/// How NetBox devices are matched to devices from other sources when
+/// enriching IP addresses.
+#[derive(Clone, Copy, Debug, Serialize, Deserialize, Default, PartialEq, Eq)]
+pub enum NetBoxMatch {
+    Name,
+    Mac,
+    /// Match by MAC address, falling back to the device name.
+    #[default]
+    #[serde(alias = "NameOrMac")]
+    MacOrName,
+}
+
+/// NetBox connection and mapping settings, from `keys.ron`.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct NetBoxConfig {
+    /// Base URL of NetBox, without `/api`.
+    pub url: String,
+    pub token: String,
+    pub hierarchy: NetBoxHierarchy,
+    /// Device role slugs that represent customer devices.
+    pub client_roles: Vec<String>,
+    /// Custom fields on customer devices holding their rates, in Mbps.
+    pub download_field: String,
+    pub upload_field: String,
+    /// Replace IP addresses of devices from other sources with NetBox's.
+    pub enrich: bool,
+    pub match_by: NetBoxMatch,
+}
+
+impl Default for NetBoxConfig {
+    fn default() -> Self {
+        Self {
+            url: String::new(),
+            token: String::new(),
+            hierarchy: NetBoxHierarchy::Region,
+            client_roles: vec!["cpe".to_string()],
+            download_field: "download_mbps".to_string(),
+            upload_field: "upload_mbps".to_string(),
+            enrich: false,
+            match_by: NetBoxMatch::MacOrName,
+        }
+    }
+}
+
+impl NetBoxConfig {
+    pub fn api(&self) -> String {
+        format!("{}/api", self.url.trim_end_matches('/'))
+    }
+}
@@ -0,0 +1,37 @@
+use super::{Choice, NestedIp, NestedRef};
+use serde::Deserialize;
+use serde_json::Value;
+use std::collections::HashMap;
+
+#[derive(Deserialize, Debug)]
+pub struct Device {
+    pub id: usize,
+    pub name: Option<String>,
+    pub site: Option<NestedRef>,
+    /// NetBox 3.6+ calls this `role`; earlier versions use `device_role`.
+    #[serde(alias = "device_role")]
+    pub role: Option<NestedRef>,
+    pub tenant: Option<NestedRef>,
+    pub status: Option<Choice>,
+    pub primary_ip4: Option<NestedIp>,
+    #[serde(default)]
+    pub custom_fields: HashMap<String, Value>,
+}
+
+impl Device {
+    pub fn is_active(&self) -> bool {
+        self.status
+            .as_ref()
+            .map(|s| s.value == "active")
+            .unwrap_or(true)
+    }
+
+    /// Reads a numeric custom field, such as a configured rate.
+    pub fn custom_number(&self, field: &str) -> Option<f64> {
+        match self.custom_fields.get(field)? {
+            Value::Number(n) => n.as_f64(),
+            Value::String(s) => s.trim().parse().ok(),
+            _ => None,
+        }
+    }
+}
@@ -0,0 +1,9 @@
+use super::NestedRef;
+use serde::Deserialize;
+
+#[derive(Deserialize, Debug)]
+pub struct Interface {
+    pub id: usize,
+    pub device: NestedRef,
+    pub mac_address: Option<String>,
+}
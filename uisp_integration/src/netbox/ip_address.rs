@@ -0,0 +1,43 @@
+use super::{Choice, NestedRef};
+use serde::Deserialize;
+
+#[derive(Deserialize, Debug)]
+pub struct IpAddress {
+    pub address: String,
+    pub status: Option<Choice>,
+    pub assigned_object_type: Option<String>,
+    pub assigned_object_id: Option<usize>,
+}
+
+impl IpAddress {
+    /// The interface this address is assigned to, if it's on a device interface.
+    pub fn interface_id(&self) -> Option<usize> {
+        match self.assigned_object_type.as_deref() {
+            Some("dcim.interface") => self.assigned_object_id,
+            _ => None,
+        }
+    }
+
+    pub fn is_active(&self) -> bool {
+        self.status
+            .as_ref()
+            .map(|s| s.value == "active")
+            .unwrap_or(true)
+    }
+}
+
+#[derive(Deserialize, Debug)]
+pub struct Prefix {
+    pub prefix: String,
+    pub tenant: Option<NestedRef>,
+    pub status: Option<Choice>,
+}
+
+impl Prefix {
+    pub fn is_active(&self) -> bool {
+        self.status
+            .as_ref()
+            .map(|s| s.value == "active")
+            .unwrap_or(true)
+    }
+}
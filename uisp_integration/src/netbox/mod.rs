@@ -0,0 +1,157 @@
+mod config;
+mod device;
+mod interface;
+mod ip_address;
+mod nested;
+mod rest;
+mod site;
+use std::collections::HashMap;
+
+use anyhow::Result;
+pub use config::*;
+pub use device::Device;
+use integration_core::RunMetrics;
+pub use interface::Interface;
+pub use ip_address::{IpAddress, Prefix};
+pub use nested::*;
+pub use rest::*;
+use serde::de::DeserializeOwned;
+pub use site::{Site, SiteHierarchy};
+use tokio::{join, sync::OnceCell};
+
+/// Everything read from NetBox in one run.
+pub struct NetBoxData {
+    pub sites: Vec<Site>,
+    pub regions: Vec<SiteHierarchy>,
+    pub site_groups: Vec<SiteHierarchy>,
+    pub devices: Vec<Device>,
+    pub interfaces: Vec<Interface>,
+    pub ip_addresses: Vec<IpAddress>,
+    pub prefixes: Vec<Prefix>,
+}
+
+/// An address without its interface mask, such as `10.0.0.5` for
+/// `10.0.0.5/24`.
+fn host(address: &str) -> &str {
+    address.split('/').next().unwrap_or_default()
+}
+
+impl NetBoxData {
+    /// The address to shape for each device, keyed by device ID: its primary
+    /// IPv4, else the first active address on one of its interfaces, else the
+    /// first active prefix belonging to the device's tenant. Addresses lose
+    /// their interface mask; a prefix keeps its length, so all of it is shaped.
+    pub fn device_ips(&self) -> HashMap<usize, String> {
+        let interface_device: HashMap<usize, usize> = self
+            .interfaces
+            .iter()
+            .map(|i| (i.id, i.device.id))
+            .collect();
+        let mut interface_ips = HashMap::<usize, String>::new();
+        for ip in self.ip_addresses.iter().filter(|ip| ip.is_active()) {
+            if let Some(device) = ip.interface_id().and_then(|i| interface_device.get(&i)) {
+                interface_ips
+                    .entry(*device)
+                    .or_insert_with(|| host(&ip.address).to_string());
+            }
+        }
+
+        self.devices
+            .iter()
+            .filter_map(|d| {
+                let ip = d
+                    .primary_ip4
+                    .as_ref()
+                    .map(|ip| host(&ip.address).to_string())
+                    .or_else(|| interface_ips.get(&d.id).cloned())
+                    .or_else(|| {
+                        let tenant = d.tenant.as_ref()?;
+                        self.prefixes
+                            .iter()
+                            .filter(|p| p.is_active())
+                            .find(|p| p.tenant.as_ref().map(|t| t.id) == Some(tenant.id))
+                            .map(|p| p.prefix.clone())
+                    })?;
+                Some((d.id, ip))
+            })
+            .collect()
+    }
+
+    /// MAC addresses of each device's interfaces, keyed by device ID.
+    pub fn device_macs(&self) -> HashMap<usize, Vec<String>> {
+        let mut result = HashMap::<usize, Vec<String>>::new();
+        for interface in self.interfaces.iter() {
+            if let Some(mac) = &interface.mac_address {
+                result
+                    .entry(interface.device.id)
+                    .or_default()
+                    .push(mac.to_lowercase());
+            }
+        }
+        result
+    }
+}
+
+/// Fetches every page of a NetBox endpoint, recording how long it took.
+async fn get_all<T: DeserializeOwned>(
+    path: &str,
+    token: &str,
+    api: &str,
+    metrics: &RunMetrics,
+) -> Result<Vec<T>> {
+    Ok(metrics
+        .timed_fetch("netbox", path, netbox_request_get_all(path, token, api))
+        .await?)
+}
+
+/// Connects to NetBox and downloads sites, their hierarchy, devices,
+/// interfaces, IP addresses and prefixes.
+pub async fn pre_load_netbox(config: &NetBoxConfig, metrics: &RunMetrics) -> Result<NetBoxData> {
+    let api = config.api();
+    let token = &config.token;
+    let (sites, regions, site_groups, devices, interfaces, ip_addresses, prefixes) = join!(
+        get_all::<Site>("dcim/sites/", token, &api, metrics),
+        get_all::<SiteHierarchy>("dcim/regions/", token, &api, metrics),
+        get_all::<SiteHierarchy>("dcim/site-groups/", token, &api, metrics),
+        get_all::<Device>("dcim/devices/", token, &api, metrics),
+        get_all::<Interface>("dcim/interfaces/", token, &api, metrics),
+        get_all::<IpAddress>("ipam/ip-addresses/", token, &api, metrics),
+        get_all::<Prefix>("ipam/prefixes/", token, &api, metrics),
+    );
+    Ok(NetBoxData {
+        sites: sites?,
+        regions: regions?,
+        site_groups: site_groups?,
+        devices: devices?,
+        interfaces: interfaces?,
+        ip_addresses: ip_addresses?,
+        prefixes: prefixes?,
+    })
+}
+
+/// Downloads NetBox data at most once per run, so the `netbox` source and
+/// IP enrichment share a single load.
+pub struct NetBoxLoader {
+    config: NetBoxConfig,
+    data: OnceCell<NetBoxData>,
+}
+
+impl NetBoxLoader {
+    pub fn new(config: NetBoxConfig) -> Self {
+        Self {
+            config,
+            data: OnceCell::new(),
+        }
+    }
+
+    pub fn config(&self) -> &NetBoxConfig {
+        &self.config
+    }
+
+    /// The NetBox data, fetched on first use.
+    pub async fn load(&self, metrics: &RunMetrics) -> Result<&NetBoxData> {
+        self.data
+            .get_or_try_init(|| pre_load_netbox(&self.config, metrics))
+            .await
+    }
+}
@@ -0,0 +1,23 @@
+use serde::Deserialize;
+
+/// The abbreviated form NetBox uses when one object refers to another.
+#[derive(Deserialize, Debug, Clone)]
+pub struct NestedRef {
+    pub id: usize,
+    #[serde(default)]
+    pub name: String,
+    #[serde(default)]
+    pub slug: String,
+}
+
+/// NetBox's `{ "value": ..., "label": ... }` choice fields.
+#[derive(Deserialize, Debug, Clone)]
+pub struct Choice {
+    pub value: String,
+}
+
+/// An IP address reference, e.g. a device's primary IPv4.
+#[derive(Deserialize, Debug, Clone)]
+pub struct NestedIp {
+    pub address: String,
+}
@@ -0,0 +1,38 @@
+use serde::{de::DeserializeOwned, Deserialize};
+
+/// A page of results from the NetBox API.
+#[derive(Deserialize, Debug)]
+struct Page<T> {
+    next: Option<String>,
+    results: Vec<T>,
+}
+
+/// Submits a request to the NetBox API, following pagination links until
+/// every result has been fetched. Returns a deserialized vector of type T.
+pub async fn netbox_request_get_all<T>(
+    url: &str,
+    token: &str,
+    api: &str,
+) -> Result<Vec<T>, reqwest::Error>
+where
+    T: DeserializeOwned,
+{
+    let client = reqwest::Client::new();
+    let mut result = Vec::new();
+    let mut next = Some(format!("{}/{}?limit=1000", api, url));
+
+    while let Some(full_url) = next {
+        let res = client
+            .get(&full_url)
+            .header("Accept", "application/json")
+            .header("Authorization", format!("Token {token}"))
+            .send()
+            .await?
+            .error_for_status()?;
+        let page = res.json::<Page<T>>().await?;
+        result.extend(page.results);
+        next = page.next;
+    }
+
+    Ok(result)
+}
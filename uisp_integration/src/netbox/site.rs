@@ -0,0 +1,28 @@
+use super::{Choice, NestedRef};
+use serde::Deserialize;
+
+#[derive(Deserialize, Debug)]
+pub struct Site {
+    pub id: usize,
+    pub name: String,
+    pub status: Option<Choice>,
+    pub region: Option<NestedRef>,
+    pub group: Option<NestedRef>,
+}
+
+impl Site {
+    pub fn is_active(&self) -> bool {
+        self.status
+            .as_ref()
+            .map(|s| s.value == "active")
+            .unwrap_or(true)
+    }
+}
+
+/// Regions and site groups share a shape: a name and an optional parent.
+#[derive(Deserialize, Debug)]
+pub struct SiteHierarchy {
+    pub id: usize,
+    pub name: String,
+    pub parent: Option<NestedRef>,
+}
@@ -0,0 +1,58 @@
+mod netbox;
+mod uisp;
+use crate::{netbox::NetBoxLoader, unms::Keys};
+use anyhow::{Error, Result};
+use integration_core::{FileIntegration, Integration};
+pub use netbox::{enrich_ips, NetBoxIntegration};
+use std::sync::Arc;
+pub use uisp::UispIntegration;
+
+/// Creates the data sources named in `keys.ron`, in the order listed. The
+/// `netbox` source reads through `netbox`, which is built from the `netbox`
+/// section of `keys.ron` if there is one.
+pub fn configured_sources(
+    keys: &Keys,
+    netbox: Option<&Arc<NetBoxLoader>>,
+) -> Result<Vec<Box<dyn Integration>>> {
+    let mut sources: Vec<Box<dyn Integration>> = Vec::new();
+    for name in keys.sources() {
+        match name.as_str() {
+            "uisp" => {
+                let instances = keys.uisp_instances();
+                if instances.is_empty() {
+                    return Err(Error::msg(
+                        "The uisp source needs nms_url or uisp_instances set in keys.ron",
+                    ));
+                }
+                let namespace = instances.len() > 1;
+                for instance in instances {
+                    sources.push(Box::new(UispIntegration::new(
+                        instance,
+                        namespace,
+                        keys.root(),
+                        keys.root_selection(),
+                        keys.uisp_options().clone(),
+                        keys.output().clone(),
+                    )));
+                }
+            }
+            "file" => {
+                let path = keys.inventory_file().ok_or_else(|| {
+                    Error::msg("The file source needs inventory_file set in keys.ron")
+                })?;
+                sources.push(Box::new(FileIntegration::new(path)));
+            }
+            "netbox" => {
+                let netbox = netbox.ok_or_else(|| {
+                    Error::msg("The netbox source needs a netbox section in keys.ron")
+                })?;
+                sources.push(Box::new(NetBoxIntegration::new(
+                    netbox.clone(),
+                    keys.output().clone(),
+                )));
+            }
+            _ => return Err(Error::msg(format!("Unknown data source: {name}"))),
+        }
+    }
+    Ok(sources)
+}
@@ -0,0 +1,326 @@
+use crate::{
+    netbox::{NetBoxConfig, NetBoxData, NetBoxHierarchy, NetBoxLoader, NetBoxMatch},
+    topology::{load_sites_csv, Overrides},
+};
+use anyhow::Result;
+use async_trait::async_trait;
+use integration_core::{
+    Integration, Inventory, LqClientDevice, LqClientSite, LqSite, OutputConfig, RunMetrics,
+};
+use std::{
+    collections::{BTreeMap, HashMap},
+    sync::Arc,
+    time::Instant,
+};
+use tracing::info;
+
+/// Builds an inventory from NetBox: regions or site groups and sites form
+/// the site tree, and devices with a customer role become circuits.
+pub struct NetBoxIntegration {
+    /// Shared with IP enrichment, so NetBox is only downloaded once.
+    netbox: Arc<NetBoxLoader>,
+    /// Where `Sites.csv` lives.
+    output: OutputConfig,
+}
+
+impl NetBoxIntegration {
+    pub fn new(netbox: Arc<NetBoxLoader>, output: OutputConfig) -> Self {
+        Self { netbox, output }
+    }
+}
+
+fn hierarchy_id(kind: NetBoxHierarchy, id: usize) -> String {
+    match kind {
+        NetBoxHierarchy::Region => format!("nb-region-{id}"),
+        NetBoxHierarchy::SiteGroup => format!("nb-group-{id}"),
+    }
+}
+
+fn new_site(id: String, name: &str, parent: Option<String>, rates: (usize, usize)) -> LqSite {
+    LqSite {
+        id,
+        name: name.replace(',', "_"),
+        parent,
+        children: Vec::new(),
+        access_points: BTreeMap::new(),
+        download_mbps: rates.0,
+        upload_mbps: rates.1,
+    }
+}
+
+/// Converts NetBox data into sites and circuits, with site rates from
+/// `sites_csv`.
+pub fn build_netbox_inventory(
+    data: &NetBoxData,
+    config: &NetBoxConfig,
+    sites_csv: &Overrides,
+) -> Inventory {
+    let rates = |id: &str, name: &str| sites_csv.lookup(id, name).unwrap_or((1_000, 1_000));
+    let mut inventory = Inventory::default();
+
+    let groups = match config.hierarchy {
+        NetBoxHierarchy::Region => &data.regions,
+        NetBoxHierarchy::SiteGroup => &data.site_groups,
+    };
+    for group in groups.iter() {
+        let id = hierarchy_id(config.hierarchy, group.id);
+        let parent = group
+            .parent
+            .as_ref()
+            .map(|p| hierarchy_id(config.hierarchy, p.id));
+        inventory.sites.insert(
+            id.clone(),
+            new_site(id.clone(), &group.name, parent, rates(&id, &group.name)),
+        );
+    }
+
+    for site in data.sites.iter().filter(|s| s.is_active()) {
+        let id = format!("nb-site-{}", site.id);
+        let parent = match config.hierarchy {
+            NetBoxHierarchy::Region => site.region.as_ref(),
+            NetBoxHierarchy::SiteGroup => site.group.as_ref(),
+        }
+        .map(|p| hierarchy_id(config.hierarchy, p.id));
+        inventory.sites.insert(
+            id.clone(),
+            new_site(id.clone(), &site.name, parent, rates(&id, &site.name)),
+        );
+    }
+
+    let ips = data.device_ips();
+    let macs = data.device_macs();
+    for device in data.devices.iter().filter(|d| d.is_active()) {
+        let is_client = device
+            .role
+            .as_ref()
+            .map(|r| config.client_roles.contains(&r.slug))
+            .unwrap_or(false);
+        let (site, ip) = match (&device.site, ips.get(&device.id)) {
+            (Some(site), Some(ip)) if is_client => (site, ip),
+            _ => continue,
+        };
+        let name = device
+            .name
+            .clone()
+            .unwrap_or_else(|| format!("netbox-{}", device.id))
+            .replace(',', "_");
+        let mbps = |field: &str| {
+            device
+                .custom_number(field)
+                .map(|n| (n * 1_000_000.0) as usize)
+                .unwrap_or(0)
+        };
+        let (download, upload) = (mbps(&config.download_field), mbps(&config.upload_field));
+
+        inventory.circuits.push(LqClientSite {
+            id: format!("nb-{}", device.id),
+            name: name.clone(),
+            download,
+            upload,
+            suspended: false,
+            devices: vec![LqClientDevice {
+                id: format!("nb-{}", device.id),
+                hostname: name,
+                mac: macs
+                    .get(&device.id)
+                    .and_then(|m| m.first().cloned())
+                    .unwrap_or_default(),
+                model: String::new(),
+                ip: ip.clone(),
+                access_point_id: String::new(),
+                access_point_name: String::new(),
+                parent_site_id: format!("nb-site-{}", site.id),
+                parent_site_name: site.name.replace(',', "_"),
+                upload,
+                download,
+                is_access_point: false,
+                is_bridge: false,
+            }],
+        });
+    }
+
+    inventory
+}
+
+/// Replaces the IP addresses of devices from other sources with the address
+/// NetBox has for the same device, matched by MAC and/or name. Returns the
+/// number of devices updated.
+pub fn enrich_ips(inventory: &mut Inventory, data: &NetBoxData, match_by: NetBoxMatch) -> usize {
+    let ips = data.device_ips();
+    let by_mac: HashMap<String, &String> = data
+        .device_macs()
+        .into_iter()
+        .filter_map(|(id, macs)| Some((macs, ips.get(&id)?)))
+        .flat_map(|(macs, ip)| macs.into_iter().map(move |mac| (mac, ip)))
+        .collect();
+    let by_name: HashMap<String, &String> = data
+        .devices
+        .iter()
+        .filter_map(|d| {
+            Some((
+                d.name.as_ref()?.replace(',', "_").to_lowercase(),
+                ips.get(&d.id)?,
+            ))
+        })
+        .collect();
+
+    let mut updated = 0;
+    for device in inventory
+        .circuits
+        .iter_mut()
+        .flat_map(|c| c.devices.iter_mut())
+    {
+        let mac = || by_mac.get(&device.mac.to_lowercase());
+        let name = || by_name.get(&device.hostname.to_lowercase());
+        let found = match match_by {
+            NetBoxMatch::Name => name(),
+            NetBoxMatch::Mac => mac(),
+            NetBoxMatch::MacOrName => mac().or_else(name),
+        };
+        if let Some(ip) = found {
+            if device.ip != **ip {
+                device.ip = (*ip).clone();
+                updated += 1;
+            }
+        }
+    }
+    updated
+}
+
+#[async_trait]
+impl Integration for NetBoxIntegration {
+    fn name(&self) -> &str {
+        "netbox"
+    }
+
+    async fn fetch_inventory(&self, metrics: &RunMetrics) -> Result<Inventory> {
+        info!("Fetching sites, devices and IP addresses from NetBox");
+        let start_fetch = Instant::now();
+        let data = self.netbox.load(metrics).await?;
+        info!(elapsed = ?start_fetch.elapsed(), "Fetched all NetBox data");
+        Ok(build_netbox_inventory(
+            data,
+            self.netbox.config(),
+            &load_sites_csv(&self.output)?,
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_server::serve_fixtures;
+    use integration_core::shaped_devices_csv;
+
+    /// Canned NetBox responses by request path. `{base}` is replaced with
+    /// the server's address, so `next` links point back at it.
+    const FIXTURES: &[(&str, &str)] = &[
+        (
+            "/api/dcim/regions/?limit=1000",
+            r#"{"count": 2, "next": null, "results": [
+                {"id": 1, "name": "North", "parent": null},
+                {"id": 2, "name": "Hills", "parent": {"id": 1, "name": "North"}}
+            ]}"#,
+        ),
+        (
+            "/api/dcim/site-groups/?limit=1000",
+            r#"{"count": 0, "next": null, "results": []}"#,
+        ),
+        (
+            "/api/dcim/sites/?limit=1000",
+            r#"{"count": 2, "next": null, "results": [
+                {"id": 10, "name": "Tower, East", "status": {"value": "active"}, "region": {"id": 2}},
+                {"id": 11, "name": "Old Tower", "status": {"value": "retired"}, "region": {"id": 2}}
+            ]}"#,
+        ),
+        (
+            "/api/dcim/devices/?limit=1000",
+            r#"{"count": 3, "next": "{base}/api/dcim/devices/?limit=1000&offset=2", "results": [
+                {"id": 100, "name": "cpe-1", "site": {"id": 10, "name": "Tower, East"},
+                 "role": {"id": 1, "slug": "cpe"}, "status": {"value": "active"},
+                 "primary_ip4": {"address": "100.64.0.1/24"},
+                 "custom_fields": {"download_mbps": 50, "upload_mbps": "10"}},
+                {"id": 101, "name": "switch-1", "site": {"id": 10, "name": "Tower, East"},
+                 "role": {"id": 2, "slug": "switch"}, "status": {"value": "active"},
+                 "primary_ip4": {"address": "10.0.0.1/32"}}
+            ]}"#,
+        ),
+        (
+            "/api/dcim/devices/?limit=1000&offset=2",
+            r#"{"count": 3, "next": null, "results": [
+                {"id": 102, "name": "cpe-2", "site": {"id": 10, "name": "Tower, East"},
+                 "device_role": {"id": 1, "slug": "cpe"}, "tenant": {"id": 7},
+                 "custom_fields": {"download_mbps": 25, "upload_mbps": 5}}
+            ]}"#,
+        ),
+        (
+            "/api/dcim/interfaces/?limit=1000",
+            r#"{"count": 1, "next": null, "results": [
+                {"id": 1000, "device": {"id": 102}, "mac_address": "AA:BB:CC:00:00:02"}
+            ]}"#,
+        ),
+        (
+            "/api/ipam/ip-addresses/?limit=1000",
+            r#"{"count": 0, "next": null, "results": []}"#,
+        ),
+        (
+            "/api/ipam/prefixes/?limit=1000",
+            r#"{"count": 1, "next": null, "results": [
+                {"prefix": "100.64.1.0/29", "tenant": {"id": 7}, "status": {"value": "active"}}
+            ]}"#,
+        ),
+    ];
+
+    #[tokio::test]
+    async fn pages_are_followed_and_mapped_to_an_inventory() {
+        let (url, requests) = serve_fixtures(FIXTURES).await;
+        let netbox = Arc::new(NetBoxLoader::new(NetBoxConfig {
+            url,
+            token: "secret".to_string(),
+            ..Default::default()
+        }));
+        let dir = tempfile::tempdir().unwrap();
+        let output = OutputConfig {
+            directory: dir.path().display().to_string(),
+            ..Default::default()
+        };
+        let source = NetBoxIntegration::new(netbox.clone(), output);
+        let metrics = RunMetrics::default();
+        let inventory = source.fetch_inventory(&metrics).await.unwrap();
+
+        let mut site_ids: Vec<&String> = inventory.sites.keys().collect();
+        site_ids.sort();
+        assert_eq!(site_ids, vec!["nb-region-1", "nb-region-2", "nb-site-10"]);
+        let tower = &inventory.sites["nb-site-10"];
+        assert_eq!(tower.name, "Tower_ East");
+        assert_eq!(tower.parent.as_deref(), Some("nb-region-2"));
+        assert_eq!(
+            inventory.sites["nb-region-2"].parent.as_deref(),
+            Some("nb-region-1")
+        );
+
+        // Both device pages are read; the switch isn't a customer device.
+        assert_eq!(inventory.circuits.len(), 2);
+        let first = &inventory.circuits[0];
+        assert_eq!(first.id, "nb-100");
+        assert_eq!((first.download, first.upload), (50_000_000, 10_000_000));
+        // An address loses its interface mask; a tenant prefix is kept whole.
+        assert_eq!(first.devices[0].ip, "100.64.0.1");
+        assert_eq!(first.devices[0].parent_site_id, "nb-site-10");
+        let second = &inventory.circuits[1];
+        assert_eq!(second.id, "nb-102");
+        assert_eq!(second.devices[0].ip, "100.64.1.0/29");
+        assert_eq!(second.devices[0].mac, "aa:bb:cc:00:00:02");
+        let shaped_devices = shaped_devices_csv(&inventory.circuits);
+        assert!(
+            shaped_devices.contains(",100.64.0.1,,") && shaped_devices.contains(",100.64.1.0/29,,"),
+            "{shaped_devices}"
+        );
+
+        // Enrichment reuses the data the source loaded.
+        let fetched = requests.lock().unwrap().len();
+        assert_eq!(fetched, FIXTURES.len());
+        netbox.load(&metrics).await.unwrap();
+        assert_eq!(requests.lock().unwrap().len(), fetched);
+    }
+}
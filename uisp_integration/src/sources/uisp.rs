@@ -0,0 +1,220 @@
+use crate::{clients, topology, ucrm, unms::*};
+use anyhow::Result;
+use async_trait::async_trait;
+use integration_core::{Integration, Inventory, OutputConfig, RootSelection, RunMetrics};
+use std::{collections::HashMap, time::Instant};
+use tokio::join;
+use tracing::{info, info_span};
+
+/// Builds an inventory from one UISP NMS, with client rates from UCRM if
+/// a CRM key is configured.
+pub struct UispIntegration {
+    instance: UispInstance,
+    /// Prefix IDs with the instance name, when there are several instances.
+    namespace: bool,
+    /// The overall root site name, beneath which the instance is attached.
+    root_name: String,
+    /// The overall top-level sites, from `root_sites` or `auto_roots`.
+    roots: RootSelection,
+    options: UispOptions,
+    /// Where the overrides files live.
+    output: OutputConfig,
+}
+
+impl UispIntegration {
+    pub fn new(
+        instance: UispInstance,
+        namespace: bool,
+        root_name: &str,
+        roots: RootSelection,
+        options: UispOptions,
+        output: OutputConfig,
+    ) -> Self {
+        Self {
+            instance,
+            namespace,
+            root_name: root_name.to_string(),
+            roots,
+            options,
+            output,
+        }
+    }
+
+    /// The name of this instance's top site.
+    fn instance_root(&self) -> &str {
+        self.instance
+            .root_site_name
+            .as_deref()
+            .unwrap_or(&self.root_name)
+    }
+
+    /// The sites the data-link hierarchy is walked from: the instance's own
+    /// top site if it has one, else the overall root selection.
+    fn hierarchy_roots(&self) -> RootSelection {
+        match (&self.instance.root_site_name, &self.roots) {
+            (Some(name), _) => RootSelection::Named(vec![name.clone()]),
+            (None, RootSelection::Named(names)) => {
+                RootSelection::Named(names.iter().filter(|n| !n.is_empty()).cloned().collect())
+            }
+            (None, RootSelection::Automatic) => RootSelection::Automatic,
+        }
+    }
+}
+
+/// Connects to uISP and downloads all sites, devices and data-links.
+/// Please ensure that you setup `keys.ron` correctly, or this won't work.
+async fn pre_load_uisp(
+    instance: &UispInstance,
+    metrics: &RunMetrics,
+) -> Result<(Vec<Site>, Vec<Device>, Vec<DataLink>)> {
+    let (key, url) = instance.uisp();
+    let name = &instance.name;
+    let sites_future = metrics.timed_fetch(
+        name,
+        "sites",
+        nms_request_get_vec::<Site>("sites", key, url),
+    );
+    let devices_future = metrics.timed_fetch(
+        name,
+        "devices",
+        nms_request_get_vec::<Device>("devices?authorized=true", key, url),
+    );
+    let data_links_future = metrics.timed_fetch(
+        name,
+        "data-links",
+        nms_request_get_vec::<DataLink>("data-links", key, url),
+    );
+
+    let (sites, devices, data_links) = join!(sites_future, devices_future, data_links_future);
+    Ok((sites?, devices?, data_links?))
+}
+
+#[async_trait]
+impl Integration for UispIntegration {
+    fn name(&self) -> &str {
+        &self.instance.name
+    }
+
+    async fn fetch_inventory(&self, metrics: &RunMetrics) -> Result<Inventory> {
+        let name = &self.instance.name;
+        info!("Fetching sites, devices and data links from uISP");
+        let start_fetch = Instant::now();
+        let (uisp_data, crm_rates) = join!(
+            pre_load_uisp(&self.instance, metrics),
+            ucrm::pre_load_crm(&self.instance, metrics)
+        );
+        let (all_sites, all_devices, all_data_links) = uisp_data?;
+        let crm_rates = crm_rates?;
+        info!(elapsed = ?start_fetch.elapsed(), "Fetched all uISP data");
+        if self.instance.ucrm().is_some() {
+            info!(rates = crm_rates.len(), "Loaded client rates from UCRM");
+        }
+
+        let prefix = if self.namespace {
+            format!("{name}:")
+        } else {
+            String::new()
+        };
+        let sites_csv = topology::load_sites_csv(&self.output)?.with_prefix(&prefix);
+        let mut network_sites = topology::build_site_list(&all_sites, &sites_csv)?;
+        let hierarchy_report = topology::apply_hierarchy(
+            &mut network_sites,
+            &all_data_links,
+            &self.hierarchy_roots(),
+            self.options.hierarchy,
+        )?;
+        let infrastructure = &clients::create_network_infrastructure(&network_sites, &all_devices)?;
+        let measured = match &self.options.live_capacity {
+            Some(live) => {
+                let device_ids =
+                    topology::measured_devices(&network_sites, &all_devices, &all_data_links);
+                info!(devices = device_ids.len(), "Fetching device statistics");
+                let measured = metrics
+                    .timed_fetch(
+                        name,
+                        "devices/statistics",
+                        measured_capacity(&self.instance, device_ids, live),
+                    )
+                    .await;
+                let count = topology::apply_measured_access_points(
+                    &mut network_sites,
+                    &all_devices,
+                    &measured,
+                );
+                info!(count, "Set access point capacities from statistics");
+                measured
+            }
+            None => HashMap::new(),
+        };
+        if self.options.backhaul_nodes {
+            let count = topology::insert_backhaul_nodes(
+                &mut network_sites,
+                &all_data_links,
+                &all_devices,
+                &sites_csv,
+                &measured,
+            );
+            info!(count, "Inserted backhaul capacity nodes");
+        } else if !measured.is_empty() {
+            let count = topology::apply_measured_backhaul(
+                &mut network_sites,
+                &all_data_links,
+                &all_devices,
+                &measured,
+            );
+            info!(count, "Set site capacities from backhaul statistics");
+        }
+        let classify = info_span!("classify").entered();
+        let active = clients::active_clients(&all_sites, &crm_rates, metrics);
+        let mut clients = clients::single_entry_clients(&active, &all_devices, &all_data_links)?;
+        let complex_clients = clients::complex_clients(
+            &active,
+            &all_devices,
+            &all_data_links,
+            &mut network_sites,
+            metrics,
+        )?;
+        clients.extend_from_slice(&complex_clients);
+        info!(circuits = clients.len(), "Classified clients");
+        drop(classify);
+        clients.extend_from_slice(infrastructure);
+
+        let mut inventory = Inventory {
+            sites: network_sites,
+            circuits: clients,
+            locations: topology::locations(&all_sites, &all_devices),
+            ..Default::default()
+        };
+        if let Some(report) = hierarchy_report {
+            let report_name = if self.namespace {
+                format!("Hierarchy-{name}.csv")
+            } else {
+                "Hierarchy.csv".to_string()
+            };
+            inventory.files.add_supporting(&report_name, report);
+        }
+        if self.namespace {
+            inventory.prefix_ids(&prefix);
+        }
+
+        // Attach the instance's top site beneath its configured parent (by ID
+        // or name, resolved after merging), or the overall root.
+        let instance_root = self.instance_root();
+        let parent = self
+            .instance
+            .parent_site
+            .clone()
+            .or_else(|| Some(self.root_name.clone()).filter(|r| !r.is_empty()));
+        if let Some(parent) = parent.filter(|p| p != instance_root) {
+            for site in inventory
+                .sites
+                .values_mut()
+                .filter(|s| s.name == instance_root)
+            {
+                site.parent = Some(parent.clone());
+            }
+        }
+
+        Ok(inventory)
+    }
+}
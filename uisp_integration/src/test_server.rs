@@ -0,0 +1,50 @@
+//! A local HTTP server with canned JSON responses, for testing API clients.
+
+use std::sync::{Arc, Mutex};
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::TcpListener,
+};
+
+/// Serves `fixtures`, a list of response bodies by request path, returning
+/// the base URL and the head of every request handled. `{base}` in a body is
+/// replaced with the base URL, so links can point back at the server.
+/// Unknown paths get a 404.
+pub async fn serve_fixtures(
+    fixtures: &'static [(&'static str, &'static str)],
+) -> (String, Arc<Mutex<Vec<String>>>) {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let base = format!("http://{}", listener.local_addr().unwrap());
+    let requests = Arc::new(Mutex::new(Vec::new()));
+    let (server_base, handled) = (base.clone(), requests.clone());
+    tokio::spawn(async move {
+        loop {
+            let (mut stream, _) = listener.accept().await.unwrap();
+            let mut request = Vec::new();
+            let mut buf = [0u8; 1024];
+            while !request.windows(4).any(|w| w == b"\r\n\r\n") {
+                let n = stream.read(&mut buf).await.unwrap();
+                if n == 0 {
+                    break;
+                }
+                request.extend_from_slice(&buf[..n]);
+            }
+            let request = String::from_utf8_lossy(&request).to_string();
+            let path = request.split_whitespace().nth(1).unwrap_or("").to_string();
+            handled.lock().unwrap().push(request);
+            let response = match fixtures.iter().find(|(p, _)| *p == path) {
+                Some((_, body)) => {
+                    let body = body.replace("{base}", &server_base);
+                    format!(
+                        "HTTP/1.1 200 OK\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{body}",
+                        body.len()
+                    )
+                }
+                None => "HTTP/1.1 404 Not Found\r\nContent-Length: 0\r\nConnection: close\r\n\r\n"
+                    .to_string(),
+            };
+            stream.write_all(response.as_bytes()).await.unwrap();
+        }
+    });
+    (base, requests)
+}
@@ -0,0 +1,159 @@
+use super::{Overrides, DEFAULT_RATES};
+use crate::unms::{DataLink, Device};
+use integration_core::LqSite;
+use std::collections::{BTreeMap, HashMap};
+
+/// Appended to the child site's ID to form its backhaul node's ID.
+const BACKHAUL_SUFFIX: &str = "-backhaul";
+
+/// Whether a site is a capacity node added by `insert_backhaul_nodes`.
+pub fn is_backhaul_node(id: &str) -> bool {
+    id.ends_with(BACKHAUL_SUFFIX)
+}
+
+/// A point-to-point link carrying a child site's traffic from its parent.
+struct Backhaul {
+    parent: String,
+    child: String,
+    /// Reported (download, upload) capacity in Mbps, summed over parallel links.
+    reported: Option<(usize, usize)>,
+    /// Measured capacity in Mbps, from live statistics, summed over parallel links.
+    measured: Option<(usize, usize)>,
+}
+
+/// Reported link capacity from a device's overview, in Mbps.
+fn device_capacity(device: Option<&&Device>) -> Option<(usize, usize)> {
+    let overview = device?.overview.as_ref()?;
+    let download = overview.downlinkCapacity?;
+    let upload = overview.uplinkCapacity?;
+    Some((
+        (download / 1_000_000) as usize,
+        (upload / 1_000_000) as usize,
+    ))
+}
+
+fn add(total: &mut Option<(usize, usize)>, capacity: Option<(usize, usize)>) {
+    if let Some((download, upload)) = capacity {
+        let (d, u) = total.unwrap_or((0, 0));
+        *total = Some((d + download, u + upload));
+    }
+}
+
+/// Finds data links between infrastructure sites where one site is the
+/// other's parent, keyed by child site ID.
+fn find_backhauls(
+    sites: &HashMap<String, LqSite>,
+    data_links: &[DataLink],
+    devices: &[Device],
+    measured: &HashMap<String, (usize, usize)>,
+) -> HashMap<String, Backhaul> {
+    let devices: HashMap<&str, &Device> = devices
+        .iter()
+        .map(|d| (d.identification.id.as_str(), d))
+        .collect();
+
+    let mut backhauls = HashMap::<String, Backhaul>::new();
+    for link in data_links.iter() {
+        let (from_site, to_site) = match (&link.from.site, &link.to.site) {
+            (Some(from), Some(to)) => (&from.identification.id, &to.identification.id),
+            _ => continue,
+        };
+        let (from, to) = match (sites.get(from_site), sites.get(to_site)) {
+            (Some(from), Some(to)) if from.id != to.id => (from, to),
+            _ => continue,
+        };
+        let from_device = &link.from.device.identification.id;
+        let to_device = &link.to.device.identification.id;
+        let (parent, child, parent_device, child_device) = if to.parent.as_ref() == Some(&from.id) {
+            (from, to, from_device, to_device)
+        } else if from.parent.as_ref() == Some(&to.id) {
+            (to, from, to_device, from_device)
+        } else {
+            continue;
+        };
+
+        let backhaul = backhauls.entry(child.id.clone()).or_insert(Backhaul {
+            parent: parent.id.clone(),
+            child: child.id.clone(),
+            reported: None,
+            measured: None,
+        });
+        add(
+            &mut backhaul.reported,
+            device_capacity(devices.get(parent_device.as_str()))
+                .or_else(|| device_capacity(devices.get(child_device.as_str()))),
+        );
+        add(
+            &mut backhaul.measured,
+            measured
+                .get(parent_device)
+                .or_else(|| measured.get(child_device))
+                .cloned(),
+        );
+    }
+    backhauls
+}
+
+/// Inserts a capacity node between each parent and child infrastructure site
+/// joined by a data link, so shaping respects the backhaul radio that
+/// actually limits the child site. The node's capacity comes from live
+/// measurements if available, then `Sites.csv` if listed, then the capacity
+/// UISP reports for the parent side's radio (or the child side's), and
+/// finally 1 Gbps. Returns the number of nodes inserted.
+pub fn insert_backhaul_nodes(
+    sites: &mut HashMap<String, LqSite>,
+    data_links: &[DataLink],
+    devices: &[Device],
+    sites_csv: &Overrides,
+    measured: &HashMap<String, (usize, usize)>,
+) -> usize {
+    let backhauls = find_backhauls(sites, data_links, devices, measured);
+    let count = backhauls.len();
+    for backhaul in backhauls.into_values() {
+        let child_name = sites[&backhaul.child].name.clone();
+        let id = format!("{}{BACKHAUL_SUFFIX}", backhaul.child);
+        let name = format!("{child_name} Backhaul");
+        let (download_mbps, upload_mbps) = backhaul
+            .measured
+            .or_else(|| sites_csv.lookup(&id, &name))
+            .or(backhaul.reported)
+            .unwrap_or(DEFAULT_RATES);
+        sites.insert(
+            id.clone(),
+            LqSite {
+                id: id.clone(),
+                name,
+                parent: Some(backhaul.parent),
+                children: Vec::new(),
+                access_points: BTreeMap::new(),
+                download_mbps,
+                upload_mbps,
+            },
+        );
+        if let Some(child) = sites.get_mut(&backhaul.child) {
+            child.parent = Some(id);
+        }
+    }
+    count
+}
+
+/// Without backhaul nodes, limits each child site to its backhaul's measured
+/// capacity instead. Returns the number of sites updated.
+pub fn apply_measured_backhaul(
+    sites: &mut HashMap<String, LqSite>,
+    data_links: &[DataLink],
+    devices: &[Device],
+    measured: &HashMap<String, (usize, usize)>,
+) -> usize {
+    let mut count = 0;
+    for backhaul in find_backhauls(sites, data_links, devices, measured).into_values() {
+        if let (Some((download, upload)), Some(site)) =
+            (backhaul.measured, sites.get_mut(&backhaul.child))
+        {
+            site.download_mbps = download;
+            site.upload_mbps = upload;
+            count += 1;
+        }
+    }
+    count
+}
@@ -1,55 +1,465 @@
-use anyhow::Result;
-use std::{collections::HashMap, fs::File, io::Read, path::Path};
-
-pub fn load_sites_csv() -> Result<HashMap<String, (usize, usize)>> {
-    let path = Path::new("Sites.csv");
-    if path.exists() {
-        let mut result = HashMap::<String, (usize, usize)>::new();
-        let mut data = String::new();
-        let mut f = File::open(path)?;
-        f.read_to_string(&mut data)?;
-        data.split('\n').skip(1).for_each(|line| {
-            if !line.trim().is_empty() {
-                let cols = line.split(',').collect::<Vec<&str>>();
-                let name = cols[0].trim();
-                let download = cols[1].trim();
-                let upload = cols[2].trim();
-                result.insert(
-                    name.to_string(),
-                    (
-                        download.parse().unwrap_or(1_000),
-                        upload.parse().unwrap_or(1_000),
+use anyhow::{Error, Result};
+use integration_core::OutputConfig;
+use std::{
+    collections::HashMap,
+    fmt, fs,
+    path::{Path, PathBuf},
+};
+use tracing::warn;
+
+/// A problem with one line of an overrides file. The line is kept as-is and
+/// its values ignored until it's fixed.
+#[derive(Debug, Clone)]
+pub struct OverrideError {
+    pub file: String,
+    pub line: usize,
+    pub message: String,
+}
+
+impl fmt::Display for OverrideError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}: {}", self.file, self.line, self.message)
+    }
+}
+
+/// A row of an overrides file, with one field per header column.
+struct Row {
+    fields: Vec<String>,
+    /// Line number in the loaded file, or 0 for rows added since.
+    line: usize,
+    rates: Option<(usize, usize)>,
+}
+
+/// A line of an overrides file, in file order.
+enum Line {
+    Row(Row),
+    /// A comment, blank line or unparseable row, written back verbatim.
+    Verbatim(String),
+}
+
+/// A hand-editable capacity file such as `Sites.csv` or `AccessPoints.csv`.
+///
+/// Rows are keyed by the UISP `ID` column, with a name column as a hint for
+/// humans and `Download` and `Upload` in Mbps. Rows without an ID (as in
+/// files written before IDs were added) are matched by name, and gain an ID
+/// when the file is saved. Any other columns, `#` comments and rows for
+/// entries that no longer exist are preserved; new entries are appended.
+pub struct Overrides {
+    path: PathBuf,
+    /// Comments and blank lines above the header.
+    preamble: Vec<String>,
+    header: Vec<String>,
+    lines: Vec<Line>,
+    /// Line indexes of rows with an ID.
+    by_id: HashMap<String, usize>,
+    /// Line indexes of rows without an ID, by name.
+    by_name: HashMap<String, usize>,
+    id_col: usize,
+    name_col: usize,
+    /// Prepended to IDs when looking them up, for namespaced sources.
+    prefix: String,
+    migrated: bool,
+    errors: Vec<OverrideError>,
+}
+
+/// A record read from an overrides file, with its line number and text.
+struct Record {
+    line: usize,
+    text: String,
+    /// The trimmed fields, or `None` for blank and `#` comment lines.
+    fields: Option<Vec<String>>,
+}
+
+/// Splits `data` into CSV records, keeping blank and comment lines so they
+/// can be written back. Quoted fields may span lines.
+fn read_records(data: &str) -> Result<Vec<Record>> {
+    let mut reader = csv::ReaderBuilder::new()
+        .has_headers(false)
+        .flexible(true)
+        .trim(csv::Trim::All)
+        .from_reader(data.as_bytes());
+    let mut result = Vec::new();
+    let mut record = csv::StringRecord::new();
+    let mut end = 0;
+    let mut line = 1;
+    while reader.read_record(&mut record)? {
+        let position = record
+            .position()
+            .cloned()
+            .unwrap_or_else(csv::Position::new);
+        let start = position.byte() as usize;
+        // The reader skips blank lines between records.
+        for _ in data[end..start].lines() {
+            result.push(Record {
+                line,
+                text: String::new(),
+                fields: None,
+            });
+            line += 1;
+        }
+        end = reader.position().byte() as usize;
+        line = position.line() as usize;
+        let text = data[start..end].trim_end_matches(['\r', '\n']).to_string();
+        let comment = record.iter().all(|f| f.is_empty())
+            || record.get(0).is_some_and(|f| f.starts_with('#'));
+        result.push(Record {
+            line,
+            fields: (!comment).then(|| record.iter().map(|f| f.to_string()).collect()),
+            text,
+        });
+        line = reader.position().line() as usize;
+    }
+    Ok(result)
+}
+
+fn column(header: &[String], name: &str) -> Option<usize> {
+    header.iter().position(|h| h.eq_ignore_ascii_case(name))
+}
+
+impl Overrides {
+    /// Loads `path`, or starts an empty file with the columns `ID`, `key`,
+    /// `Download` and `Upload` if it doesn't exist. A file without a header
+    /// row is read with those columns, or without `ID` if its rows are one
+    /// field short. Rows that can't be used are recorded as errors rather
+    /// than failing the load; a header without `Download` and `Upload`
+    /// columns is an error.
+    pub fn load(path: impl AsRef<Path>, key: &str) -> Result<Self> {
+        let path = path.as_ref().to_path_buf();
+        let file = path.display().to_string();
+        let mut result = Self {
+            path: path.clone(),
+            header: ["ID", key, "Download", "Upload"]
+                .iter()
+                .map(|h| h.to_string())
+                .collect(),
+            preamble: Vec::new(),
+            lines: Vec::new(),
+            by_id: HashMap::new(),
+            by_name: HashMap::new(),
+            id_col: 0,
+            name_col: 1,
+            prefix: String::new(),
+            migrated: false,
+            errors: Vec::new(),
+        };
+        if !path.exists() {
+            return Ok(result);
+        }
+
+        let data = fs::read_to_string(&path)?;
+        let records = read_records(&data).map_err(|e| Error::msg(format!("{file}: {e}")))?;
+        let mut records = records.into_iter().peekable();
+        // Comments and blank lines before the header are kept above it.
+        while let Some(record) = records.next_if(|r| r.fields.is_none()) {
+            result.preamble.push(record.text);
+        }
+
+        let mut has_id = true;
+        if let Some(first) = records
+            .peek()
+            .and_then(|r| Some((r.line, r.fields.clone()?)))
+        {
+            let (line, fields) = first;
+            let is_header = fields.iter().any(|f| {
+                ["ID", key, "Download", "Upload"]
+                    .iter()
+                    .any(|h| f.eq_ignore_ascii_case(h))
+            });
+            if is_header {
+                if column(&fields, "Download").is_none() || column(&fields, "Upload").is_none() {
+                    return Err(Error::msg(format!(
+                        "{file}:{line}: the header needs Download and Upload columns (found {})",
+                        fields.join(", ")
+                    )));
+                }
+                has_id = column(&fields, "ID").is_some();
+                result.header = fields;
+                records.next();
+            } else {
+                // Older files had no header and no ID: `name,download,upload`.
+                if fields.len() < result.header.len() {
+                    result.header.remove(0);
+                    has_id = false;
+                }
+                result.errors.push(OverrideError {
+                    file: file.clone(),
+                    line,
+                    message: format!(
+                        "no header row; reading columns as {}",
+                        result.header.join(", ")
                     ),
-                );
+                });
             }
-        });
-        Ok(result)
-    } else {
-        Ok(HashMap::new())
-    }
-}
-
-pub fn load_aps_csv() -> Result<HashMap<String, (usize, usize)>> {
-    let path = Path::new("AccessPoints.csv");
-    if path.exists() {
-        let mut result = HashMap::<String, (usize, usize)>::new();
-        let mut data = String::new();
-        let mut f = File::open(path)?;
-        f.read_to_string(&mut data)?;
-        data.split('\n').skip(1).for_each(|line| {
-            if !line.trim().is_empty() {
-                let cols = line.split(',').collect::<Vec<&str>>();
-                let name = cols[0].trim();
-                let download = cols[1].trim();
-                let upload = cols[2].trim();
-                result.insert(
-                    name.to_string(),
-                    (download.parse().unwrap(), upload.parse().unwrap()),
-                );
+        }
+        if !has_id {
+            // A name-keyed file: add an empty ID column, filled in on save.
+            if column(&result.header, "ID").is_none() {
+                result.header.insert(0, "ID".to_string());
             }
-        });
+            result.migrated = true;
+        }
+        let id_col = column(&result.header, "ID").unwrap_or(0);
+        let name_col = column(&result.header, key).unwrap_or(if id_col == 0 { 1 } else { 0 });
+        result.id_col = id_col;
+        result.name_col = name_col;
+        let download_col = column(&result.header, "Download").unwrap_or(2);
+        let upload_col = column(&result.header, "Upload").unwrap_or(3);
+
+        for record in records {
+            let line_number = record.line;
+            let line = record.text;
+            let error = |message: String| OverrideError {
+                file: file.clone(),
+                line: line_number,
+                message,
+            };
+            let mut fields = match record.fields {
+                Some(mut fields) => {
+                    if !has_id {
+                        fields.insert(0, String::new());
+                    }
+                    fields
+                }
+                None => {
+                    result.lines.push(Line::Verbatim(line));
+                    continue;
+                }
+            };
+            fields.resize(fields.len().max(result.header.len()), String::new());
+
+            let id = fields[id_col].clone();
+            let name = fields[name_col].clone();
+            let (index, label) = if !id.is_empty() {
+                (&mut result.by_id, id)
+            } else if !name.is_empty() {
+                (&mut result.by_name, name)
+            } else {
+                result
+                    .errors
+                    .push(error("row has neither an ID nor a name".to_string()));
+                result.lines.push(Line::Verbatim(line));
+                continue;
+            };
+            if index.contains_key(&label) {
+                result
+                    .errors
+                    .push(error(format!("duplicate entry '{label}', ignored")));
+                result.lines.push(Line::Verbatim(line));
+                continue;
+            }
+            index.insert(label.clone(), result.lines.len());
+
+            let parse = |col: usize| fields[col].parse::<usize>().ok().filter(|v| *v > 0);
+            let rates = match (parse(download_col), parse(upload_col)) {
+                (Some(download), Some(upload)) => Some((download, upload)),
+                _ => {
+                    result.errors.push(error(format!(
+                        "'{label}' needs whole-number Download and Upload rates (got '{}', '{}')",
+                        fields[download_col], fields[upload_col]
+                    )));
+                    None
+                }
+            };
+            result.lines.push(Line::Row(Row {
+                fields,
+                line: line_number,
+                rates,
+            }));
+        }
         Ok(result)
-    } else {
-        Ok(HashMap::new())
+    }
+
+    /// Looks IDs up as `{prefix}{id}`, matching IDs saved after a namespaced
+    /// source's IDs were prefixed.
+    pub fn with_prefix(mut self, prefix: &str) -> Self {
+        self.prefix = prefix.to_string();
+        self
+    }
+
+    fn row(&self, index: usize) -> Option<&Row> {
+        match &self.lines[index] {
+            Line::Row(row) => Some(row),
+            Line::Verbatim(_) => None,
+        }
+    }
+
+    fn row_mut(&mut self, index: usize) -> Option<&mut Row> {
+        match &mut self.lines[index] {
+            Line::Row(row) => Some(row),
+            Line::Verbatim(_) => None,
+        }
+    }
+
+    /// Logs any problems found while loading.
+    pub fn report_errors(&self) {
+        if self.migrated {
+            warn!(
+                file = %self.path.display(),
+                "No ID column; matching rows by name and adding IDs"
+            );
+        }
+        for error in self.errors.iter() {
+            warn!("{error}");
+        }
+    }
+
+    /// The configured rates for an entry, by ID, or by name for rows that
+    /// don't have an ID yet.
+    pub fn lookup(&self, id: &str, name: &str) -> Option<(usize, usize)> {
+        let id = format!("{}{id}", self.prefix);
+        self.by_id
+            .get(&id)
+            .or_else(|| self.by_name.get(name))
+            .and_then(|index| self.row(*index))
+            .and_then(|row| row.rates)
+    }
+
+    /// Records an entry's current rates. Existing rows, which the operator
+    /// may have edited, keep their rates: a row found by ID has its name
+    /// updated, and a row found by name gains its ID. New entries are appended.
+    pub fn insert(&mut self, id: &str, name: &str, download: usize, upload: usize) {
+        let (id_col, name_col) = (self.id_col, self.name_col);
+        if let Some(index) = self.by_id.get(id).cloned() {
+            if let Some(row) = self.row_mut(index) {
+                row.fields[name_col] = name.to_string();
+            }
+            return;
+        }
+        if let Some(index) = self.by_name.remove(name) {
+            if let Some(row) = self.row_mut(index) {
+                row.fields[id_col] = id.to_string();
+            }
+            self.by_id.insert(id.to_string(), index);
+            return;
+        }
+        let download_col = column(&self.header, "Download").unwrap_or(2);
+        let upload_col = column(&self.header, "Upload").unwrap_or(3);
+        let mut fields = vec![String::new(); self.header.len()];
+        fields[id_col] = id.to_string();
+        fields[name_col] = name.to_string();
+        fields[download_col] = download.to_string();
+        fields[upload_col] = upload.to_string();
+        self.by_id.insert(id.to_string(), self.lines.len());
+        self.lines.push(Line::Row(Row {
+            fields,
+            line: 0,
+            rates: Some((download, upload)),
+        }));
+    }
+
+    /// Logs a warning for each row without an ID that didn't match any
+    /// entry's name. Call after every current entry has been inserted.
+    pub fn report_stale(&self) {
+        let mut stale: Vec<&Row> = self
+            .by_name
+            .values()
+            .filter_map(|index| self.row(*index))
+            .collect();
+        stale.sort_by_key(|row| row.line);
+        for row in stale {
+            warn!(
+                "{}:{}: '{}' doesn't match anything by name; its speeds are unused",
+                self.path.display(),
+                row.line,
+                row.fields[self.name_col]
+            );
+        }
+    }
+
+    /// The file's new contents, quoting fields where needed.
+    pub fn to_csv(&self) -> Result<String> {
+        let mut writer = csv::WriterBuilder::new()
+            .flexible(true)
+            .from_writer(Vec::new());
+        writer.write_record(&self.header)?;
+        let mut data: String = self.preamble.iter().map(|l| format!("{l}\n")).collect();
+        data += &String::from_utf8(writer.into_inner()?)?;
+        for line in self.lines.iter() {
+            match line {
+                Line::Verbatim(text) => {
+                    data += text;
+                    data += "\n";
+                }
+                Line::Row(row) => {
+                    let mut writer = csv::WriterBuilder::new()
+                        .flexible(true)
+                        .from_writer(Vec::new());
+                    writer.write_record(&row.fields)?;
+                    data += &String::from_utf8(writer.into_inner()?)?;
+                }
+            }
+        }
+        Ok(data)
+    }
+}
+
+pub fn load_sites_csv(output: &OutputConfig) -> Result<Overrides> {
+    Overrides::load(output.path("Sites.csv"), "Site")
+}
+
+pub fn load_aps_csv(output: &OutputConfig) -> Result<Overrides> {
+    Overrides::load(output.path("AccessPoints.csv"), "AP")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn load(data: &str) -> (tempfile::TempDir, Result<Overrides>) {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("Sites.csv");
+        fs::write(&path, data).unwrap();
+        let overrides = Overrides::load(&path, "Site");
+        (dir, overrides)
+    }
+
+    #[test]
+    fn comments_extra_columns_and_quoting_are_kept() {
+        let data = "# Edit the speeds below\n\
+                    ID,Site,Download,Upload,Notes\n\
+                    s1,\"Tower, East\",100,50,\"two\nlines\"\n\
+                    \n\
+                    # retired\n\
+                    s2,Hill,abc,10,\n";
+        let (_dir, overrides) = load(data);
+        let overrides = overrides.unwrap();
+        assert_eq!(overrides.lookup("s1", ""), Some((100, 50)));
+        assert_eq!(overrides.lookup("s2", "Hill"), None);
+        assert_eq!(overrides.errors.len(), 1);
+        assert_eq!(overrides.errors[0].line, 7);
+        assert_eq!(overrides.to_csv().unwrap(), data);
+    }
+
+    #[test]
+    fn headerless_files_keep_their_first_row() {
+        let (_dir, overrides) = load("Tower,100,50\nHill,200,100\n");
+        let mut overrides = overrides.unwrap();
+        assert!(overrides.migrated);
+        assert_eq!(overrides.lookup("s1", "Tower"), Some((100, 50)));
+        assert_eq!(overrides.lookup("s2", "Hill"), Some((200, 100)));
+        assert!(overrides.errors[0].message.contains("no header row"));
+
+        overrides.insert("s1", "Tower", 1_000, 1_000);
+        assert_eq!(
+            overrides.to_csv().unwrap(),
+            "ID,Site,Download,Upload\ns1,Tower,100,50\n,Hill,200,100\n"
+        );
+    }
+
+    #[test]
+    fn headerless_files_with_ids() {
+        let (_dir, overrides) = load("s1,Tower,100,50\n");
+        let overrides = overrides.unwrap();
+        assert!(!overrides.migrated);
+        assert_eq!(overrides.lookup("s1", ""), Some((100, 50)));
+    }
+
+    #[test]
+    fn header_without_rates_is_an_error() {
+        let (_dir, overrides) = load("ID,Site,Down,Up\ns1,Tower,100,50\n");
+        let error = overrides.err().unwrap().to_string();
+        assert!(error.ends_with(
+            "Sites.csv:1: the header needs Download and Upload columns (found ID, Site, Down, Up)"
+        ));
     }
 }
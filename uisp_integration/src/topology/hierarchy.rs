@@ -0,0 +1,395 @@
+use crate::unms::DataLink;
+use anyhow::{Error, Result};
+use integration_core::{LqSite, RootSelection};
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet, VecDeque};
+use tracing::warn;
+
+/// Where infrastructure sites get their parents from.
+#[derive(Clone, Copy, Debug, Serialize, Deserialize, Default, PartialEq, Eq)]
+pub enum HierarchySource {
+    /// The parent set on each site in UISP.
+    #[default]
+    Declared,
+    /// Walk site-to-site data links outwards from the roots. Sites the walk
+    /// can't reach keep their declared parent.
+    DataLinks,
+}
+
+/// Infers each site's parent with a breadth-first walk over data links
+/// between sites, starting from every site in `root_ids` at once. Returns a
+/// map of site ID to parent site ID; the roots and unreachable sites are
+/// absent.
+pub fn infer_parents(
+    sites: &HashMap<String, LqSite>,
+    data_links: &[DataLink],
+    root_ids: &[String],
+) -> HashMap<String, String> {
+    let mut neighbors = HashMap::<&str, Vec<&str>>::new();
+    for link in data_links.iter() {
+        let (from, to) = match (&link.from.site, &link.to.site) {
+            (Some(from), Some(to)) => (&from.identification.id, &to.identification.id),
+            _ => continue,
+        };
+        if from != to && sites.contains_key(from) && sites.contains_key(to) {
+            neighbors.entry(from).or_default().push(to);
+            neighbors.entry(to).or_default().push(from);
+        }
+    }
+    // Visit neighbors in name order, so ties resolve the same way every run.
+    for list in neighbors.values_mut() {
+        list.sort_by(|a, b| sites[*a].name.cmp(&sites[*b].name).then(a.cmp(b)));
+        list.dedup();
+    }
+
+    let mut parents = HashMap::new();
+    let mut visited: HashSet<&str> = root_ids.iter().map(|id| id.as_str()).collect();
+    let mut queue: VecDeque<&str> = root_ids.iter().map(|id| id.as_str()).collect();
+    while let Some(current) = queue.pop_front() {
+        for next in neighbors.get(current).into_iter().flatten() {
+            if visited.insert(next) {
+                parents.insert(next.to_string(), current.to_string());
+                queue.push_back(next);
+            }
+        }
+    }
+    parents
+}
+
+/// Compares declared and inferred parents, as CSV for the operator to review.
+pub fn hierarchy_report(
+    sites: &HashMap<String, LqSite>,
+    inferred: &HashMap<String, String>,
+    root_ids: &[String],
+    used: HierarchySource,
+) -> String {
+    let name = |id: Option<&String>| {
+        id.and_then(|id| sites.get(id))
+            .map(|s| s.name.clone())
+            .unwrap_or_default()
+    };
+    let mut rows: Vec<&LqSite> = sites.values().collect();
+    rows.sort_by(|a, b| a.name.cmp(&b.name));
+
+    let mut csv = "Site,Declared Parent,Inferred Parent,Status,Used\n".to_string();
+    for site in rows {
+        let declared = site.parent.as_ref();
+        let found = inferred.get(&site.id);
+        let status = if root_ids.contains(&site.id) {
+            "root"
+        } else if found.is_none() {
+            "unreachable"
+        } else if found == declared {
+            "agrees"
+        } else {
+            "differs"
+        };
+        let used = match (used, found) {
+            (HierarchySource::DataLinks, Some(_)) => "inferred",
+            _ => "declared",
+        };
+        csv += &format!(
+            "{},{},{},{status},{used}\n",
+            site.name,
+            name(declared),
+            name(found)
+        );
+    }
+    csv
+}
+
+/// The IDs of the sites the walk starts from, sorted: the sites with the
+/// selected names, or every site without a (known) parent.
+fn root_ids(sites: &HashMap<String, LqSite>, roots: &RootSelection) -> Vec<String> {
+    let mut ids: Vec<String> = sites
+        .values()
+        .filter(|s| match roots {
+            RootSelection::Named(names) => names.contains(&s.name),
+            RootSelection::Automatic => s.parent.as_ref().is_none_or(|p| !sites.contains_key(p)),
+        })
+        .map(|s| s.id.clone())
+        .collect();
+    ids.sort();
+    ids
+}
+
+/// Infers parents from data links, starting at the `roots`, and applies
+/// them if `source` asks for them. Returns the comparison report. If none of
+/// the roots are found, inferring parents is an error; with declared
+/// parents, it's only a warning and there is no report.
+pub fn apply_hierarchy(
+    sites: &mut HashMap<String, LqSite>,
+    data_links: &[DataLink],
+    roots: &RootSelection,
+    source: HierarchySource,
+) -> Result<Option<String>> {
+    let root_ids = root_ids(sites, roots);
+    if root_ids.is_empty() {
+        let looked_for = match roots {
+            RootSelection::Named(names) => format!("sites named {}", names.join(", ")),
+            RootSelection::Automatic => "sites without a parent".to_string(),
+        };
+        let message = format!("No root site found for the data-link hierarchy ({looked_for})");
+        if source == HierarchySource::DataLinks {
+            return Err(Error::msg(message));
+        }
+        warn!("{message}");
+        return Ok(None);
+    }
+    let inferred = infer_parents(sites, data_links, &root_ids);
+    let report = hierarchy_report(sites, &inferred, &root_ids, source);
+    if source == HierarchySource::DataLinks {
+        // The roots are the top of the walk, whatever UISP says their parents are.
+        for id in root_ids.iter() {
+            if let Some(root) = sites.get_mut(id) {
+                root.parent = None;
+            }
+        }
+        // Parents are applied before their children, so each check sees the
+        // final path from the parent to the root.
+        let depth = |id: &str| {
+            let mut depth = 0;
+            let mut current = id;
+            while let Some(parent) = inferred.get(current) {
+                depth += 1;
+                current = parent;
+            }
+            depth
+        };
+        let mut ordered: Vec<(usize, &String, &String)> = inferred
+            .iter()
+            .map(|(child, parent)| (depth(child), child, parent))
+            .collect();
+        ordered.sort();
+        for (_, child, parent) in ordered {
+            if is_ancestor(sites, child, parent) {
+                warn!(
+                    site = sites[child].name,
+                    parent = sites[parent].name,
+                    "Ignoring inferred parent, as it would make a cycle"
+                );
+                continue;
+            }
+            if let Some(site) = sites.get_mut(child) {
+                site.parent = Some(parent.clone());
+            }
+        }
+    }
+    Ok(Some(report))
+}
+
+/// Whether `ancestor` is `site` or one of its parents, following the
+/// current parents.
+fn is_ancestor(sites: &HashMap<String, LqSite>, ancestor: &str, site: &str) -> bool {
+    let mut seen = HashSet::new();
+    let mut current = Some(site);
+    while let Some(id) = current {
+        if id == ancestor {
+            return true;
+        }
+        if !seen.insert(id) {
+            return false;
+        }
+        current = sites.get(id).and_then(|s| s.parent.as_deref());
+    }
+    false
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::BTreeMap;
+
+    fn sites(list: &[(&str, Option<&str>)]) -> HashMap<String, LqSite> {
+        list.iter()
+            .map(|(id, parent)| {
+                let site = LqSite {
+                    id: id.to_string(),
+                    name: id.to_uppercase(),
+                    parent: parent.map(|p| p.to_string()),
+                    children: Vec::new(),
+                    access_points: BTreeMap::new(),
+                    download_mbps: 100,
+                    upload_mbps: 100,
+                };
+                (id.to_string(), site)
+            })
+            .collect()
+    }
+
+    fn links(pairs: &[(&str, &str)]) -> Vec<DataLink> {
+        let end = |site: &str| {
+            serde_json::json!({
+                "device": {"identification": {"id": format!("dev-{site}"), "name": site}},
+                "site": {"identification": {"id": site, "name": site}},
+            })
+        };
+        pairs
+            .iter()
+            .enumerate()
+            .map(|(i, (from, to))| {
+                serde_json::from_value(serde_json::json!({
+                    "id": i.to_string(),
+                    "from": end(from),
+                    "to": end(to),
+                }))
+                .unwrap()
+            })
+            .collect()
+    }
+
+    fn parents(sites: &HashMap<String, LqSite>) -> Vec<(String, Option<String>)> {
+        let mut result: Vec<_> = sites
+            .values()
+            .map(|s| (s.id.clone(), s.parent.clone()))
+            .collect();
+        result.sort();
+        result
+    }
+
+    fn named(name: &str) -> RootSelection {
+        RootSelection::Named(vec![name.to_string()])
+    }
+
+    #[test]
+    fn parents_are_inferred_breadth_first() {
+        let sites = sites(&[("root", None), ("a", None), ("b", None), ("c", None)]);
+        let links = links(&[("c", "b"), ("a", "root"), ("b", "root"), ("a", "c")]);
+        let inferred = infer_parents(&sites, &links, &["root".to_string()]);
+        let mut inferred: Vec<_> = inferred.into_iter().collect();
+        inferred.sort();
+        assert_eq!(
+            inferred,
+            vec![
+                ("a".to_string(), "root".to_string()),
+                ("b".to_string(), "root".to_string()),
+                ("c".to_string(), "a".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn data_links_replace_a_root_parent_inside_its_subtree() {
+        // UISP says the root's parent is "b", and "b"'s parent is "a".
+        let mut sites = sites(&[
+            ("root", Some("b")),
+            ("a", Some("root")),
+            ("b", Some("a")),
+            ("x", None),
+        ]);
+        let links = links(&[("root", "a"), ("a", "b")]);
+        apply_hierarchy(
+            &mut sites,
+            &links,
+            &named("ROOT"),
+            HierarchySource::DataLinks,
+        )
+        .unwrap();
+        assert_eq!(
+            parents(&sites),
+            vec![
+                ("a".to_string(), Some("root".to_string())),
+                ("b".to_string(), Some("a".to_string())),
+                ("root".to_string(), None),
+                ("x".to_string(), None),
+            ]
+        );
+        let mut root = sites["root"].clone();
+        root.take_children(&sites);
+        assert_eq!(root.children[0].children[0].id, "b");
+    }
+
+    #[test]
+    fn declared_mode_only_reports() {
+        let mut sites = sites(&[("root", None), ("a", None)]);
+        let before = parents(&sites);
+        let report = apply_hierarchy(
+            &mut sites,
+            &links(&[("root", "a")]),
+            &named("ROOT"),
+            HierarchySource::Declared,
+        )
+        .unwrap()
+        .unwrap();
+        assert_eq!(parents(&sites), before);
+        assert!(report.contains("A,,ROOT,differs,declared"), "{report}");
+    }
+
+    #[test]
+    fn every_selected_root_starts_a_walk() {
+        let mut tree = sites(&[
+            ("east", None),
+            ("west", Some("x")),
+            ("a", None),
+            ("b", None),
+            ("x", None),
+        ]);
+        let links = links(&[("east", "a"), ("west", "b"), ("a", "b")]);
+        let roots = RootSelection::Named(vec!["EAST".to_string(), "WEST".to_string()]);
+        let report = apply_hierarchy(&mut tree, &links, &roots, HierarchySource::DataLinks)
+            .unwrap()
+            .unwrap();
+        assert_eq!(
+            parents(&tree),
+            vec![
+                ("a".to_string(), Some("east".to_string())),
+                ("b".to_string(), Some("west".to_string())),
+                ("east".to_string(), None),
+                ("west".to_string(), None),
+                ("x".to_string(), None),
+            ]
+        );
+        assert!(report.contains("WEST,X,,root,declared"), "{report}");
+    }
+
+    #[test]
+    fn automatic_roots_are_the_parentless_sites() {
+        let mut tree = sites(&[("root", None), ("a", Some("gone")), ("b", Some("a"))]);
+        apply_hierarchy(
+            &mut tree,
+            &links(&[("root", "b")]),
+            &RootSelection::Automatic,
+            HierarchySource::DataLinks,
+        )
+        .unwrap();
+        // "a" has an unknown parent, so it's a root too; "b" is linked to "root".
+        assert_eq!(
+            parents(&tree),
+            vec![
+                ("a".to_string(), None),
+                ("b".to_string(), Some("root".to_string())),
+                ("root".to_string(), None),
+            ]
+        );
+    }
+
+    #[test]
+    fn a_missing_root_is_reported() {
+        let mut tree = sites(&[("a", None), ("b", None)]);
+        let links = links(&[("a", "b")]);
+        let error = apply_hierarchy(
+            &mut tree,
+            &links,
+            &named("ROOT"),
+            HierarchySource::DataLinks,
+        )
+        .unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "No root site found for the data-link hierarchy (sites named ROOT)"
+        );
+        let report =
+            apply_hierarchy(&mut tree, &links, &named("ROOT"), HierarchySource::Declared).unwrap();
+        assert!(report.is_none());
+    }
+
+    #[test]
+    fn cycles_are_detected() {
+        let tree = sites(&[("root", None), ("a", Some("root")), ("b", Some("a"))]);
+        assert!(is_ancestor(&tree, "a", "b"));
+        assert!(is_ancestor(&tree, "b", "b"));
+        assert!(!is_ancestor(&tree, "b", "a"));
+        let looped = sites(&[("a", Some("b")), ("b", Some("a"))]);
+        assert!(!is_ancestor(&looped, "root", "a"));
+    }
+}
@@ -0,0 +1,70 @@
+use crate::unms::{DataLink, Device};
+use integration_core::{LqAccessPoint, LqSite};
+use std::collections::{BTreeSet, HashMap};
+
+fn is_access_point(device: &Device) -> bool {
+    device.identification.role.as_deref() == Some("ap")
+}
+
+/// The devices worth measuring: access points on infrastructure sites, and
+/// radios at either end of a link between two infrastructure sites.
+pub fn measured_devices(
+    sites: &HashMap<String, LqSite>,
+    devices: &[Device],
+    data_links: &[DataLink],
+) -> Vec<String> {
+    let mut ids = BTreeSet::new();
+    for device in devices.iter().filter(|d| is_access_point(d)) {
+        if let Some(site) = &device.identification.site {
+            if sites.contains_key(&site.id) {
+                ids.insert(device.identification.id.clone());
+            }
+        }
+    }
+    for link in data_links.iter() {
+        if let (Some(from), Some(to)) = (&link.from.site, &link.to.site) {
+            if sites.contains_key(&from.identification.id)
+                && sites.contains_key(&to.identification.id)
+            {
+                ids.insert(link.from.device.identification.id.clone());
+                ids.insert(link.to.device.identification.id.clone());
+            }
+        }
+    }
+    ids.into_iter().collect()
+}
+
+/// Adds each measured access point to its site with its measured rates.
+/// Clients are attached when the topology is built; access points that end
+/// up without clients are left out. Returns the number of access points set.
+pub fn apply_measured_access_points(
+    sites: &mut HashMap<String, LqSite>,
+    devices: &[Device],
+    measured: &HashMap<String, (usize, usize)>,
+) -> usize {
+    let mut count = 0;
+    for device in devices.iter().filter(|d| is_access_point(d)) {
+        let (site, name) = match (&device.identification.site, &device.identification.name) {
+            (Some(site), Some(name)) => (site, name.replace(',', "_")),
+            _ => continue,
+        };
+        let (site, (download_mbps, upload_mbps)) = match (
+            sites.get_mut(&site.id),
+            measured.get(&device.identification.id),
+        ) {
+            (Some(site), Some(capacity)) => (site, *capacity),
+            _ => continue,
+        };
+        site.access_points.insert(
+            device.identification.id.clone(),
+            LqAccessPoint {
+                name,
+                download_mbps,
+                upload_mbps,
+                clients: Vec::new(),
+            },
+        );
+        count += 1;
+    }
+    count
+}
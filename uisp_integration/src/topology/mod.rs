@@ -1,31 +1,31 @@
-mod site;
-pub use site::*;
-mod access_point;
-pub use access_point::*;
+mod backhaul;
 mod csv;
-use crate::unms::Keys;
-use crate::{clients::LqClientSite, unms::Site};
+mod hierarchy;
+mod live;
+use crate::{
+    clients::LqClientSite,
+    unms::{Device, Site},
+};
 use anyhow::Result;
+pub use backhaul::{apply_measured_backhaul, insert_backhaul_nodes, is_backhaul_node};
 pub use csv::*;
+pub use hierarchy::{apply_hierarchy, HierarchySource};
+pub use integration_core::LqSite;
+use integration_core::{
+    diagnose_parentless, parentless_csv, Excluded, Location, OutputConfig, Outputs, RootSelection,
+    TopologyBuilder,
+};
+pub use live::{apply_measured_access_points, measured_devices};
 use std::collections::HashMap;
-use std::fs::File;
-use std::io::Write;
+use tracing::warn;
 
-pub fn build_site_tree(sites: &HashMap<String, LqSite>) -> Result<LqSite> {
-    let keys = Keys::load()?;
-    let root_name = keys.root();
-    let mut root = sites
-        .iter()
-        .find(|s| s.1.name == root_name)
-        .unwrap()
-        .1
-        .clone();
-    root.take_children(sites);
-    Ok(root)
-}
+/// Capacity in Mbps of sites and access points without an override.
+pub const DEFAULT_RATES: (usize, usize) = (1_000, 1_000);
 
-pub fn build_site_list(all_sites: &[Site]) -> Result<HashMap<String, LqSite>> {
-    let sites_csv = load_sites_csv()?;
+pub fn build_site_list(
+    all_sites: &[Site],
+    sites_csv: &Overrides,
+) -> Result<HashMap<String, LqSite>> {
     let sites = all_sites
         .iter()
         .filter(|s| {
@@ -38,96 +38,206 @@ pub fn build_site_list(all_sites: &[Site]) -> Result<HashMap<String, LqSite>> {
             }
             false
         })
-        .filter_map(|s| s.as_lq_site(&sites_csv))
+        .filter_map(|s| s.as_lq_site(sites_csv))
         .map(|s| (s.id.clone(), s))
         .collect::<HashMap<String, LqSite>>();
     Ok(sites)
 }
 
+/// The locations of every site, and of every device at a site with a
+/// location, keyed by site or device ID.
+pub fn locations(all_sites: &[Site], all_devices: &[Device]) -> HashMap<String, Location> {
+    let mut locations: HashMap<String, Location> = all_sites
+        .iter()
+        .filter_map(|s| {
+            let location = s.description.as_ref()?.location.as_ref()?;
+            Some((
+                s.id.clone(),
+                Location {
+                    latitude: location.latitude,
+                    longitude: location.longitude,
+                },
+            ))
+        })
+        .collect();
+    let devices: Vec<(String, Location)> = all_devices
+        .iter()
+        .filter_map(|d| {
+            let site = d.identification.site.as_ref()?;
+            Some((d.identification.id.clone(), *locations.get(&site.id)?))
+        })
+        .collect();
+    locations.extend(devices);
+    locations
+}
+
+/// Builds the tree from the sites and clients, adding the updated
+/// `AccessPoints.csv` and `Sites.csv` and `Parentless.csv` to `outputs`.
+/// Returns the roots, and what was left out of the tree for the report.
 pub fn build_topology(
     clients: &mut [LqClientSite],
     network_sites: &mut HashMap<String, LqSite>,
-) -> Result<LqSite> {
-    let access_points_csv = load_aps_csv()?;
-    let mut parentless = Vec::new();
-    for client in clients.iter_mut() {
-        for cpe in client.devices.iter_mut() {
-            let mut no_parent = false;
-            if cpe.parent_site_id.is_empty() {
-                no_parent = true;
-            } else if let Some(site) = network_sites.get_mut(&cpe.parent_site_id) {
-                let access_point = if cpe.access_point_name.is_empty() {
-                    format!("{}-NoAP", cpe.parent_site_name.replace(",", "_"))
-                } else {
-                    cpe.access_point_name.clone()
-                };
+    locations: &HashMap<String, Location>,
+    roots: &RootSelection,
+    output: &OutputConfig,
+    outputs: &mut Outputs,
+) -> Result<(Vec<LqSite>, Excluded)> {
+    let mut ap_overrides = load_aps_csv(output)?;
+    let mut site_overrides = load_sites_csv(output)?;
+    ap_overrides.report_errors();
+    site_overrides.report_errors();
 
-                if let Some(ap) = site.access_points.get_mut(&access_point) {
-                    ap.clients.push(cpe.clone());
-                } else {
-                    let (download_mbps, upload_mbps) =
-                        if let Some(ap_info) = access_points_csv.get(&access_point) {
-                            (ap_info.0, ap_info.1)
-                        } else {
-                            (1_000, 1_000)
-                        };
-                    site.access_points.insert(
-                        access_point.clone(),
-                        LqAccessPoint {
-                            name: access_point.clone(),
-                            clients: vec![cpe.clone()],
-                            download_mbps,
-                            upload_mbps,
-                        },
-                    );
-                }
-            } else {
-                no_parent = true;
-            }
+    // Access points are named after the AP device, or "{site}-NoAP", and
+    // identified by the device ID or "{site ID}-NoAP".
+    let ap_rates = clients
+        .iter()
+        .flat_map(|c| c.devices.iter())
+        .filter_map(|device| {
+            let id = device.parent_node_id();
+            let rates = ap_overrides.lookup(&id, &device.parent_node())?;
+            Some((id, rates))
+        })
+        .collect();
 
-            if no_parent {
-                cpe.access_point_id = "noparent".to_string();
-                cpe.access_point_name = "Unparented".to_string();
-                parentless.push(cpe.clone());
-            }
+    let topology = TopologyBuilder::new(network_sites.clone())
+        .access_point_rates(ap_rates)
+        .build_forest(roots, clients)?;
+    let rejected_sites = topology.rejected_sites();
+    for site in rejected_sites.iter() {
+        warn!(site = site.name, "Rejected - not beneath a root site");
+    }
+    let rejected_sites: Vec<String> = rejected_sites.iter().map(|s| s.name.clone()).collect();
+    *network_sites = topology.sites;
+
+    // Update "AccessPoints.csv" and "Sites.csv" with any new entries. Rates
+    // measured from statistics or reported by UISP aren't operator settings,
+    // so new rows get the default instead, and backhaul nodes are only listed
+    // if the operator added them.
+    let mut sites: Vec<&LqSite> = network_sites.values().collect();
+    sites.sort_by(|a, b| a.name.cmp(&b.name).then_with(|| a.id.cmp(&b.id)));
+    let (download, upload) = DEFAULT_RATES;
+    for site in sites {
+        for (id, ap) in site.access_points.iter() {
+            ap_overrides.insert(id, &ap.name, download, upload);
+        }
+        if !is_backhaul_node(&site.id) || site_overrides.lookup(&site.id, "").is_some() {
+            site_overrides.insert(&site.id, &site.name, download, upload);
         }
     }
+    ap_overrides.report_stale();
+    site_overrides.report_stale();
+    outputs.add_supporting("AccessPoints.csv", ap_overrides.to_csv()?);
+    outputs.add_supporting("Sites.csv", site_overrides.to_csv()?);
+
+    let parentless = diagnose_parentless(&topology.parentless, clients, network_sites, locations);
+    outputs.add_supporting("Parentless.csv", parentless_csv(&parentless)?);
+
+    let excluded = Excluded {
+        parentless,
+        rejected_sites,
+    };
+    Ok((topology.roots, excluded))
+}
 
-    // Save "AccessPoints.csv", and "Sites.csv"
-    let mut acsv = "AP,Download,Upload\n".to_string();
-    let mut scsv = "Site,Download,Upload\n".to_string();
-    for (_, site) in network_sites.iter() {
-        for (_, ap) in site.access_points.iter() {
-            acsv += &format!("{},{},{}\n", ap.name, ap.download_mbps, ap.upload_mbps);
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use integration_core::{LqAccessPoint, LqClientDevice};
+    use std::{collections::BTreeMap, fs};
+
+    fn site(id: &str, parent: Option<&str>, rates: usize) -> LqSite {
+        LqSite {
+            id: id.to_string(),
+            name: id.to_uppercase(),
+            parent: parent.map(|p| p.to_string()),
+            children: Vec::new(),
+            access_points: BTreeMap::new(),
+            download_mbps: rates,
+            upload_mbps: rates,
         }
-        scsv += &format!(
-            "{},{},{}\n",
-            site.name, site.download_mbps, site.upload_mbps
-        );
     }
-    let mut f = File::create("AccessPoints.csv")?;
-    f.write_all(acsv.as_bytes())?;
-    let mut f = File::create("Sites.csv")?;
-    f.write_all(scsv.as_bytes())?;
 
-    // Save "Parentless.csv"
-    let mut pcsv = "Hostname\n".to_string();
-    for p in parentless.iter() {
-        pcsv += &format!("{}\n", p.hostname);
+    fn client(id: &str, ap: &str, site: &str) -> LqClientSite {
+        LqClientSite {
+            id: id.to_string(),
+            name: id.to_string(),
+            download: 10_000_000,
+            upload: 1_000_000,
+            suspended: false,
+            devices: vec![LqClientDevice {
+                id: format!("{id}-cpe"),
+                hostname: format!("{id}-cpe"),
+                mac: String::new(),
+                model: String::new(),
+                ip: "100.64.0.1".to_string(),
+                access_point_id: ap.to_string(),
+                access_point_name: ap.to_uppercase(),
+                parent_site_id: site.to_string(),
+                parent_site_name: site.to_uppercase(),
+                upload: 1_000_000,
+                download: 10_000_000,
+                is_access_point: false,
+                is_bridge: false,
+            }],
+        }
     }
-    let mut f = File::create("Parentless.csv")?;
-    f.write_all(pcsv.as_bytes())?;
 
-    // Overall topology
-    let mut network_map = build_site_tree(network_sites)?;
-    network_map.access_points.insert(
-        "0".to_string(),
-        LqAccessPoint {
-            name: "Unparented".to_string(),
-            clients: parentless,
-            download_mbps: 1_000,
-            upload_mbps: 1_000,
-        },
-    );
-    Ok(network_map)
+    #[test]
+    fn measured_rates_are_not_saved_as_overrides() {
+        let dir = tempfile::tempdir().unwrap();
+        let output = OutputConfig {
+            directory: dir.path().display().to_string(),
+            ..Default::default()
+        };
+        fs::write(
+            output.path("AccessPoints.csv"),
+            "ID,AP,Download,Upload\nap2,AP2,200,20\n",
+        )
+        .unwrap();
+
+        // A measured access point, a backhaul node and a site limited to its
+        // measured backhaul.
+        let mut tower = site("tower", Some("tower-backhaul"), 150);
+        tower.access_points.insert(
+            "ap1".to_string(),
+            LqAccessPoint {
+                name: "AP1".to_string(),
+                download_mbps: 300,
+                upload_mbps: 30,
+                clients: Vec::new(),
+            },
+        );
+        let mut sites: HashMap<String, LqSite> = [
+            site("core", None, 1_000),
+            site("tower-backhaul", Some("core"), 500),
+            tower,
+        ]
+        .into_iter()
+        .map(|s| (s.id.clone(), s))
+        .collect();
+        let mut clients = vec![client("c1", "ap1", "tower"), client("c2", "ap2", "tower")];
+        let mut outputs = Outputs::default();
+        build_topology(
+            &mut clients,
+            &mut sites,
+            &HashMap::new(),
+            &RootSelection::Named(vec!["CORE".to_string()]),
+            &output,
+            &mut outputs,
+        )
+        .unwrap();
+
+        // Shaping uses the measured rates...
+        let ap1 = &sites["tower"].access_points["ap1"];
+        assert_eq!((ap1.download_mbps, ap1.upload_mbps), (300, 30));
+        // ...but only operator settings and defaults are saved.
+        assert_eq!(
+            outputs.get("AccessPoints.csv").unwrap(),
+            "ID,AP,Download,Upload\nap2,AP2,200,20\nap1,AP1,1000,1000\n"
+        );
+        assert_eq!(
+            outputs.get("Sites.csv").unwrap(),
+            "ID,Site,Download,Upload\ncore,CORE,1000,1000\ntower,TOWER,1000,1000\n"
+        );
+    }
 }
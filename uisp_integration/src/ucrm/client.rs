@@ -0,0 +1,8 @@
+use serde::Deserialize;
+
+#[allow(non_snake_case)]
+#[derive(Deserialize, Debug)]
+pub struct CrmClient {
+    pub id: usize,
+    pub isArchived: Option<bool>,
+}
@@ -0,0 +1,231 @@
+mod client;
+mod rest;
+mod service;
+mod service_plan;
+use std::collections::HashMap;
+
+use crate::unms::UispInstance;
+use anyhow::Result;
+pub use client::CrmClient;
+use integration_core::RunMetrics;
+pub use rest::*;
+pub use service::CrmService;
+pub use service_plan::CrmServicePlan;
+use tokio::join;
+
+/// Rates and status for a single CRM client, in bits per second
+/// to match the NMS `qos` block.
+#[derive(Debug, Clone, Copy)]
+pub struct CrmRate {
+    pub download: usize,
+    pub upload: usize,
+    pub suspended: bool,
+}
+
+/// Service plan rates indexed by the CRM client ID that UISP stores on
+/// each endpoint site.
+#[derive(Debug, Default)]
+pub struct CrmRates {
+    by_client: HashMap<String, CrmRate>,
+}
+
+impl CrmRates {
+    pub fn get(&self, client_id: &str) -> Option<&CrmRate> {
+        self.by_client.get(client_id)
+    }
+
+    pub fn len(&self) -> usize {
+        self.by_client.len()
+    }
+
+    /// Combines the raw CRM lists into per-client rates. Service-level
+    /// speed overrides win over the plan speeds. If a client has several
+    /// current services, the fastest active one is used. Services without
+    /// a download and upload speed are skipped, so the client falls back to
+    /// the site's NMS QoS rather than being shaped to nothing.
+    pub fn from_crm(
+        clients: &[CrmClient],
+        services: &[CrmService],
+        plans: &[CrmServicePlan],
+    ) -> Self {
+        let plans: HashMap<usize, &CrmServicePlan> = plans.iter().map(|p| (p.id, p)).collect();
+        let mut by_client = HashMap::<String, CrmRate>::new();
+
+        clients
+            .iter()
+            .filter(|c| !c.isArchived.unwrap_or(false))
+            .for_each(|client| {
+                services
+                    .iter()
+                    .filter(|s| s.clientId == client.id && s.is_current())
+                    .for_each(|service| {
+                        let plan = service.servicePlanId.and_then(|id| plans.get(&id));
+                        let speed = |service: Option<f64>, plan: Option<f64>| {
+                            service.filter(|s| *s > 0.0).or(plan.filter(|s| *s > 0.0))
+                        };
+                        let download =
+                            speed(service.downloadSpeed, plan.and_then(|p| p.downloadSpeed));
+                        let upload = speed(service.uploadSpeed, plan.and_then(|p| p.uploadSpeed));
+                        let (Some(download), Some(upload)) = (download, upload) else {
+                            return;
+                        };
+                        let rate = CrmRate {
+                            download: mbps_to_bps(download),
+                            upload: mbps_to_bps(upload),
+                            suspended: service.is_suspended(),
+                        };
+
+                        let entry = by_client.entry(client.id.to_string()).or_insert(rate);
+                        let better = (entry.suspended && !rate.suspended)
+                            || (entry.suspended == rate.suspended
+                                && rate.download > entry.download);
+                        if better {
+                            *entry = rate;
+                        }
+                    });
+            });
+
+        Self { by_client }
+    }
+}
+
+fn mbps_to_bps(mbps: f64) -> usize {
+    (mbps * 1_000_000.0) as usize
+}
+
+/// Connects to UCRM and downloads clients, services and service plans.
+/// If the instance doesn't have a `crm_key`, an empty rate list is returned
+/// and site QoS from the NMS is used instead.
+pub async fn pre_load_crm(instance: &UispInstance, metrics: &RunMetrics) -> Result<CrmRates> {
+    let (key, url) = match instance.ucrm() {
+        Some(crm) => crm,
+        None => return Ok(CrmRates::default()),
+    };
+    let name = &instance.name;
+    let clients_future = metrics.timed_fetch(
+        name,
+        "crm/clients",
+        crm_request_get_vec::<CrmClient>("clients", key, url),
+    );
+    let services_future = metrics.timed_fetch(
+        name,
+        "crm/clients/services",
+        crm_request_get_vec::<CrmService>("clients/services", key, url),
+    );
+    let plans_future = metrics.timed_fetch(
+        name,
+        "crm/service-plans",
+        crm_request_get_vec::<CrmServicePlan>("service-plans", key, url),
+    );
+
+    let (clients, services, plans) = join!(clients_future, services_future, plans_future);
+    Ok(CrmRates::from_crm(&clients?, &services?, &plans?))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_server::serve_fixtures;
+
+    const CLIENTS: &str = r#"[
+        {"id": 1, "isArchived": false, "firstName": "Plan"},
+        {"id": 2, "isArchived": false, "firstName": "Override"},
+        {"id": 3, "isArchived": false, "firstName": "No speeds"},
+        {"id": 4, "isArchived": false, "firstName": "Suspended"},
+        {"id": 5, "isArchived": true, "firstName": "Archived"}
+    ]"#;
+
+    const SERVICES: &str = r#"[
+        {"id": 10, "clientId": 1, "servicePlanId": 100, "status": 1, "downloadSpeed": null, "uploadSpeed": null},
+        {"id": 11, "clientId": 2, "servicePlanId": 100, "status": 1, "downloadSpeed": 50.0, "uploadSpeed": 0.0},
+        {"id": 12, "clientId": 3, "servicePlanId": 101, "status": 1, "downloadSpeed": null, "uploadSpeed": null},
+        {"id": 13, "clientId": 3, "servicePlanId": null, "status": 1, "downloadSpeed": 0.0, "uploadSpeed": 0.0},
+        {"id": 14, "clientId": 4, "servicePlanId": 100, "status": 3, "downloadSpeed": null, "uploadSpeed": null},
+        {"id": 15, "clientId": 4, "servicePlanId": 100, "status": 2, "downloadSpeed": 500.0, "uploadSpeed": 500.0},
+        {"id": 16, "clientId": 5, "servicePlanId": 100, "status": 1, "downloadSpeed": null, "uploadSpeed": null}
+    ]"#;
+
+    const PLANS: &str = r#"[
+        {"id": 100, "name": "Basic", "downloadSpeed": 25.0, "uploadSpeed": 5.0},
+        {"id": 101, "name": "Unset", "downloadSpeed": null, "uploadSpeed": null}
+    ]"#;
+
+    /// The CRM API, as served by the mock server.
+    const FIXTURES: &[(&str, &str)] = &[
+        ("/clients", CLIENTS),
+        ("/clients/services", SERVICES),
+        ("/service-plans", PLANS),
+    ];
+
+    fn rates() -> CrmRates {
+        let clients: Vec<CrmClient> = serde_json::from_str(CLIENTS).unwrap();
+        let services: Vec<CrmService> = serde_json::from_str(SERVICES).unwrap();
+        let plans: Vec<CrmServicePlan> = serde_json::from_str(PLANS).unwrap();
+        CrmRates::from_crm(&clients, &services, &plans)
+    }
+
+    #[test]
+    fn rates_come_from_plans_and_service_overrides() {
+        let rates = rates();
+        let plan = rates.get("1").unwrap();
+        assert_eq!((plan.download, plan.upload), (25_000_000, 5_000_000));
+        assert!(!plan.suspended);
+
+        // A zero override falls back to the plan speed.
+        let overridden = rates.get("2").unwrap();
+        assert_eq!(
+            (overridden.download, overridden.upload),
+            (50_000_000, 5_000_000)
+        );
+    }
+
+    #[test]
+    fn services_without_speeds_have_no_rate() {
+        assert!(rates().get("3").is_none());
+    }
+
+    #[tokio::test]
+    async fn rates_are_loaded_from_the_crm_api() {
+        let (url, requests) = serve_fixtures(FIXTURES).await;
+        let instance: UispInstance = ron::from_str(&format!(
+            r#"(name: "east", nms_key: "", nms_url: "", crm_key: Some("crm-secret"), crm_url: Some("{url}"))"#
+        ))
+        .unwrap();
+        let rates = pre_load_crm(&instance, &RunMetrics::default())
+            .await
+            .unwrap();
+        assert_eq!(rates.len(), 3);
+        let plan = rates.get("1").unwrap();
+        assert_eq!((plan.download, plan.upload), (25_000_000, 5_000_000));
+
+        let requests = requests.lock().unwrap();
+        assert_eq!(requests.len(), FIXTURES.len());
+        for request in requests.iter() {
+            let request = request.to_lowercase();
+            assert!(request.contains("x-auth-app-key: crm-secret"), "{request}");
+        }
+    }
+
+    #[tokio::test]
+    async fn crm_errors_fail_the_load() {
+        // Only the clients are served; the other requests get a 404.
+        let (url, _) = serve_fixtures(&[("/clients", CLIENTS)]).await;
+        let instance: UispInstance = ron::from_str(&format!(
+            r#"(nms_key: "", nms_url: "", crm_key: Some("crm-secret"), crm_url: Some("{url}"))"#
+        ))
+        .unwrap();
+        assert!(pre_load_crm(&instance, &RunMetrics::default())
+            .await
+            .is_err());
+    }
+
+    #[test]
+    fn suspended_archived_and_ended_services() {
+        let rates = rates();
+        let suspended = rates.get("4").unwrap();
+        assert!(suspended.suspended);
+        assert_eq!(suspended.download, 25_000_000);
+        assert!(rates.get("5").is_none());
+        assert_eq!(rates.len(), 3);
+    }
+}
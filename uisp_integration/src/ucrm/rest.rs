@@ -0,0 +1,23 @@
+use serde::de::DeserializeOwned;
+
+/// Submits a request to the UCRM API, returning a deserialized vector of type T.
+pub async fn crm_request_get_vec<T>(
+    url: &str,
+    key: &str,
+    api: &str,
+) -> Result<Vec<T>, reqwest::Error>
+where
+    T: DeserializeOwned,
+{
+    let full_url = format!("{}/{}", api, url);
+    let client = reqwest::Client::new();
+
+    let res = client
+        .get(&full_url)
+        .header("Accept", "application/json")
+        .header("X-Auth-App-Key", key)
+        .send()
+        .await?;
+
+    res.json::<Vec<T>>().await
+}
@@ -0,0 +1,28 @@
+use serde::Deserialize;
+
+/// UCRM service status codes, as documented in the UCRM API.
+const STATUS_SUSPENDED: i32 = 3;
+const STATUS_ENDED: i32 = 2;
+const STATUS_OBSOLETE: i32 = 5;
+
+#[allow(non_snake_case)]
+#[derive(Deserialize, Debug)]
+pub struct CrmService {
+    pub clientId: usize,
+    pub servicePlanId: Option<usize>,
+    pub status: i32,
+    pub downloadSpeed: Option<f64>,
+    pub uploadSpeed: Option<f64>,
+}
+
+impl CrmService {
+    pub fn is_suspended(&self) -> bool {
+        self.status == STATUS_SUSPENDED
+    }
+
+    /// Ended and obsolete services are kept by UCRM for history, but no longer
+    /// represent something we should be shaping.
+    pub fn is_current(&self) -> bool {
+        self.status != STATUS_ENDED && self.status != STATUS_OBSOLETE
+    }
+}
@@ -0,0 +1,9 @@
+use serde::Deserialize;
+
+#[allow(non_snake_case)]
+#[derive(Deserialize, Debug)]
+pub struct CrmServicePlan {
+    pub id: usize,
+    pub downloadSpeed: Option<f64>,
+    pub uploadSpeed: Option<f64>,
+}
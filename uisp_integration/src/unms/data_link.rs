@@ -1,6 +1,6 @@
 use serde::Deserialize;
 
-#[allow(non_snake_case)]
+#[allow(non_snake_case, dead_code)]
 #[derive(Deserialize, Debug)]
 pub struct DataLink {
     pub id: String,
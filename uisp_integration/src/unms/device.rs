@@ -8,12 +8,22 @@ pub struct Device {
     pub ipAddress: Option<String>,
     pub attributes: Option<DeviceAttributes>,
     pub mode: Option<String>,
+    pub overview: Option<DeviceOverview>,
+}
+
+#[allow(non_snake_case)]
+#[derive(Deserialize, Debug)]
+pub struct DeviceOverview {
+    /// Link capacities reported by the radio, in bits per second.
+    pub downlinkCapacity: Option<u64>,
+    pub uplinkCapacity: Option<u64>,
 }
 
 #[allow(non_snake_case)]
 #[derive(Deserialize, Debug)]
 pub struct DeviceIdentification {
     pub id: String,
+    pub name: Option<String>,
     pub hostname: Option<String>,
     pub mac: Option<String>,
     pub model: Option<String>,
@@ -35,7 +45,7 @@ pub struct DeviceParent {
     pub name: String,
 }
 
-#[allow(non_snake_case)]
+#[allow(non_snake_case, dead_code)]
 #[derive(Deserialize, Debug)]
 pub struct DeviceAttributes {
     pub ssid: Option<String>,
@@ -104,7 +114,8 @@ impl Device {
                 id: self.identification.id.clone(),
                 hostname,
                 mac: self.identification.mac.clone().unwrap_or_default(),
-                ip: ip.clone(),
+                // UISP gives the address with its interface's mask.
+                ip: ip.split('/').next().unwrap_or_default().to_string(),
                 model: self.identification.model.clone().unwrap_or_default(),
                 access_point_id,
                 access_point_name,
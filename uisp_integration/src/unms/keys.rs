@@ -1,14 +1,187 @@
+use crate::{netbox::NetBoxConfig, topology::HierarchySource};
 use anyhow::{Error, Result};
+use integration_core::{
+    CapacityLimits, CapacityPlanConfig, GraphConfig, LogConfig, MergeRules, MetricsConfig,
+    NodeNaming, OutputConfig, ReportConfig, RootSelection,
+};
 use ron::de::from_reader;
 use serde::{Deserialize, Serialize};
 use std::{fs::File, path::Path};
 
+/// Connection details for one UISP controller.
+#[derive(Clone, Serialize, Deserialize, Default)]
+pub struct UispInstance {
+    /// Short name, used to prefix this instance's IDs when there is more
+    /// than one instance.
+    #[serde(default)]
+    pub name: String,
+    nms_key: String,
+    nms_url: String,
+    /// Optional UCRM app key. When present, client rates come from CRM
+    /// service plans rather than the NMS site QoS settings.
+    #[serde(default)]
+    crm_key: Option<String>,
+    /// Optional UCRM API URL. Defaults to the CRM on the same host as the NMS.
+    #[serde(default)]
+    crm_url: Option<String>,
+    /// The instance's top site. Defaults to the overall `root_site_name`.
+    #[serde(default)]
+    pub root_site_name: Option<String>,
+    /// Site (ID or name) to attach this instance's top site beneath.
+    /// Defaults to the overall root.
+    #[serde(default)]
+    pub parent_site: Option<String>,
+}
+
+impl UispInstance {
+    /// Expands the configured base URLs into API URLs.
+    fn resolve_urls(&mut self) {
+        let base = self.nms_url.trim_end_matches('/').to_string();
+        self.nms_url = format!("{}/nms/api/v2.1", base);
+        self.crm_url = Some(match &self.crm_url {
+            Some(url) => url.trim_end_matches('/').to_string(),
+            None => format!("{}/crm/api/v1.0", base),
+        });
+    }
+
+    pub fn uisp(&self) -> (&str, &str) {
+        (&self.nms_key, &self.nms_url)
+    }
+
+    pub fn ucrm(&self) -> Option<(&str, &str)> {
+        match (&self.crm_key, &self.crm_url) {
+            (Some(key), Some(url)) => Some((key, url)),
+            _ => None,
+        }
+    }
+}
+
+/// A top-level site in the output, optionally with its own capacity.
+#[derive(Clone, Serialize, Deserialize, Default)]
+pub struct RootSite {
+    pub name: String,
+    #[serde(default)]
+    pub download_mbps: Option<usize>,
+    #[serde(default)]
+    pub upload_mbps: Option<usize>,
+}
+
+/// Options controlling how a UISP inventory is built.
+#[derive(Clone, Serialize, Deserialize, Default)]
+pub struct UispOptions {
+    /// Insert a capacity node for each backhaul link between infrastructure sites.
+    #[serde(default)]
+    pub backhaul_nodes: bool,
+    /// Use the parents declared in UISP, or infer them from data links.
+    #[serde(default)]
+    pub hierarchy: HierarchySource,
+    /// Set access point and backhaul capacities from UISP's measured link
+    /// capacity, falling back to `AccessPoints.csv` and `Sites.csv`.
+    #[serde(default)]
+    pub live_capacity: Option<LiveCapacity>,
+}
+
+/// How measured capacity is turned into a shaping rate.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct LiveCapacity {
+    /// Fraction of the measured capacity to shape to.
+    #[serde(default = "LiveCapacity::default_safety_factor")]
+    pub safety_factor: f64,
+    /// Number of recent samples averaged together.
+    #[serde(default = "LiveCapacity::default_samples")]
+    pub samples: usize,
+    /// Statistics interval requested from UISP, such as `hour` or `day`.
+    #[serde(default = "LiveCapacity::default_interval")]
+    pub interval: String,
+}
+
+impl LiveCapacity {
+    fn default_safety_factor() -> f64 {
+        0.8
+    }
+
+    fn default_samples() -> usize {
+        12
+    }
+
+    fn default_interval() -> String {
+        "hour".to_string()
+    }
+}
+
+impl Default for LiveCapacity {
+    fn default() -> Self {
+        Self {
+            safety_factor: Self::default_safety_factor(),
+            samples: Self::default_samples(),
+            interval: Self::default_interval(),
+        }
+    }
+}
+
 /// Key store structure
 #[derive(Clone, Serialize, Deserialize, Default)]
 pub struct Keys {
+    /// The original single UISP instance. Optional if `uisp_instances` is used.
+    #[serde(default)]
     nms_key: String,
+    #[serde(default)]
     nms_url: String,
+    #[serde(default)]
     root_site_name: String,
+    /// Several top-level sites, producing a forest. Overrides `root_site_name`.
+    /// With `auto_roots`, only used for their capacities.
+    #[serde(default)]
+    root_sites: Vec<RootSite>,
+    /// Use every site without a parent as a top-level site.
+    #[serde(default)]
+    auto_roots: bool,
+    #[serde(default)]
+    crm_key: Option<String>,
+    #[serde(default)]
+    crm_url: Option<String>,
+    #[serde(default)]
+    uisp_options: UispOptions,
+    /// Additional UISP controllers, fetched concurrently.
+    #[serde(default)]
+    uisp_instances: Vec<UispInstance>,
+    /// Data sources to combine, in order. Defaults to `["uisp"]`.
+    #[serde(default)]
+    sources: Option<Vec<String>>,
+    /// How sites and rates are matched up when combining several sources.
+    #[serde(default)]
+    merge: MergeRules,
+    /// Optional NetBox connection, used by the `netbox` source and to enrich
+    /// device IP addresses.
+    #[serde(default)]
+    netbox: Option<NetBoxConfig>,
+    /// Optional CSV directory or JSON Lines file, used by the `file` source.
+    #[serde(default)]
+    inventory_file: Option<String>,
+    /// How tree nodes are named in the output.
+    #[serde(default)]
+    naming: NodeNaming,
+    /// Where `network.json` and the shaper files are published.
+    #[serde(default)]
+    output: OutputConfig,
+    /// Log level and format.
+    #[serde(default)]
+    log: LogConfig,
+    /// Where run metrics are written for Prometheus.
+    #[serde(default)]
+    metrics: MetricsConfig,
+    /// Diagrams of the site tree.
+    #[serde(default)]
+    graph: GraphConfig,
+    /// Oversubscription ratios at which sites and APs are flagged.
+    #[serde(default)]
+    capacity: CapacityLimits,
+    /// A human-readable report of each run.
+    #[serde(default)]
+    report: ReportConfig,
+    /// Where the capacity-planning report is written.
+    #[serde(default)]
+    capacity_plan: CapacityPlanConfig,
 }
 
 impl Keys {
@@ -20,19 +193,122 @@ impl Keys {
 
         let f = File::open("keys.ron").unwrap();
         let mut keys: Self = from_reader(f)?;
-        if keys.nms_url.ends_with('/') {
-            keys.nms_url = format!("{}nms/api/v2.1", keys.nms_url);
-        } else {
-            keys.nms_url = format!("{}/nms/api/v2.1", keys.nms_url);
+        if keys.root_site_name.is_empty() && keys.root_sites.is_empty() && !keys.auto_roots {
+            return Err(Error::msg(
+                "Please set root_site_name, root_sites or auto_roots in keys.ron",
+            ));
+        }
+        for instance in keys.uisp_instances.iter_mut() {
+            if instance.name.is_empty() {
+                return Err(Error::msg("Each entry in uisp_instances needs a name"));
+            }
+            instance.resolve_urls();
         }
+        keys.graph.resolve_paths(&keys.output);
+        keys.capacity_plan.resolve_paths(&keys.output);
+        keys.report.resolve_paths(&keys.output);
         Ok(keys)
     }
 
-    pub fn uisp(&self) -> (&str, &str) {
-        (&self.nms_key, &self.nms_url)
+    /// Every configured UISP controller: the top-level one (if its URL is set)
+    /// followed by `uisp_instances`.
+    pub fn uisp_instances(&self) -> Vec<UispInstance> {
+        let mut result = Vec::new();
+        if !self.nms_url.is_empty() {
+            let mut instance = UispInstance {
+                name: "uisp".to_string(),
+                nms_key: self.nms_key.clone(),
+                nms_url: self.nms_url.clone(),
+                crm_key: self.crm_key.clone(),
+                crm_url: self.crm_url.clone(),
+                root_site_name: None,
+                parent_site: None,
+            };
+            instance.resolve_urls();
+            result.push(instance);
+        }
+        result.extend(self.uisp_instances.iter().cloned());
+        result
+    }
+
+    pub fn uisp_options(&self) -> &UispOptions {
+        &self.uisp_options
+    }
+
+    pub fn sources(&self) -> Vec<String> {
+        self.sources
+            .clone()
+            .unwrap_or_else(|| vec!["uisp".to_string()])
+    }
+
+    /// The configured merge rules, with the root sites, which every source
+    /// shares, always matched by name.
+    pub fn merge_rules(&self) -> MergeRules {
+        let mut rules = self.merge.clone();
+        rules.match_names.push(self.root_site_name.clone());
+        rules
+            .match_names
+            .extend(self.root_sites.iter().map(|r| r.name.clone()));
+        rules
+    }
+
+    pub fn netbox(&self) -> Option<&NetBoxConfig> {
+        self.netbox.as_ref()
+    }
+
+    pub fn inventory_file(&self) -> Option<&str> {
+        self.inventory_file.as_deref()
+    }
+
+    pub fn naming(&self) -> &NodeNaming {
+        &self.naming
+    }
+
+    pub fn output(&self) -> &OutputConfig {
+        &self.output
+    }
+
+    pub fn log(&self) -> &LogConfig {
+        &self.log
+    }
+
+    pub fn metrics(&self) -> &MetricsConfig {
+        &self.metrics
+    }
+
+    pub fn graph(&self) -> &GraphConfig {
+        &self.graph
+    }
+
+    pub fn capacity(&self) -> &CapacityLimits {
+        &self.capacity
+    }
+
+    pub fn report(&self) -> &ReportConfig {
+        &self.report
+    }
+
+    pub fn capacity_plan(&self) -> &CapacityPlanConfig {
+        &self.capacity_plan
     }
 
     pub fn root(&self) -> &str {
         &self.root_site_name
     }
+
+    /// Which sites become top-level nodes in `network.json`.
+    pub fn root_selection(&self) -> RootSelection {
+        if self.auto_roots {
+            RootSelection::Automatic
+        } else if !self.root_sites.is_empty() {
+            RootSelection::Named(self.root_sites.iter().map(|r| r.name.clone()).collect())
+        } else {
+            RootSelection::Named(vec![self.root_site_name.clone()])
+        }
+    }
+
+    /// Configured capacities for top-level sites.
+    pub fn root_sites(&self) -> &[RootSite] {
+        &self.root_sites
+    }
 }
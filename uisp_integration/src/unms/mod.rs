@@ -3,9 +3,11 @@ mod device;
 mod keys;
 mod rest;
 mod site;
+mod statistics;
 
 pub use data_link::DataLink;
 pub use device::Device;
-pub use keys::Keys;
+pub use keys::{Keys, LiveCapacity, UispInstance, UispOptions};
 pub use rest::*;
 pub use site::Site;
+pub use statistics::measured_capacity;
@@ -45,3 +45,21 @@ where
 
     res.json::<Vec<T>>().await
 }
+
+/// Submits a request to the UNMS API, returning a single deserialized object of type T.
+pub async fn nms_request_get_one<T>(url: &str, key: &str, api: &str) -> Result<T, reqwest::Error>
+where
+    T: DeserializeOwned,
+{
+    let full_url = format!("{}/{}", api, url);
+    let client = reqwest::Client::new();
+
+    let res = client
+        .get(&full_url)
+        .header("'Content-Type", "application/json")
+        .header("X-Auth-Token", key)
+        .send()
+        .await?;
+
+    res.json::<T>().await
+}
@@ -1,20 +1,24 @@
-use std::collections::HashMap;
+use std::collections::BTreeMap;
 
 use crate::clients::LqClientSite;
-use crate::topology::LqSite;
+use crate::topology::{LqSite, Overrides, DEFAULT_RATES};
+use crate::ucrm::CrmRates;
+use integration_core::{Counter, RunMetrics};
 use serde::{Deserialize, Serialize};
+use tracing::warn;
 
-#[allow(non_snake_case)]
+#[allow(non_snake_case, dead_code)]
 #[derive(Deserialize, Debug)]
 pub struct Site {
     pub id: String,
     pub identification: Option<SiteId>,
     pub description: Option<Description>,
     pub qos: Option<Qos>,
+    pub ucrm: Option<SiteUcrm>,
 }
 
 impl Site {
-    pub fn as_lq_site(&self, sites_csv: &HashMap<String, (usize, usize)>) -> Option<LqSite> {
+    pub fn as_lq_site(&self, sites_csv: &Overrides) -> Option<LqSite> {
         if !self.is_active() {
             return None;
         }
@@ -33,17 +37,15 @@ impl Site {
                 None
             };
             if let Some(name) = &ident.name {
-                let (download_mbps, upload_mbps) = if let Some(site_info) = sites_csv.get(name) {
-                    (site_info.0, site_info.1)
-                } else {
-                    (1_000, 1_000)
-                };
+                let name = name.replace(",", "_");
+                let (download_mbps, upload_mbps) =
+                    sites_csv.lookup(&self.id, &name).unwrap_or(DEFAULT_RATES);
                 result = Some(LqSite {
                     id: self.id.clone(),
-                    name: name.replace(",", "_"),
+                    name,
                     parent,
                     children: Vec::new(),
-                    access_points: HashMap::new(),
+                    access_points: BTreeMap::new(),
                     download_mbps,
                     upload_mbps,
                 });
@@ -52,27 +54,46 @@ impl Site {
         result
     }
 
-    pub fn as_lq_client_site(&self) -> Option<LqClientSite> {
+    pub fn as_lq_client_site(&self, crm: &CrmRates, metrics: &RunMetrics) -> Option<LqClientSite> {
         let mut result = None;
 
         if let Some(ident) = &self.identification {
-            if let Some(qos) = &self.qos {
-                if let Some(name) = &ident.name {
+            if let Some(name) = &ident.name {
+                if let Some(rate) = self.crm_client_id().and_then(|id| crm.get(id)) {
+                    result = Some(LqClientSite {
+                        id: self.id.clone(),
+                        name: name.clone(),
+                        download: rate.download,
+                        upload: rate.upload,
+                        suspended: rate.suspended,
+                        devices: Vec::new(),
+                    });
+                } else if let Some(qos) = &self.qos {
                     result = Some(LqClientSite {
                         id: self.id.clone(),
                         name: name.clone(),
                         download: qos.downloadSpeed.unwrap_or(0),
                         upload: qos.uploadSpeed.unwrap_or(0),
+                        suspended: false,
                         devices: Vec::new(),
                     });
+                } else {
+                    metrics.record(Counter::RejectedCircuits, name);
+                    warn!(site = name, "Rejected - no QoS or CRM service plan");
                 }
-            } else {
-                println!("Rejected - no QoS");
             }
         }
         result
     }
 
+    /// The UCRM client linked to this site, if any.
+    pub fn crm_client_id(&self) -> Option<&str> {
+        self.ucrm
+            .as_ref()
+            .and_then(|u| u.client.as_ref())
+            .map(|c| c.id.as_str())
+    }
+
     pub fn is_active(&self) -> bool {
         if let Some(id) = &self.identification {
             if id.suspended {
@@ -113,7 +134,7 @@ pub struct Endpoint {
     pub parentId: Option<String>,
 }
 
-#[allow(non_snake_case)]
+#[allow(non_snake_case, dead_code)]
 #[derive(Deserialize, Debug)]
 pub struct Description {
     pub location: Option<Location>,
@@ -121,17 +142,29 @@ pub struct Description {
     pub endpoints: Option<Vec<Endpoint>>,
 }
 
-#[allow(non_snake_case)]
+#[allow(non_snake_case, dead_code)]
 #[derive(Deserialize, Debug)]
 pub struct Location {
     pub longitude: f64,
     pub latitude: f64,
 }
 
-#[allow(non_snake_case)]
+#[allow(non_snake_case, dead_code)]
 #[derive(Deserialize, Debug)]
 pub struct Qos {
     pub enabled: bool,
     pub downloadSpeed: Option<usize>,
     pub uploadSpeed: Option<usize>,
 }
+
+#[allow(non_snake_case)]
+#[derive(Deserialize, Debug)]
+pub struct SiteUcrm {
+    pub client: Option<SiteUcrmClient>,
+}
+
+#[allow(non_snake_case)]
+#[derive(Deserialize, Debug)]
+pub struct SiteUcrmClient {
+    pub id: String,
+}
@@ -0,0 +1,86 @@
+use super::{nms_request_get_one, LiveCapacity, UispInstance};
+use futures::{stream, StreamExt};
+use serde::Deserialize;
+use std::collections::HashMap;
+use tracing::warn;
+
+/// How many statistics requests are in flight at once.
+const CONCURRENT_REQUESTS: usize = 8;
+
+#[allow(non_snake_case)]
+#[derive(Deserialize, Debug)]
+pub struct DeviceStatistics {
+    pub downlinkCapacity: Option<Vec<StatisticsSample>>,
+    pub uplinkCapacity: Option<Vec<StatisticsSample>>,
+}
+
+/// A point in a statistics series: a timestamp and a value, which may be
+/// missing if the device didn't report.
+#[derive(Deserialize, Debug)]
+pub struct StatisticsSample {
+    pub x: f64,
+    pub y: Option<f64>,
+}
+
+/// Averages the most recent `samples` reported values, returning Mbps.
+fn smoothed(series: &Option<Vec<StatisticsSample>>, samples: usize) -> Option<f64> {
+    let mut points: Vec<&StatisticsSample> = series
+        .as_ref()?
+        .iter()
+        .filter(|s| s.y.map(|y| y > 0.0).unwrap_or(false))
+        .collect();
+    points.sort_by(|a, b| a.x.total_cmp(&b.x));
+    let recent: Vec<f64> = points
+        .iter()
+        .rev()
+        .take(samples.max(1))
+        .filter_map(|s| s.y)
+        .collect();
+    if recent.is_empty() {
+        None
+    } else {
+        Some(recent.iter().sum::<f64>() / recent.len() as f64 / 1_000_000.0)
+    }
+}
+
+impl DeviceStatistics {
+    /// Measured (download, upload) capacity in Mbps, averaged over the last
+    /// `samples` points and scaled by `safety_factor`.
+    pub fn capacity_mbps(&self, samples: usize, safety_factor: f64) -> Option<(usize, usize)> {
+        let download = smoothed(&self.downlinkCapacity, samples)?;
+        let upload = smoothed(&self.uplinkCapacity, samples)?;
+        let scale = |mbps: f64| usize::max(1, (mbps * safety_factor) as usize);
+        Some((scale(download), scale(upload)))
+    }
+}
+
+/// Fetches statistics for each device and returns its measured (download,
+/// upload) capacity in Mbps, keyed by device ID. Devices whose statistics
+/// can't be fetched or report no capacity are left out.
+pub async fn measured_capacity(
+    instance: &UispInstance,
+    device_ids: Vec<String>,
+    options: &LiveCapacity,
+) -> HashMap<String, (usize, usize)> {
+    let (key, url) = instance.uisp();
+    stream::iter(device_ids)
+        .map(|id| async move {
+            let path = format!("devices/{id}/statistics?interval={}", options.interval);
+            let statistics = nms_request_get_one::<DeviceStatistics>(&path, key, url).await;
+            (id, statistics)
+        })
+        .buffer_unordered(CONCURRENT_REQUESTS)
+        .filter_map(|(id, statistics)| async move {
+            match statistics {
+                Ok(statistics) => statistics
+                    .capacity_mbps(options.samples, options.safety_factor)
+                    .map(|capacity| (id, capacity)),
+                Err(e) => {
+                    warn!(device = id, "Unable to fetch statistics: {e}");
+                    None
+                }
+            }
+        })
+        .collect()
+        .await
+}